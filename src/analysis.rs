@@ -0,0 +1,288 @@
+//! Batch analysis of EPD positions across a pool of engines.
+//!
+//! Each worker starts its own engine and takes positions from a shared
+//! counter, so slow positions don't hold the others up. Results come back in
+//! the order they finish and are passed on in file order as soon as every
+//! position before them is done. A position whose search kills the engine is
+//! skipped, and the engine restarted for the rest.
+
+use crate::engine::{AnyEngine, Engine, EngineConfig, PvLine, SearchLimits, SearchResult};
+use crate::epd::{self, Epd};
+use color_eyre::eyre::{Result, eyre};
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// The engine switching to a new best move during a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BestMoveChange {
+    /// The first move of the new principal variation, in UCI notation
+    pub best_move: String,
+    /// When the engine reported it, in milliseconds into the search
+    pub time_ms: u64,
+    /// The depth it reported it at
+    pub depth: Option<u32>,
+}
+
+/// An engine's verdict on one position.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The position as read, with the engine's `bm`, `ce` and `pv` added
+    pub epd: Epd,
+    /// What the engine reported
+    pub search: SearchResult,
+    /// Every change of the engine's mind, in the order it happened
+    pub changes: Vec<BestMoveChange>,
+}
+
+/// An analysis as written to a JSON lines file.
+#[derive(Serialize)]
+struct JsonAnalysis<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    fen: &'a str,
+    best_move: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_move_san: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<crate::engine::Score>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depth: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nodes: Option<u64>,
+    pv: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    lines: &'a [PvLine],
+}
+
+impl Analysis {
+    /// The analysis as one line of JSON.
+    ///
+    /// # Errors
+    /// Returns an error if serialization fails.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&JsonAnalysis {
+            id: self.epd.operand("id"),
+            fen: &self.epd.fen,
+            best_move: &self.search.best_move,
+            best_move_san: self.epd.operand("bm"),
+            score: self.search.score,
+            depth: self.search.depth,
+            nodes: self.search.nodes,
+            pv: &self.search.pv,
+            // A single line only repeats the fields above
+            lines: if self.search.lines.len() > 1 {
+                &self.search.lines
+            } else {
+                &[]
+            },
+        })?)
+    }
+
+    /// The principal variation in SAN, as far as it is legal.
+    ///
+    /// # Errors
+    /// Returns an error if the position is invalid.
+    pub fn san_pv(&self) -> Result<Vec<String>> {
+        Ok(epd::san_line(&self.epd.position()?, &self.search.pv))
+    }
+}
+
+/// Analyse every position with `limits`, using `workers` copies of the
+/// engine described by `config`, calling `on_result` as each one finishes
+/// and `in_order` with each in file order.
+///
+/// Returns every position's analysis in file order, `None` where the search
+/// failed.
+///
+/// Every position is searched from a fresh game, so nothing carries over
+/// from the previous one in the engine's hash table. A failed search skips
+/// its position and restarts the engine.
+///
+/// # Errors
+/// Returns an error if `in_order` fails, or no engine could be started.
+pub async fn analyse(
+    config: &EngineConfig,
+    name: &str,
+    positions: Vec<Epd>,
+    limits: SearchLimits,
+    workers: usize,
+    mut on_result: impl FnMut(&Analysis),
+    mut in_order: impl FnMut(&Analysis) -> Result<()>,
+) -> Result<Vec<Option<Analysis>>> {
+    let count = positions.len();
+    let positions = Arc::new(positions);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, mut rx) = mpsc::channel(workers.max(1) * 2);
+
+    let mut handles = Vec::new();
+    for worker_id in 0..workers.clamp(1, count.max(1)) {
+        let config = config.clone();
+        let name = format!("{name}-{worker_id}");
+        let positions = Arc::clone(&positions);
+        let next = Arc::clone(&next);
+        let tx = tx.clone();
+        handles.push(tokio::spawn(async move {
+            let result = analyse_worker(&config, &name, &positions, &next, limits, &tx).await;
+            if let Err(e) = &result {
+                tracing::error!(worker_id, error = %e, "Analysis worker failed");
+            }
+            result
+        }));
+    }
+    drop(tx);
+
+    // Finished positions wait here until every one before them is done
+    let mut done: Vec<Option<Option<Analysis>>> = vec![None; count];
+    let mut passed_on = 0;
+    while let Some((index, analysis)) = rx.recv().await {
+        if let Some(analysis) = &analysis {
+            on_result(analysis);
+        }
+        done[index] = Some(analysis);
+        while let Some(Some(analysis)) = done.get(passed_on) {
+            if let Some(analysis) = analysis {
+                in_order(analysis)?;
+            }
+            passed_on += 1;
+        }
+    }
+    // Only fail outright if no engine could be started at all
+    let mut error = None;
+    let mut started = false;
+    for handle in handles {
+        match handle.await? {
+            Ok(()) => started = true,
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+    if let Some(e) = error.filter(|_| !started) {
+        return Err(e.wrap_err("Every analysis worker failed"));
+    }
+
+    Ok(done.into_iter().map(Option::flatten).collect())
+}
+
+/// The analyses of every position, for callers that need them all.
+///
+/// # Errors
+/// Returns an error if any position wasn't analysed.
+pub fn require_all(results: Vec<Option<Analysis>>) -> Result<Vec<Analysis>> {
+    results
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| eyre!("Not every position was analysed"))
+}
+
+/// Analyse positions claimed from `next` until there are none left, sending
+/// `None` for those whose search failed.
+async fn analyse_worker(
+    config: &EngineConfig,
+    name: &str,
+    positions: &[Epd],
+    next: &AtomicUsize,
+    limits: SearchLimits,
+    tx: &mpsc::Sender<(usize, Option<Analysis>)>,
+) -> Result<()> {
+    let mut engine = AnyEngine::start(config, name).await?;
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(position) = positions.get(index) else {
+            break;
+        };
+        let analysis = match analyse_position(&mut engine, position, limits).await {
+            Ok(analysis) => Some(analysis),
+            Err(e) => {
+                let id = position.operand("id").unwrap_or(&position.fen);
+                tracing::warn!(position = id, error = %e, "Analysis failed, restarting engine");
+                engine.quit().await.ok();
+                engine = AnyEngine::start(config, name).await?;
+                None
+            }
+        };
+        if tx.send((index, analysis)).await.is_err() {
+            break;
+        }
+    }
+    engine.quit().await.ok();
+    Ok(())
+}
+
+/// Search `position` from a fresh game.
+async fn analyse_position(
+    engine: &mut AnyEngine,
+    position: &Epd,
+    limits: SearchLimits,
+) -> Result<Analysis> {
+    engine.new_game().await?;
+    engine.set_position_from(Some(&position.fen), &[]).await?;
+    let mut changes: Vec<BestMoveChange> = Vec::new();
+    let started = Instant::now();
+    let search = engine
+        .search_with(&limits, |info| {
+            let Some(best_move) = info.pv.first() else {
+                return;
+            };
+            if changes
+                .last()
+                .is_some_and(|last| &last.best_move == best_move)
+            {
+                return;
+            }
+            // Prefer the engine's own clock, which leaves out the pipe
+            let elapsed_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+            changes.push(BestMoveChange {
+                best_move: best_move.clone(),
+                time_ms: info.time_ms.unwrap_or(elapsed_ms),
+                depth: info.depth,
+            });
+        })
+        .await?;
+    let mut epd = position.clone();
+    epd.set_analysis(&search.best_move, search.score, &search.pv)?;
+    Ok(Analysis {
+        epd,
+        search,
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Score;
+
+    #[test]
+    fn test_analysis_json() {
+        let mut epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - id \"pawn\";").expect("Invalid EPD");
+        let search = SearchResult {
+            best_move: "e2e4".to_string(),
+            score: Some(Score::Cp(150)),
+            depth: Some(12),
+            pv: vec!["e2e4".to_string(), "e8d7".to_string()],
+            ..SearchResult::default()
+        };
+        epd.set_analysis(&search.best_move, search.score, &search.pv)
+            .expect("Failed to set analysis");
+        let analysis = Analysis {
+            epd,
+            search,
+            changes: Vec::new(),
+        };
+
+        assert_eq!(
+            analysis.to_json().expect("Failed to serialize"),
+            r#"{"id":"pawn","fen":"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1","best_move":"e2e4","best_move_san":"e4","score":{"Cp":150},"depth":12,"pv":["e2e4","e8d7"]}"#
+        );
+        assert_eq!(
+            analysis.san_pv().expect("Invalid position"),
+            vec!["e4", "Kd7"]
+        );
+        assert_eq!(
+            analysis.epd.to_string(),
+            r#"4k3/8/8/8/8/8/4P3/4K3 w - - id "pawn"; bm e4; ce 150; pv e4 Kd7;"#
+        );
+    }
+}
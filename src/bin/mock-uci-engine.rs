@@ -0,0 +1,21 @@
+//! A scriptable fake UCI engine for exercising match runs without real engines.
+//!
+//! The behaviour comes from the first argument or the `MOCK_UCI_BEHAVIOUR`
+//! environment variable, e.g. `random:7`, `illegal`, `crash:10` or `slow:250`.
+
+use color_eyre::eyre::{Result, eyre};
+use reckless_vs_stockfish::engine::mock::{self, MockBehaviour};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let spec = std::env::args()
+        .nth(1)
+        .or_else(|| std::env::var("MOCK_UCI_BEHAVIOUR").ok())
+        .unwrap_or_else(|| "random".to_string());
+    let behaviour: MockBehaviour = spec.parse().map_err(|e: String| eyre!(e))?;
+
+    mock::serve(behaviour, tokio::io::stdin(), tokio::io::stdout()).await?;
+    Ok(())
+}
@@ -0,0 +1,513 @@
+//! Finding blunders in finished games with a referee engine.
+//!
+//! Every position of every game is searched to a fixed depth by the referee.
+//! A move is a blunder when the referee's evaluation, from the mover's point
+//! of view, drops by more than a threshold from before the move to after it.
+
+use crate::analysis;
+use crate::engine::{EngineConfig, Score, SearchLimits};
+use crate::epd::Epd;
+use crate::game::{self, GameRecord, Termination};
+use crate::pgn::{self, Annotation};
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use serde::Deserialize;
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+use shakmaty::{Chess, Color, EnPassantMode, Position, Role};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Scores beyond this many centipawns, mates included, count as won: going
+/// from +15 to +8 is no blunder.
+pub const EVAL_CAP_CP: i32 = 1000;
+
+/// Moves up to this move number are the opening, unless material says the
+/// game is already in the endgame.
+const OPENING_MOVES: u32 = 10;
+
+/// Positions with at most this much material besides pawns, in pawns and
+/// counting both sides, are the endgame.
+const ENDGAME_MATERIAL: u32 = 26;
+
+/// Numeric annotation glyph for `??`.
+const BLUNDER_NAG: u8 = 4;
+
+/// A finished game, with the engines that played it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredGame {
+    /// The game's round, counted from 1
+    pub round: u64,
+    /// Who played White
+    pub white: String,
+    /// Who played Black
+    pub black: String,
+    /// The moves and result
+    pub record: GameRecord,
+}
+
+/// A game as written to a JSON lines file.
+#[derive(Deserialize)]
+struct JsonGame {
+    game_num: u64,
+    white: String,
+    black: String,
+    #[serde(flatten)]
+    record: GameRecord,
+}
+
+impl StoredGame {
+    /// The position before each move and after the last one.
+    ///
+    /// # Errors
+    /// Returns an error if the start position or a move is invalid.
+    pub fn positions(&self) -> Result<Vec<Chess>> {
+        let mut position = self
+            .record
+            .start_fen
+            .as_deref()
+            .map_or_else(|| Ok(Chess::default()), game::start_position)?;
+        let mut positions = vec![position.clone()];
+        for (ply, record) in self.record.plies.iter().enumerate() {
+            let m = record
+                .uci
+                .parse::<UciMove>()
+                .map_err(|e| eyre!("Invalid UCI move {} at ply {ply}: {e}", record.uci))?
+                .to_move(&position)
+                .map_err(|e| eyre!("Illegal move {} at ply {ply}: {e}", record.uci))?;
+            position.play_unchecked(&m);
+            positions.push(position.clone());
+        }
+        Ok(positions)
+    }
+
+    /// The engine that made the move at `ply`.
+    #[must_use]
+    pub fn mover(&self, ply: usize) -> &str {
+        if (ply % 2 == 0) == self.record.white_moves_first() {
+            &self.white
+        } else {
+            &self.black
+        }
+    }
+}
+
+/// Load the games of a PGN file, or of a JSON lines file as written with
+/// `--jsonl`, telling them apart by the `.pgn` extension in any case.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or a game cannot be parsed.
+pub fn load_games(path: &Path) -> Result<Vec<StoredGame>> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let games = if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pgn"))
+    {
+        pgn::parse_games(&text)
+            .wrap_err_with(|| format!("Failed to parse {}", path.display()))?
+            .into_iter()
+            .enumerate()
+            .map(|(index, game)| StoredGame {
+                round: game
+                    .tag("Round")
+                    .and_then(|round| round.parse().ok())
+                    .unwrap_or(index as u64 + 1),
+                white: game.tag("White").unwrap_or("?").to_string(),
+                black: game.tag("Black").unwrap_or("?").to_string(),
+                record: game.record,
+            })
+            .collect()
+    } else {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                let game: JsonGame = serde_json::from_str(line)
+                    .wrap_err_with(|| format!("{}:{}", path.display(), number + 1))?;
+                Ok(StoredGame {
+                    round: game.game_num + 1,
+                    white: game.white,
+                    black: game.black,
+                    record: game.record,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+    if games.is_empty() {
+        return Err(eyre!("No games in {}", path.display()));
+    }
+    Ok(games)
+}
+
+/// A stage of the game, by move number and material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// The first moves, while most pieces are on the board
+    Opening,
+    /// Everything between
+    Middlegame,
+    /// Few pieces left besides pawns
+    Endgame,
+}
+
+impl Phase {
+    /// Every phase, in the order games go through them.
+    pub const ALL: [Self; 3] = [Self::Opening, Self::Middlegame, Self::Endgame];
+
+    /// The phase `position` is in.
+    #[must_use]
+    pub fn of(position: &Chess) -> Self {
+        let board = position.board();
+        let material: u32 = [
+            (Role::Knight, 3),
+            (Role::Bishop, 3),
+            (Role::Rook, 5),
+            (Role::Queen, 9),
+        ]
+        .into_iter()
+        .map(|(role, value)| value * u32::try_from(board.by_role(role).count()).unwrap_or_default())
+        .sum();
+        if material <= ENDGAME_MATERIAL {
+            Self::Endgame
+        } else if position.fullmoves().get() <= OPENING_MOVES {
+            Self::Opening
+        } else {
+            Self::Middlegame
+        }
+    }
+
+    /// A lowercase name for display.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Opening => "opening",
+            Self::Middlegame => "middlegame",
+            Self::Endgame => "endgame",
+        }
+    }
+}
+
+/// `score` in centipawns, with mates and anything beyond
+/// [`EVAL_CAP_CP`] capped to it.
+#[must_use]
+pub fn centipawns(score: Score) -> i32 {
+    match score {
+        Score::Cp(cp) => cp.clamp(-EVAL_CAP_CP, EVAL_CAP_CP),
+        // Engines report a checkmated side to move as mate 0
+        Score::Mate(mate) if mate <= 0 => -EVAL_CAP_CP,
+        Score::Mate(_) => EVAL_CAP_CP,
+    }
+}
+
+/// The score of a position the rules have ended, which there is nothing to
+/// search in.
+fn final_score(position: &Chess) -> Option<Score> {
+    match game::game_over(position)? {
+        (_, Termination::Checkmate) => Some(Score::Mate(0)),
+        _ => Some(Score::Cp(0)),
+    }
+}
+
+/// The referee's view of one move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveReview {
+    /// Index of the move in the game's plies
+    pub ply: usize,
+    /// The phase the move was played in
+    pub phase: Phase,
+    /// The mover's evaluation before the move, in capped centipawns
+    pub before_cp: i32,
+    /// The mover's evaluation after the move, in capped centipawns
+    pub after_cp: i32,
+}
+
+impl MoveReview {
+    /// How much worse the move left the mover's position.
+    #[must_use]
+    pub const fn drop_cp(&self) -> i32 {
+        self.before_cp - self.after_cp
+    }
+}
+
+/// Review each move between `positions`, given the referee's `scores` of
+/// them from the side to move's point of view. Moves next to a position
+/// without a score are left out.
+#[must_use]
+pub fn review(positions: &[Chess], scores: &[Option<Score>]) -> Vec<MoveReview> {
+    positions
+        .iter()
+        .zip(scores.windows(2))
+        .enumerate()
+        .filter_map(|(ply, (position, scores))| {
+            let (before, after) = (scores[0]?, scores[1]?);
+            Some(MoveReview {
+                ply,
+                phase: Phase::of(position),
+                before_cp: centipawns(before),
+                // The opponent is to move after the move
+                after_cp: -centipawns(after),
+            })
+        })
+        .collect()
+}
+
+/// A game's moves as judged by the referee.
+#[derive(Debug, Clone)]
+pub struct GameReview {
+    /// The game reviewed
+    pub game: StoredGame,
+    /// The position before each move and after the last one
+    pub positions: Vec<Chess>,
+    /// Every move the referee scored
+    pub moves: Vec<MoveReview>,
+}
+
+impl GameReview {
+    /// The moves that lost more than `threshold_cp`.
+    pub fn blunders(&self, threshold_cp: i32) -> impl Iterator<Item = &MoveReview> {
+        self.moves
+            .iter()
+            .filter(move |review| review.drop_cp() > threshold_cp)
+    }
+
+    /// The move at `ply` in SAN, with its move number, e.g. `12... Qxd4`.
+    #[must_use]
+    pub fn move_text(&self, ply: usize) -> String {
+        let position = &self.positions[ply];
+        let uci = &self.game.record.plies[ply].uci;
+        let san = uci
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci| uci.to_move(position).ok())
+            .map_or_else(
+                || uci.clone(),
+                |m| SanPlus::from_move(position.clone(), &m).to_string(),
+            );
+        let dots = if position.turn() == Color::White {
+            "."
+        } else {
+            "..."
+        };
+        format!("{}{dots} {san}", position.fullmoves())
+    }
+
+    /// NAGs and referee comments for the moves that lost more than
+    /// `threshold_cp`.
+    #[must_use]
+    pub fn annotations(&self, threshold_cp: i32) -> Vec<Annotation> {
+        self.blunders(threshold_cp)
+            .map(|review| Annotation {
+                ply: review.ply,
+                nag: BLUNDER_NAG,
+                comment: Some(format!(
+                    "referee {} -> {}",
+                    Score::Cp(review.before_cp),
+                    Score::Cp(review.after_cp)
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Search every position of `games` with `limits`, using `workers` copies of
+/// the referee described by `config`, and review every move.
+///
+/// Positions the rules have ended are scored without searching.
+///
+/// # Errors
+/// Returns an error if a game cannot be replayed or a search fails.
+pub async fn review_games(
+    config: &EngineConfig,
+    name: &str,
+    games: Vec<StoredGame>,
+    limits: SearchLimits,
+    workers: usize,
+) -> Result<Vec<GameReview>> {
+    let positions = games
+        .iter()
+        .map(StoredGame::positions)
+        .collect::<Result<Vec<_>>>()?;
+    let mut searched = Vec::new();
+    for position in positions.iter().flatten() {
+        if final_score(position).is_none() {
+            let fen = Fen::from_position(position.clone(), EnPassantMode::Legal);
+            searched.push(Epd::parse(&fen.to_string())?);
+        }
+    }
+    tracing::info!(
+        games = games.len(),
+        positions = searched.len(),
+        workers,
+        referee = name,
+        "Reviewing"
+    );
+    let results =
+        analysis::analyse(config, name, searched, limits, workers, |_| {}, |_| Ok(())).await?;
+    let mut results = analysis::require_all(results)?.into_iter();
+
+    let mut reviews = Vec::new();
+    for (game, positions) in games.into_iter().zip(positions) {
+        let scores: Vec<Option<Score>> = positions
+            .iter()
+            .map(|position| {
+                final_score(position)
+                    .or_else(|| results.next().and_then(|analysis| analysis.search.score))
+            })
+            .collect();
+        reviews.push(GameReview {
+            moves: review(&positions, &scores),
+            game,
+            positions,
+        });
+    }
+    Ok(reviews)
+}
+
+/// Moves and blunders counted together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    /// Moves reviewed
+    pub moves: u32,
+    /// Moves that lost more than the threshold
+    pub blunders: u32,
+}
+
+impl Tally {
+    /// Blunders as a percentage of moves.
+    #[must_use]
+    pub fn rate(&self) -> f64 {
+        f64::from(self.blunders) * 100.0 / f64::from(self.moves.max(1))
+    }
+}
+
+impl fmt::Display for Tally {
+    /// Blunders out of moves and the rate, e.g. `2/40 (5.0%)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} ({:.1}%)", self.blunders, self.moves, self.rate())
+    }
+}
+
+/// Blunder rates of each engine in each phase.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Tallies by engine and phase
+    pub engines: BTreeMap<String, BTreeMap<Phase, Tally>>,
+}
+
+impl Report {
+    /// Count every move of a reviewed game against the engine that made it.
+    pub fn add(&mut self, review: &GameReview, threshold_cp: i32) {
+        for moved in &review.moves {
+            let engine = review.game.mover(moved.ply).to_string();
+            let tally = self
+                .engines
+                .entry(engine)
+                .or_default()
+                .entry(moved.phase)
+                .or_default();
+            tally.moves += 1;
+            tally.blunders += u32::from(moved.drop_cp() > threshold_cp);
+        }
+    }
+
+    /// An engine's tally over every phase.
+    #[must_use]
+    pub fn total(&self, engine: &str) -> Tally {
+        self.engines
+            .get(engine)
+            .into_iter()
+            .flat_map(BTreeMap::values)
+            .fold(Tally::default(), |total, tally| Tally {
+                moves: total.moves + tally.moves,
+                blunders: total.blunders + tally.blunders,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameResult, PlyRecord};
+
+    fn game(moves: &[&str]) -> StoredGame {
+        StoredGame {
+            round: 1,
+            white: "stockfish".to_string(),
+            black: "reckless".to_string(),
+            record: GameRecord {
+                result: GameResult::BlackWins,
+                termination: Termination::Checkmate,
+                plies: moves
+                    .iter()
+                    .map(|uci| PlyRecord {
+                        uci: (*uci).to_string(),
+                        score: None,
+                        depth: None,
+                        time_ms: 0,
+                        search_ms: None,
+                        position_us: None,
+                    })
+                    .collect(),
+                start_fen: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_review_fools_mate() {
+        let game = game(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let positions = game.positions().expect("Invalid game");
+        assert_eq!(final_score(&positions[4]), Some(Score::Mate(0)));
+        let scores = [
+            Some(Score::Cp(20)),
+            Some(Score::Cp(30)),
+            Some(Score::Cp(-50)),
+            Some(Score::Mate(1)),
+            Some(Score::Mate(0)),
+        ];
+        let moves = review(&positions, &scores);
+        let drops: Vec<i32> = moves.iter().map(MoveReview::drop_cp).collect();
+        assert_eq!(drops, vec![50, -20, 950, 0]);
+        assert!(moves.iter().all(|review| review.phase == Phase::Opening));
+
+        let review = GameReview {
+            game,
+            positions,
+            moves,
+        };
+        let blunders: Vec<usize> = review.blunders(200).map(|review| review.ply).collect();
+        assert_eq!(blunders, vec![2]);
+        assert_eq!(review.move_text(2), "2. g4");
+        assert_eq!(review.move_text(3), "2... Qh4#");
+        assert_eq!(
+            review.annotations(200),
+            vec![Annotation {
+                ply: 2,
+                nag: BLUNDER_NAG,
+                comment: Some("referee -0.50 -> -10.00".to_string()),
+            }]
+        );
+
+        let mut report = Report::default();
+        report.add(&review, 200);
+        let stockfish = report.engines["stockfish"][&Phase::Opening];
+        assert_eq!(stockfish.to_string(), "1/2 (50.0%)");
+        assert_eq!(report.total("reckless").to_string(), "0/2 (0.0%)");
+    }
+
+    #[test]
+    fn test_phase_and_centipawns() {
+        assert_eq!(Phase::of(&Chess::default()), Phase::Opening);
+        let endgame = game::start_position("4k3/8/8/8/8/8/4P3/R3K3 w - - 0 40").expect("Bad FEN");
+        assert_eq!(Phase::of(&endgame), Phase::Endgame);
+        let middlegame =
+            game::start_position("r2qk2r/8/8/8/8/8/8/R2QK2R w KQkq - 0 20").expect("Bad FEN");
+        assert_eq!(Phase::of(&middlegame), Phase::Middlegame);
+
+        assert_eq!(centipawns(Score::Cp(2500)), EVAL_CAP_CP);
+        assert_eq!(centipawns(Score::Mate(-3)), -EVAL_CAP_CP);
+        assert_eq!(centipawns(Score::Cp(-35)), -35);
+    }
+}
@@ -0,0 +1,248 @@
+//! Opening books grown from an engine's own choices.
+//!
+//! From the standard starting position the engine searches with `MultiPV`,
+//! and every line it rates close to its best, and still close to level, is
+//! followed to the next ply. The positions left after the last ply make the
+//! book: varied, since several moves are tried at every turn, and balanced,
+//! since no line the engine dislikes for either side survives.
+
+use crate::engine::{Engine, Score, SearchLimits, UciEngine};
+use crate::epd::{self, Epd};
+use color_eyre::eyre::{Result, eyre};
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::zobrist::{Zobrist64, ZobristHash};
+use shakmaty::{Chess, Color, EnPassantMode, Position};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// How the book is grown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Plies from the starting position to the book's positions
+    pub plies: usize,
+    /// Lines the engine reports in every position
+    pub multipv: u32,
+    /// Follow lines scored at most this many centipawns below the best one
+    pub margin_cp: i32,
+    /// Keep positions scored at most this many centipawns from level
+    pub window_cp: i32,
+    /// How long the engine searches every position
+    pub limits: SearchLimits,
+}
+
+/// A position of the book and the moves that lead to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookLine {
+    /// The moves from the starting position, in UCI notation
+    pub moves: Vec<String>,
+    /// The position they lead to
+    pub position: Chess,
+    /// The engine's evaluation, from the point of view of the side to move
+    pub score_cp: i32,
+}
+
+impl BookLine {
+    /// The moves in SAN, numbered, e.g. `1. e4 c5 2. Nf3`.
+    #[must_use]
+    pub fn movetext(&self) -> String {
+        let mut text = String::new();
+        for (ply, san) in epd::san_line(&Chess::default(), &self.moves)
+            .iter()
+            .enumerate()
+        {
+            if ply % 2 == 0 {
+                let _ = write!(text, "{}. ", ply / 2 + 1);
+            }
+            let _ = write!(text, "{san} ");
+        }
+        text.trim_end().to_string()
+    }
+
+    /// The evaluation from White's point of view.
+    #[must_use]
+    pub fn white_score(&self) -> Score {
+        match self.position.turn() {
+            Color::White => Score::Cp(self.score_cp),
+            Color::Black => Score::Cp(-self.score_cp),
+        }
+    }
+
+    /// The position as an EPD record, with the evaluation as `ce` and the
+    /// moves as the comment `c0`.
+    #[must_use]
+    pub fn epd(&self, number: usize) -> Epd {
+        let fen = Fen::from_position(self.position.clone(), EnPassantMode::Legal);
+        let mut epd = Epd {
+            fen: fen.to_string(),
+            operations: Vec::new(),
+        };
+        epd.set("id", vec![format!("book.{number:03}")]);
+        epd.set("ce", vec![self.score_cp.to_string()]);
+        epd.set("c0", vec![self.movetext()]);
+        epd
+    }
+
+    /// The line as an unfinished PGN game, with the evaluation as a comment
+    /// on the last move.
+    #[must_use]
+    pub fn pgn(&self, number: usize) -> String {
+        let mut pgn = String::new();
+        let _ = writeln!(pgn, "[Event \"Opening book\"]");
+        let _ = writeln!(pgn, "[Site \"?\"]");
+        let _ = writeln!(pgn, "[Date \"????.??.??\"]");
+        let _ = writeln!(pgn, "[Round \"{number}\"]");
+        let _ = writeln!(pgn, "[White \"?\"]");
+        let _ = writeln!(pgn, "[Black \"?\"]");
+        let _ = writeln!(pgn, "[Result \"*\"]");
+        let _ = writeln!(pgn);
+        let _ = writeln!(pgn, "{} {{{}}} *", self.movetext(), self.white_score());
+        pgn
+    }
+}
+
+/// The moves worth following among the engine's lines, with their scores in
+/// centipawns.
+///
+/// Each line is given as its first move and score. A move is kept if its
+/// line is within `margin_cp` of the best one and `window_cp` of level.
+/// Mates are never balanced, so they are left out.
+#[must_use]
+pub fn candidates(lines: &[(String, Score)], margin_cp: i32, window_cp: i32) -> Vec<(String, i32)> {
+    let scored: Vec<(String, i32)> = lines
+        .iter()
+        .filter_map(|(uci, score)| match score {
+            Score::Cp(cp) => Some((uci.clone(), *cp)),
+            Score::Mate(_) => None,
+        })
+        .collect();
+    let Some(best) = scored.iter().map(|(_, cp)| *cp).max() else {
+        return Vec::new();
+    };
+    scored
+        .into_iter()
+        .filter(|(_, cp)| best - cp <= margin_cp && cp.abs() <= window_cp)
+        .collect()
+}
+
+/// The first move and score of each line the engine reports for `moves`,
+/// best line first.
+async fn search_lines(
+    engine: &mut UciEngine,
+    moves: &[String],
+    limits: &SearchLimits,
+) -> Result<Vec<(String, Score)>> {
+    engine.set_position(moves).await?;
+    let search = engine.search(limits).await?;
+    // An engine searching a single line may not number it
+    if search.lines.is_empty() {
+        return Ok(search
+            .score
+            .map(|score| (search.best_move, score))
+            .into_iter()
+            .collect());
+    }
+    Ok(search
+        .lines
+        .into_iter()
+        .filter_map(|line| Some((line.pv.into_iter().next()?, line.score?)))
+        .collect())
+}
+
+/// Grow a book with `engine`, one ply at a time, searching each position
+/// that hasn't been reached before by another move order.
+///
+/// # Errors
+/// Returns an error if the engine fails, or suggests an illegal move.
+pub async fn generate(engine: &mut UciEngine, settings: &Settings) -> Result<Vec<BookLine>> {
+    engine.set_multipv(settings.multipv).await?;
+    engine.new_game().await?;
+
+    let start = Chess::default();
+    let mut seen: HashSet<Zobrist64> = HashSet::new();
+    seen.insert(start.zobrist_hash(EnPassantMode::Legal));
+    let mut frontier = vec![BookLine {
+        moves: Vec::new(),
+        position: start,
+        score_cp: 0,
+    }];
+
+    for ply in 0..settings.plies {
+        let mut next = Vec::new();
+        for line in &frontier {
+            let lines = search_lines(engine, &line.moves, &settings.limits).await?;
+            for (uci, cp) in candidates(&lines, settings.margin_cp, settings.window_cp) {
+                let m = uci
+                    .parse::<UciMove>()
+                    .ok()
+                    .and_then(|m| m.to_move(&line.position).ok())
+                    .ok_or_else(|| eyre!("Illegal move '{uci}' after {}", line.movetext()))?;
+                let mut position = line.position.clone();
+                position.play_unchecked(&m);
+                if !seen.insert(position.zobrist_hash(EnPassantMode::Legal)) {
+                    continue;
+                }
+                let mut moves = line.moves.clone();
+                moves.push(uci);
+                next.push(BookLine {
+                    moves,
+                    position,
+                    // The side to move is the one that didn't choose the move
+                    score_cp: -cp,
+                });
+            }
+        }
+        tracing::info!(ply = ply + 1, positions = next.len(), "Expanded");
+        frontier = next;
+    }
+    Ok(frontier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_within_margin_and_window() {
+        let lines = vec![
+            ("e2e4".to_string(), Score::Cp(35)),
+            ("d2d4".to_string(), Score::Cp(30)),
+            ("g1f3".to_string(), Score::Cp(-10)),
+            ("f2f3".to_string(), Score::Cp(-60)),
+        ];
+        assert_eq!(
+            candidates(&lines, 50, 30),
+            vec![("d2d4".to_string(), 30), ("g1f3".to_string(), -10)]
+        );
+        assert!(candidates(&[("h5f7".to_string(), Score::Mate(1))], 50, 30).is_empty());
+    }
+
+    #[test]
+    fn test_book_line_formats() {
+        let moves: Vec<String> = ["e2e4", "c7c5", "g1f3"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let mut position = Chess::default();
+        for uci in &moves {
+            let m = uci
+                .parse::<UciMove>()
+                .expect("Invalid UCI")
+                .to_move(&position)
+                .expect("Illegal move");
+            position.play_unchecked(&m);
+        }
+        let line = BookLine {
+            moves,
+            position,
+            score_cp: -25,
+        };
+        assert_eq!(line.movetext(), "1. e4 c5 2. Nf3");
+        assert_eq!(line.white_score(), Score::Cp(25));
+        assert_eq!(
+            line.epd(7).to_string(),
+            r#"rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - id "book.007"; ce -25; c0 "1. e4 c5 2. Nf3";"#
+        );
+        assert!(line.pgn(7).ends_with("\n1. e4 c5 2. Nf3 {+0.25} *\n"));
+    }
+}
@@ -0,0 +1,262 @@
+//! Match configuration files.
+//!
+//! A match can be described in a TOML or YAML file (chosen by extension)
+//! holding everything from engine options to output paths. Fields left out
+//! take their defaults, so a file only needs what differs from them.
+
+use crate::elo::Sprt;
+use crate::engine::{EngineConfig, Protocol};
+use crate::game::{Adjudication, GameSettings, TimeControl};
+use crate::match_runner::Match;
+use crate::openings;
+use crate::resources::Resources;
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// The two engines of a match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Engines {
+    /// The reference engine
+    pub stockfish: EngineConfig,
+    /// The engine under test
+    pub reckless: EngineConfig,
+}
+
+impl Default for Engines {
+    fn default() -> Self {
+        Self {
+            stockfish: EngineConfig::new("stockfish"),
+            reckless: EngineConfig::new("reckless"),
+        }
+    }
+}
+
+/// Where finished games are recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Outputs {
+    /// Database of games, moves and evaluations
+    pub db: Option<PathBuf>,
+    /// PGN file to append games to
+    pub pgn: Option<PathBuf>,
+    /// JSON lines file to append games to
+    pub jsonl: Option<PathBuf>,
+}
+
+/// Everything needed to run a match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchConfig {
+    /// Number of games to play
+    pub games: u64,
+    /// Number of parallel workers (engine pairs)
+    pub workers: usize,
+    /// Seed for opening order and colours; random if unset
+    pub seed: Option<u64>,
+    /// Address to serve Prometheus metrics on
+    pub metrics_addr: Option<SocketAddr>,
+    /// Show a live dashboard instead of log output
+    pub tui: bool,
+    /// The engines and their options
+    pub engines: Engines,
+    /// How much time engines get
+    pub time_control: TimeControl,
+    /// When to end games early
+    pub adjudication: Adjudication,
+    /// EPD or FEN file of starting positions, each played with both colours
+    pub openings: Option<PathBuf>,
+    /// Where finished games are recorded
+    pub output: Outputs,
+    /// Core pinning and limits for engine processes
+    pub resources: Resources,
+    /// SPRT hypotheses for the dashboard
+    pub sprt: Sprt,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            games: 1_000_000,
+            workers: 12,
+            seed: None,
+            metrics_addr: None,
+            tui: false,
+            engines: Engines::default(),
+            time_control: TimeControl::default(),
+            adjudication: Adjudication::default(),
+            openings: None,
+            output: Outputs::default(),
+            resources: Resources::default(),
+            sprt: Sprt::default(),
+        }
+    }
+}
+
+/// Whether `path` names a YAML file.
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+}
+
+impl MatchConfig {
+    /// Read a configuration file, as YAML for `.yaml`/`.yml` files and TOML
+    /// otherwise.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read config {}", path.display()))?;
+        let config = if is_yaml(path) {
+            serde_yaml::from_str(&text)?
+        } else {
+            toml::from_str(&text)?
+        };
+        Ok(config)
+    }
+
+    /// The configuration as TOML.
+    ///
+    /// # Errors
+    /// Returns an error if serialization fails.
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// The configuration as YAML.
+    ///
+    /// # Errors
+    /// Returns an error if serialization fails.
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    /// How each game is played.
+    #[must_use]
+    pub const fn game_settings(&self) -> GameSettings {
+        GameSettings {
+            time_control: self.time_control,
+            adjudication: self.adjudication,
+        }
+    }
+
+    /// The most search threads either engine uses.
+    #[must_use]
+    pub fn threads(&self) -> usize {
+        self.engines
+            .stockfish
+            .threads()
+            .max(self.engines.reckless.threads())
+    }
+
+    /// The match this configuration describes, with its openings loaded.
+    ///
+    /// # Errors
+    /// Returns an error if an XBoard engine is given a movetime that isn't
+    /// whole seconds, the openings file cannot be loaded, there are too few
+    /// cores to pin every worker's engines to, or the cores cannot be read.
+    pub fn to_match(&self) -> Result<Match> {
+        // XBoard's `st` takes whole seconds, and a rounded movetime would
+        // leave the forfeit and overrun checks expecting less time than the
+        // engine was given
+        let movetime_ms = self.time_control.movetime_ms;
+        let xboard = [&self.engines.stockfish, &self.engines.reckless]
+            .iter()
+            .any(|engine| engine.protocol == Protocol::Xboard);
+        if xboard
+            && self.time_control.base_ms.is_none()
+            && (movetime_ms == 0 || movetime_ms % 1000 != 0)
+        {
+            return Err(eyre!(
+                "XBoard engines can only be given whole seconds per move, not {movetime_ms} ms"
+            ));
+        }
+        self.resources.check(self.workers, self.threads())?;
+        self.resources.check_ponder(self.time_control.ponder)?;
+        let mut settings = Match::new(
+            self.engines.stockfish.clone(),
+            self.engines.reckless.clone(),
+        )
+        .with_games(self.games)
+        .with_workers(self.workers)
+        .with_seed(self.seed.unwrap_or_default())
+        .with_game_settings(self.game_settings())
+        .with_resources(self.resources)
+        .with_live_events(self.tui);
+        if let Some(path) = &self.openings {
+            settings = settings.with_openings(openings::load(path)?);
+        }
+        if let Some(slots) = self.resources.search_slots(self.threads())? {
+            settings = settings.with_search_slots(slots);
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::OptionValue;
+
+    const TOML: &str = r#"
+games = 200
+workers = 4
+
+[engines.stockfish]
+path = "/opt/stockfish"
+options = { Hash = 64, Threads = 1, UCI_ShowWDL = true }
+
+[time_control]
+base_ms = 10000
+increment_ms = 100
+
+[adjudication]
+resign = { moves = 3, score_cp = 600 }
+
+[output]
+pgn = "games.pgn"
+"#;
+
+    #[test]
+    fn test_load_toml_and_round_trip() {
+        let config: MatchConfig = toml::from_str(TOML).expect("Failed to parse");
+        assert_eq!(config.games, 200);
+        assert_eq!(config.engines.stockfish.path, "/opt/stockfish");
+        assert_eq!(
+            config.engines.stockfish.options.get("Hash"),
+            Some(&OptionValue::Int(64))
+        );
+        assert_eq!(config.engines.reckless, EngineConfig::new("reckless"));
+        assert_eq!(config.time_control.base_ms, Some(10_000));
+        assert_eq!(config.time_control.movetime_ms, 100);
+        assert_eq!(config.adjudication.max_moves, 500);
+        assert_eq!(config.output.pgn, Some(PathBuf::from("games.pgn")));
+
+        let toml = config.to_toml().expect("Failed to write TOML");
+        assert_eq!(toml::from_str::<MatchConfig>(&toml).expect("TOML"), config);
+        let yaml = config.to_yaml().expect("Failed to write YAML");
+        assert_eq!(
+            serde_yaml::from_str::<MatchConfig>(&yaml).expect("YAML"),
+            config
+        );
+    }
+
+    #[test]
+    fn test_xboard_movetime_must_be_whole_seconds() {
+        let mut config = MatchConfig::default();
+        config.engines.reckless.protocol = Protocol::Xboard;
+        let error = config.to_match().expect_err("Part of a second for XBoard");
+        assert!(error.to_string().contains("whole seconds"), "{error}");
+        config.time_control.movetime_ms = 2000;
+        assert!(config.to_match().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(toml::from_str::<MatchConfig>("gmaes = 10").is_err());
+        assert!(toml::from_str::<MatchConfig>("[time_control]\nmovetime = 10").is_err());
+    }
+}
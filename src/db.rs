@@ -0,0 +1,661 @@
+//! `SQLite` storage for match results.
+//!
+//! Every run, the engines that took part, each finished game and every move
+//! with its evaluation are stored so long runs can be queried afterwards.
+
+use crate::engine::Score;
+use crate::game::GameRecord;
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Evaluation used in place of a mate score when comparing evaluations.
+const MATE_CP: i32 = 32_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    settings TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS engines (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    label TEXT NOT NULL,
+    path TEXT NOT NULL,
+    id_name TEXT,
+    id_author TEXT,
+    options TEXT,
+    binary_sha256 TEXT,
+    uci_options TEXT,
+    PRIMARY KEY (run_id, label)
+);
+CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    game_num INTEGER NOT NULL,
+    white TEXT NOT NULL,
+    black TEXT NOT NULL,
+    result TEXT NOT NULL,
+    termination TEXT NOT NULL,
+    moves TEXT NOT NULL,
+    ply_count INTEGER NOT NULL,
+    finished_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    start_fen TEXT
+);
+CREATE TABLE IF NOT EXISTS moves (
+    game_id INTEGER NOT NULL REFERENCES games(id),
+    ply INTEGER NOT NULL,
+    mover TEXT NOT NULL,
+    uci TEXT NOT NULL,
+    score_cp INTEGER,
+    score_mate INTEGER,
+    depth INTEGER,
+    time_ms INTEGER,
+    PRIMARY KEY (game_id, ply)
+);
+CREATE INDEX IF NOT EXISTS games_run ON games(run_id, game_num);
+";
+
+/// Well-known openings that can be named in queries, as UCI move prefixes.
+const NAMED_OPENINGS: &[(&str, &str)] = &[
+    ("sicilian", "e2e4 c7c5"),
+    ("french", "e2e4 e7e6"),
+    ("caro-kann", "e2e4 c7c6"),
+    ("scandinavian", "e2e4 d7d5"),
+    ("alekhine", "e2e4 g8f6"),
+    ("pirc", "e2e4 d7d6 d2d4 g8f6"),
+    ("ruy-lopez", "e2e4 e7e5 g1f3 b8c6 f1b5"),
+    ("italian", "e2e4 e7e5 g1f3 b8c6 f1c4"),
+    ("queens-gambit", "d2d4 d7d5 c2c4"),
+    ("kings-indian", "d2d4 g8f6 c2c4 g7g6"),
+    ("nimzo-indian", "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4"),
+    ("dutch", "d2d4 f7f5"),
+    ("english", "c2c4"),
+    ("reti", "g1f3"),
+];
+
+/// Resolve an opening name (e.g. `sicilian`) or a literal UCI move prefix.
+#[must_use]
+pub fn opening_prefix(opening: &str) -> String {
+    let key = opening.trim().to_lowercase().replace([' ', '_'], "-");
+    NAMED_OPENINGS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map_or_else(
+            || opening.trim().to_string(),
+            |(_, moves)| (*moves).to_string(),
+        )
+}
+
+/// A colour an engine played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Side {
+    /// Played the white pieces
+    White,
+    /// Played the black pieces
+    Black,
+}
+
+/// A game outcome from one engine's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Outcome {
+    /// The engine won
+    Win,
+    /// The engine lost
+    Loss,
+    /// The game was drawn
+    Draw,
+}
+
+/// Conditions for selecting stored games.
+#[derive(Debug, Clone, Default)]
+pub struct GameFilter {
+    /// Only games this engine played in
+    pub engine: Option<String>,
+    /// Only games where the engine had this colour
+    pub side: Option<Side>,
+    /// Only games with this outcome for the engine; a win or loss needs
+    /// `engine`
+    pub outcome: Option<Outcome>,
+    /// Only games starting with these UCI moves from the standard starting
+    /// position; games from a FEN never match
+    pub opening: Option<String>,
+    /// Only games where the engine's evaluation fell by more than this many
+    /// centipawns between two of its consecutive moves
+    pub eval_drop: Option<i32>,
+    /// Maximum number of games to return
+    pub limit: Option<u64>,
+}
+
+/// A stored game, as returned by [`ResultsDb::query`].
+#[derive(Debug, Clone)]
+pub struct GameRow {
+    /// Row id of the game
+    pub id: i64,
+    /// The run the game belongs to
+    pub run_id: i64,
+    /// Game number within the run
+    pub game_num: u64,
+    /// Label of the white engine
+    pub white: String,
+    /// Label of the black engine
+    pub black: String,
+    /// Result in PGN notation
+    pub result: String,
+    /// How the game ended
+    pub termination: String,
+    /// Number of half-moves played
+    pub ply_count: u32,
+    /// Space separated UCI moves
+    pub moves: String,
+    /// Starting position as a FEN, or `None` for the standard one
+    pub start_fen: Option<String>,
+}
+
+/// The columns of [`GameRow`], for selecting from `games g`.
+const GAME_COLUMNS: &str = "g.id, g.run_id, g.game_num, g.white, g.black, g.result, g.termination,
+    g.ply_count, g.moves, g.start_fen";
+
+impl GameRow {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            run_id: row.get(1)?,
+            game_num: row.get(2)?,
+            white: row.get(3)?,
+            black: row.get(4)?,
+            result: row.get(5)?,
+            termination: row.get(6)?,
+            ply_count: row.get(7)?,
+            moves: row.get(8)?,
+            start_fen: row.get(9)?,
+        })
+    }
+}
+
+/// A results database.
+pub struct ResultsDb {
+    conn: Connection,
+}
+
+impl ResultsDb {
+    /// Open (or create) the database at `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened or the schema cannot be created.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .wrap_err_with(|| format!("Failed to open database {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+        Ok(Self { conn })
+    }
+
+    /// Record the start of a run with its settings, returning the run id.
+    ///
+    /// # Errors
+    /// Returns an error if the insert fails.
+    pub fn start_run(&self, settings: &str) -> Result<i64> {
+        self.conn
+            .execute("INSERT INTO runs (settings) VALUES (?1)", params![settings])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Record an engine taking part in a run.
+    ///
+    /// # Errors
+    /// Returns an error if the insert fails.
+    pub fn record_engine(&self, run_id: i64, engine: &EngineMetadata) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO engines
+                (run_id, label, path, id_name, id_author, options, binary_sha256, uci_options)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                run_id,
+                engine.label,
+                engine.path,
+                engine.id_name,
+                engine.id_author,
+                engine.options,
+                engine.binary_sha256,
+                engine.uci_options,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record a finished game and all of its moves.
+    ///
+    /// # Errors
+    /// Returns an error if any insert fails; nothing is stored in that case.
+    pub fn record_game(
+        &mut self,
+        run_id: i64,
+        game_num: u64,
+        white: &str,
+        black: &str,
+        record: &GameRecord,
+    ) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO games
+                (run_id, game_num, white, black, result, termination, moves, ply_count, start_fen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                run_id,
+                game_num,
+                white,
+                black,
+                record.result.as_str(),
+                record.termination.as_str(),
+                record.uci_moves(),
+                record.plies.len(),
+                record.start_fen,
+            ],
+        )?;
+        let game_id = tx.last_insert_rowid();
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO moves
+                    (game_id, ply, mover, uci, score_cp, score_mate, depth, time_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            let white_first = record.white_moves_first();
+            for (ply, record) in record.plies.iter().enumerate() {
+                let mover = if (ply % 2 == 0) == white_first {
+                    white
+                } else {
+                    black
+                };
+                let (score_cp, score_mate) = match record.score {
+                    Some(Score::Cp(cp)) => (Some(cp), None),
+                    Some(Score::Mate(mate)) => (None, Some(mate)),
+                    None => (None, None),
+                };
+                insert.execute(params![
+                    game_id,
+                    ply,
+                    mover,
+                    record.uci,
+                    score_cp,
+                    score_mate,
+                    record.depth,
+                    record.time_ms,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Checkpoint the write-ahead log into the main file and close the database.
+    ///
+    /// # Errors
+    /// Returns an error if the checkpoint fails or the connection cannot be closed.
+    pub fn close(self) -> Result<()> {
+        self.conn
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        self.conn.close().map_err(|(_, e)| e)?;
+        Ok(())
+    }
+
+    /// Find stored games matching `filter`, oldest first.
+    ///
+    /// # Errors
+    /// Returns an error if the filter asks for wins or losses without an
+    /// engine, or the query fails.
+    pub fn query(&self, filter: &GameFilter) -> Result<Vec<GameRow>> {
+        let engine = filter.engine.as_deref();
+        let mut conditions = Vec::new();
+
+        match (engine, filter.side) {
+            (Some(_), Some(Side::White)) => conditions.push("g.white = ?1"),
+            (Some(_), Some(Side::Black)) => conditions.push("g.black = ?1"),
+            (Some(_), None) => conditions.push("(g.white = ?1 OR g.black = ?1)"),
+            (None, _) => {}
+        }
+        if engine.is_some() {
+            match filter.outcome {
+                Some(Outcome::Win) => conditions.push(
+                    "((g.white = ?1 AND g.result = '1-0') OR (g.black = ?1 AND g.result = '0-1'))",
+                ),
+                Some(Outcome::Loss) => conditions.push(
+                    "((g.white = ?1 AND g.result = '0-1') OR (g.black = ?1 AND g.result = '1-0'))",
+                ),
+                Some(Outcome::Draw) => conditions.push("g.result = '1/2-1/2'"),
+                None => {}
+            }
+        } else {
+            match filter.outcome {
+                Some(Outcome::Draw) => conditions.push("g.result = '1/2-1/2'"),
+                Some(_) => return Err(eyre!("Filtering by win or loss needs an engine")),
+                None => {}
+            }
+        }
+        if filter.opening.is_some() {
+            // Moves from a FEN start could match the text but not the opening
+            conditions.push("g.start_fen IS NULL AND (g.moves = ?2 OR g.moves LIKE ?2 || ' %')");
+        }
+        if filter.eval_drop.is_some() {
+            conditions.push(
+                "g.id IN (
+                    SELECT game_id FROM (
+                        SELECT game_id, mover,
+                            LAG(eval) OVER (PARTITION BY game_id, mover ORDER BY ply) - eval AS fall
+                        FROM (
+                            SELECT game_id, ply, mover,
+                                CASE
+                                    WHEN score_mate > 0 THEN ?4 - score_mate
+                                    WHEN score_mate <= 0 THEN -?4 - score_mate
+                                    ELSE score_cp
+                                END AS eval
+                            FROM moves
+                            WHERE score_cp IS NOT NULL OR score_mate IS NOT NULL
+                        )
+                    )
+                    WHERE fall > ?3 AND (?1 IS NULL OR mover = ?1)
+                )",
+            );
+        }
+
+        let mut sql = format!("SELECT {GAME_COLUMNS} FROM games g");
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY g.id LIMIT ?5");
+
+        let limit = filter
+            .limit
+            .map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let mut statement = self.conn.prepare(&sql)?;
+        let rows = statement.query_map(
+            params![
+                engine,
+                filter.opening.as_deref().map(opening_prefix),
+                filter.eval_drop,
+                MATE_CP,
+                limit,
+            ],
+            GameRow::from_row,
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Find game `game_num` of run `run_id`, or of the latest run that has
+    /// such a game.
+    ///
+    /// # Errors
+    /// Returns an error if the query fails.
+    pub fn find_game(&self, run_id: Option<i64>, game_num: u64) -> Result<Option<GameRow>> {
+        let sql = format!(
+            "SELECT {GAME_COLUMNS} FROM games g
+             WHERE g.game_num = ?1 AND (?2 IS NULL OR g.run_id = ?2)
+             ORDER BY g.run_id DESC, g.id DESC LIMIT 1"
+        );
+        Ok(self
+            .conn
+            .query_row(&sql, params![game_num, run_id], GameRow::from_row)
+            .optional()?)
+    }
+
+    /// The settings a run was started with.
+    ///
+    /// # Errors
+    /// Returns an error if there is no such run.
+    pub fn run_settings(&self, run_id: i64) -> Result<String> {
+        Ok(self.conn.query_row(
+            "SELECT settings FROM runs WHERE id = ?1",
+            params![run_id],
+            |row| row.get(0),
+        )?)
+    }
+}
+
+/// Identity of an engine taking part in a run.
+#[derive(Debug, Clone, Default)]
+pub struct EngineMetadata {
+    /// The label used for the engine in this tool (`stockfish`, `reckless`)
+    pub label: String,
+    /// The path or command used to start the engine
+    pub path: String,
+    /// The engine's `id name`
+    pub id_name: Option<String>,
+    /// The engine's `id author`
+    pub id_author: Option<String>,
+    /// Option values set on the engine, as JSON
+    pub options: Option<String>,
+    /// SHA-256 of the engine binary, as hex
+    pub binary_sha256: Option<String>,
+    /// Every option the engine offers, with types and limits, as JSON
+    pub uci_options: Option<String>,
+}
+
+/// Add columns introduced after a database was created.
+fn migrate(conn: &Connection) -> Result<()> {
+    for (table, column, kind) in [
+        ("games", "start_fen", "TEXT"),
+        ("engines", "uci_options", "TEXT"),
+        ("moves", "time_ms", "INTEGER"),
+    ] {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+            params![table, column],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {kind};"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the file that will run for `command`, searching `PATH` like a shell would.
+fn resolve_binary(command: &str) -> Option<PathBuf> {
+    if command.contains(std::path::MAIN_SEPARATOR) {
+        return Some(PathBuf::from(command));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(command))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Hash the binary that `command` runs.
+///
+/// # Errors
+/// Returns an error if the binary cannot be found or read.
+pub fn binary_sha256(command: &str) -> Result<String> {
+    let path = resolve_binary(command).ok_or_else(|| eyre!("{command} not found on PATH"))?;
+    let bytes =
+        std::fs::read(&path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let digest = Sha256::digest(&bytes);
+    Ok(digest.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameResult, PlyRecord, Termination};
+
+    fn ply(uci: &str, score: Option<Score>) -> PlyRecord {
+        PlyRecord {
+            uci: uci.to_string(),
+            score,
+            depth: Some(10),
+            time_ms: 100,
+            search_ms: None,
+            position_us: None,
+        }
+    }
+
+    fn sample_db() -> ResultsDb {
+        let mut db = ResultsDb::open(Path::new(":memory:")).expect("Failed to open db");
+        let run_id = db.start_run("{}").expect("Failed to start run");
+
+        // Reckless loses a Sicilian as White after its eval collapses
+        let sicilian = GameRecord {
+            result: GameResult::BlackWins,
+            termination: Termination::Checkmate,
+            plies: vec![
+                ply("e2e4", Some(Score::Cp(30))),
+                ply("c7c5", Some(Score::Cp(-20))),
+                ply("g1f3", Some(Score::Cp(25))),
+                ply("d7d6", Some(Score::Cp(-10))),
+                ply("d2d4", Some(Score::Cp(-400))),
+                ply("c5d4", Some(Score::Mate(3))),
+            ],
+            start_fen: None,
+        };
+        db.record_game(run_id, 0, "reckless", "stockfish", &sicilian)
+            .expect("Failed to record game");
+
+        // Reckless wins a French as White with a steady eval
+        let french = GameRecord {
+            result: GameResult::WhiteWins,
+            termination: Termination::Checkmate,
+            plies: vec![
+                ply("e2e4", Some(Score::Cp(30))),
+                ply("e7e6", Some(Score::Cp(-30))),
+            ],
+            start_fen: None,
+        };
+        db.record_game(run_id, 1, "reckless", "stockfish", &french)
+            .expect("Failed to record game");
+
+        // A draw with Stockfish as White
+        let draw = GameRecord {
+            result: GameResult::Draw,
+            termination: Termination::MaxMoves,
+            plies: vec![ply("d2d4", None), ply("d7d5", Some(Score::Cp(0)))],
+            start_fen: None,
+        };
+        db.record_game(run_id, 2, "stockfish", "reckless", &draw)
+            .expect("Failed to record game");
+        db
+    }
+
+    #[test]
+    fn test_query_losses_as_white_in_sicilian() {
+        let db = sample_db();
+        let rows = db
+            .query(&GameFilter {
+                engine: Some("reckless".to_string()),
+                side: Some(Side::White),
+                outcome: Some(Outcome::Loss),
+                opening: Some("Sicilian".to_string()),
+                ..GameFilter::default()
+            })
+            .expect("Query failed");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].game_num, 0);
+        assert_eq!(rows[0].result, "0-1");
+        assert_eq!(rows[0].ply_count, 6);
+
+        let reckless_ms: u64 = db
+            .conn
+            .query_row(
+                "SELECT SUM(time_ms) FROM moves WHERE mover = 'reckless'",
+                [],
+                |row| row.get(0),
+            )
+            .expect("Query failed");
+        assert_eq!(reckless_ms, 500);
+    }
+
+    #[test]
+    fn test_query_eval_drop() {
+        let db = sample_db();
+        let filter = GameFilter {
+            engine: Some("reckless".to_string()),
+            eval_drop: Some(300),
+            ..GameFilter::default()
+        };
+        let rows = db.query(&filter).expect("Query failed");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].game_num, 0);
+
+        // Stockfish's eval only went up
+        let filter = GameFilter {
+            engine: Some("stockfish".to_string()),
+            ..filter
+        };
+        assert!(db.query(&filter).expect("Query failed").is_empty());
+    }
+
+    #[test]
+    fn test_query_draws_and_limit() {
+        let db = sample_db();
+        let draws = db
+            .query(&GameFilter {
+                outcome: Some(Outcome::Draw),
+                ..GameFilter::default()
+            })
+            .expect("Query failed");
+        assert_eq!(draws.len(), 1);
+        assert_eq!(draws[0].white, "stockfish");
+
+        let limited = db
+            .query(&GameFilter {
+                limit: Some(2),
+                ..GameFilter::default()
+            })
+            .expect("Query failed");
+        assert_eq!(limited.len(), 2);
+    }
+
+    #[test]
+    fn test_query_win_or_loss_needs_engine() {
+        let db = sample_db();
+        let error = db
+            .query(&GameFilter {
+                outcome: Some(Outcome::Win),
+                ..GameFilter::default()
+            })
+            .expect_err("Wins without an engine");
+        assert!(error.to_string().contains("needs an engine"), "{error}");
+    }
+
+    #[test]
+    fn test_query_opening_skips_games_from_fen() {
+        let mut db = sample_db();
+        let run_id = db.start_run("{}").expect("Failed to start run");
+        let from_fen = GameRecord {
+            result: GameResult::Draw,
+            termination: Termination::MaxMoves,
+            plies: vec![ply("e2e4", None), ply("c7c5", None)],
+            start_fen: Some("4k3/2p5/8/8/8/8/4P3/4K3 w - - 0 1".to_string()),
+        };
+        db.record_game(run_id, 0, "reckless", "stockfish", &from_fen)
+            .expect("Failed to record game");
+        let rows = db
+            .query(&GameFilter {
+                opening: Some("e2e4 c7c5".to_string()),
+                ..GameFilter::default()
+            })
+            .expect("Query failed");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].start_fen, None);
+    }
+
+    #[test]
+    fn test_find_game_and_run_settings() {
+        let db = sample_db();
+        let game = db
+            .find_game(None, 1)
+            .expect("Query failed")
+            .expect("Game missing");
+        assert_eq!(game.moves, "e2e4 e7e6");
+        assert_eq!(game.start_fen, None);
+        assert_eq!(db.run_settings(game.run_id).expect("Run missing"), "{}");
+
+        assert!(db.find_game(None, 7).expect("Query failed").is_none());
+        assert!(db.find_game(Some(2), 1).expect("Query failed").is_none());
+    }
+}
@@ -0,0 +1,13 @@
+//! Playing a match on several machines.
+//!
+//! A coordinator owns the match: it hands out game assignments to remote
+//! workers over TCP and collects their results, reassigning any game whose
+//! worker disconnects, or goes silent for longer than the game could last,
+//! before finishing it. Workers run engine pairs locally.
+
+mod coordinator;
+mod protocol;
+mod worker;
+
+pub use protocol::Assignment;
+pub use worker::run_worker;
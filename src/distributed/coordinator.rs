@@ -0,0 +1,375 @@
+//! The coordinator, handing out games to remote workers.
+
+use super::protocol::{self, Assignment, CoordinatorMessage, WorkerMessage};
+use crate::match_runner::{Match, RunningMatch, WorkerEvent};
+use crate::schedule::GameSchedule;
+use crate::shutdown::{self, Shutdown};
+use color_eyre::eyre::{Result, eyre};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, Notify, mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// Time a worker gets beyond twice the longest possible game, for starting
+/// and restarting engines.
+const ASSIGNMENT_SLACK: Duration = Duration::from_secs(60);
+
+/// What a worker asking for a game should do.
+#[derive(Debug, PartialEq, Eq)]
+enum Claim {
+    /// Play this game
+    Game(u64),
+    /// Wait: every game is handed out, but some may still come back
+    Wait,
+    /// Disconnect: every game is finished
+    Finished,
+}
+
+/// Which games still need playing.
+struct Schedule {
+    game_counter: Arc<AtomicU64>,
+    games: u64,
+    requeued: BTreeSet<u64>,
+    in_flight: HashSet<u64>,
+}
+
+impl Schedule {
+    fn new(game_counter: Arc<AtomicU64>, games: u64) -> Self {
+        Self {
+            game_counter,
+            games,
+            requeued: BTreeSet::new(),
+            in_flight: HashSet::new(),
+        }
+    }
+
+    /// Hand out a game, preferring ones abandoned by disconnected workers.
+    fn claim(&mut self) -> Claim {
+        let game_num = self.requeued.pop_first().or_else(|| {
+            // Shutdown pushes the counter past the total to stop new games
+            let game_num = self.game_counter.fetch_add(1, Ordering::Relaxed);
+            (game_num < self.games).then_some(game_num)
+        });
+        match game_num {
+            Some(game_num) => {
+                self.in_flight.insert(game_num);
+                Claim::Game(game_num)
+            }
+            None if self.in_flight.is_empty() => Claim::Finished,
+            None => Claim::Wait,
+        }
+    }
+
+    /// A game was played, successfully or not.
+    fn finish(&mut self, game_num: u64) {
+        self.in_flight.remove(&game_num);
+    }
+
+    /// A game's worker went away, so it needs playing again.
+    fn requeue(&mut self, game_num: u64) {
+        if self.in_flight.remove(&game_num) {
+            self.requeued.insert(game_num);
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.requeued.is_empty()
+            && self.in_flight.is_empty()
+            && self.game_counter.load(Ordering::Relaxed) >= self.games
+    }
+}
+
+/// State shared by every worker connection.
+struct Shared {
+    schedule: Mutex<Schedule>,
+    /// Notified whenever a game finishes or is requeued
+    changed: Notify,
+    settings: Match,
+    /// Openings and colours of every game
+    games: GameSchedule,
+    /// How long a worker may hold a game before it is presumed lost
+    assignment_timeout: Duration,
+    events: mpsc::Sender<WorkerEvent>,
+}
+
+impl Shared {
+    /// Wait for a game to hand out, or `None` once the match is over.
+    async fn next_game(&self) -> Option<u64> {
+        loop {
+            let changed = self.changed.notified();
+            let claim = self.schedule.lock().await.claim();
+            match claim {
+                Claim::Game(game_num) => return Some(game_num),
+                Claim::Finished => return None,
+                Claim::Wait => changed.await,
+            }
+        }
+    }
+
+    async fn finish(&self, game_num: u64) {
+        self.schedule.lock().await.finish(game_num);
+        self.changed.notify_waiters();
+    }
+
+    async fn requeue(&self, game_num: u64) {
+        self.schedule.lock().await.requeue(game_num);
+        self.changed.notify_waiters();
+    }
+}
+
+impl Match {
+    /// Hand this match's games out to remote workers connecting on `listener`,
+    /// instead of playing them locally.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[must_use]
+    pub fn coordinate(self, listener: TcpListener) -> RunningMatch {
+        let game_counter = Arc::new(AtomicU64::new(0));
+        let shutdown = Arc::new(Shutdown::new(Arc::clone(&game_counter), self.games));
+        let (events, rx) = mpsc::channel(64);
+        let shared = Arc::new(Shared {
+            schedule: Mutex::new(Schedule::new(game_counter, self.games)),
+            changed: Notify::new(),
+            games: self.schedule(),
+            assignment_timeout: Duration::from_millis(self.game.longest_game_ms())
+                .saturating_mul(2)
+                .saturating_add(ASSIGNMENT_SLACK),
+            settings: self,
+            events,
+        });
+        let accept = tokio::spawn(accept_workers(listener, shared, shutdown.subscribe()));
+
+        RunningMatch {
+            events: rx,
+            shutdown,
+            workers: vec![accept],
+        }
+    }
+}
+
+/// Accept worker connections until every game is finished or the match is aborted.
+async fn accept_workers(
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    mut abort: watch::Receiver<bool>,
+) {
+    let mut connections = JoinSet::new();
+    let mut next_id = 0;
+    loop {
+        let changed = shared.changed.notified();
+        if shared.schedule.lock().await.is_finished() {
+            break;
+        }
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    tracing::info!(worker = next_id, %peer, "Worker connected");
+                    connections.spawn(serve_worker(next_id, stream, Arc::clone(&shared)));
+                    next_id += 1;
+                }
+                Err(e) => tracing::warn!(error = %e, "Failed to accept worker connection"),
+            },
+            () = changed => {}
+            () = shutdown::aborted(&mut abort) => {
+                connections.abort_all();
+                break;
+            }
+        }
+    }
+    // Let idle workers hear that there is nothing left
+    while connections.join_next().await.is_some() {}
+}
+
+/// Talk to one worker, reassigning its game if it disconnects mid-game.
+async fn serve_worker(worker_id: usize, stream: TcpStream, shared: Arc<Shared>) {
+    let mut current = None;
+    if let Err(e) = assign_games(worker_id, stream, &shared, &mut current).await {
+        tracing::warn!(worker = worker_id, error = %e, "Worker connection failed");
+    }
+    if let Some(game_num) = current {
+        tracing::warn!(
+            worker = worker_id,
+            game = game_num,
+            "Worker disconnected mid-game, reassigning"
+        );
+        shared.requeue(game_num).await;
+    }
+}
+
+/// Hand games to a worker and forward its results, tracking the game it is
+/// playing in `current`.
+///
+/// A worker lost without its connection closing, e.g. to a power cut, sends
+/// nothing more, so a game not finished within the assignment timeout is
+/// treated as a disconnect.
+async fn assign_games(
+    worker_id: usize,
+    stream: TcpStream,
+    shared: &Shared,
+    current: &mut Option<u64>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut deadline = None;
+
+    loop {
+        let received = match (deadline, *current) {
+            (Some(deadline), Some(game_num)) => {
+                tokio::time::timeout_at(deadline, protocol::recv(&mut lines))
+                    .await
+                    .map_err(|_| {
+                        eyre!(
+                            "No result for game {game_num} within {}s",
+                            shared.assignment_timeout.as_secs()
+                        )
+                    })?
+            }
+            _ => protocol::recv(&mut lines).await,
+        };
+        let Some(message) = received? else {
+            break;
+        };
+        let event = match message {
+            WorkerMessage::Request => {
+                let Some(game_num) = shared.next_game().await else {
+                    protocol::send(&mut writer, &CoordinatorMessage::Done).await?;
+                    break;
+                };
+                *current = Some(game_num);
+                deadline = Some(Instant::now() + shared.assignment_timeout);
+                let assignment = Assignment {
+                    params: shared.games.game(game_num),
+                    settings: shared.settings.game,
+                };
+                protocol::send(&mut writer, &CoordinatorMessage::Assign(assignment)).await?;
+                continue;
+            }
+            WorkerMessage::Completed(completed) => {
+                let game_num = completed.game_num;
+                if current.take() != Some(game_num) {
+                    return Err(eyre!("Result for unassigned game {game_num}"));
+                }
+                deadline = None;
+                shared.finish(game_num).await;
+                WorkerEvent::GameCompleted(completed)
+            }
+            WorkerMessage::Failed { game_num, error } => {
+                if current.take() != Some(game_num) {
+                    return Err(eyre!("Failure for unassigned game {game_num}"));
+                }
+                deadline = None;
+                shared.finish(game_num).await;
+                WorkerEvent::GameFailed {
+                    worker_id,
+                    game_num,
+                    error,
+                }
+            }
+        };
+        if shared.events.send(event).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_reassigns_abandoned_games() {
+        let mut schedule = Schedule::new(Arc::new(AtomicU64::new(0)), 2);
+        assert_eq!(schedule.claim(), Claim::Game(0));
+        assert_eq!(schedule.claim(), Claim::Game(1));
+        assert_eq!(schedule.claim(), Claim::Wait);
+
+        schedule.requeue(0);
+        assert_eq!(schedule.claim(), Claim::Game(0));
+        schedule.finish(0);
+        assert!(!schedule.is_finished());
+        assert_eq!(schedule.claim(), Claim::Wait);
+
+        schedule.finish(1);
+        assert!(schedule.is_finished());
+        assert_eq!(schedule.claim(), Claim::Finished);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_worker_game_is_reassigned_after_deadline() {
+        use crate::game::{GameRecord, GameResult, Termination};
+        use crate::match_runner::GameCompleted;
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let addr = listener.local_addr().expect("No local address");
+        let mut running = Match::new("stockfish", "reckless")
+            .with_movetime(10)
+            .with_max_moves(20)
+            .coordinate(listener);
+
+        // Takes a game, then neither reads nor writes, nor closes the socket
+        let silent = TcpStream::connect(addr).await.expect("Failed to connect");
+        let (reader, mut writer) = silent.into_split();
+        let mut silent_lines = BufReader::new(reader).lines();
+        protocol::send(&mut writer, &WorkerMessage::Request)
+            .await
+            .expect("Failed to request");
+        let assigned: CoordinatorMessage = protocol::recv(&mut silent_lines)
+            .await
+            .expect("Failed to receive")
+            .expect("Coordinator hung up");
+        assert!(matches!(assigned, CoordinatorMessage::Assign(a) if a.params.game_num == 0));
+
+        // The only game is held, so the next worker gets it once it expires
+        let healthy = TcpStream::connect(addr).await.expect("Failed to connect");
+        let (reader, mut writer) = healthy.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let started = Instant::now();
+        protocol::send(&mut writer, &WorkerMessage::Request)
+            .await
+            .expect("Failed to request");
+        let assigned: CoordinatorMessage = protocol::recv(&mut lines)
+            .await
+            .expect("Failed to receive")
+            .expect("Coordinator hung up");
+        assert!(matches!(assigned, CoordinatorMessage::Assign(a) if a.params.game_num == 0));
+        assert!(started.elapsed() >= ASSIGNMENT_SLACK);
+
+        let completed = GameCompleted {
+            game_num: 0,
+            record: GameRecord {
+                result: GameResult::Draw,
+                termination: Termination::MaxMoves,
+                plies: Vec::new(),
+                start_fen: None,
+            },
+            stockfish_is_white: true,
+        };
+        protocol::send(&mut writer, &WorkerMessage::Completed(completed))
+            .await
+            .expect("Failed to send result");
+        assert!(matches!(
+            running.next_event().await,
+            Some(WorkerEvent::GameCompleted(_))
+        ));
+        protocol::send(&mut writer, &WorkerMessage::Request)
+            .await
+            .expect("Failed to request");
+        let done: CoordinatorMessage = protocol::recv(&mut lines)
+            .await
+            .expect("Failed to receive")
+            .expect("Coordinator hung up");
+        assert!(matches!(done, CoordinatorMessage::Done));
+        drop(writer);
+        running.join().await;
+        drop(silent_lines);
+    }
+}
@@ -0,0 +1,65 @@
+//! Messages between the coordinator and workers, sent as JSON, one per line.
+
+use crate::game::GameSettings;
+use crate::match_runner::GameCompleted;
+use crate::schedule::GameParams;
+use color_eyre::eyre::{Result, WrapErr};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt, Lines};
+
+/// A game for a worker to play.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Assignment {
+    /// Which game, its colours and opening
+    #[serde(flatten)]
+    pub params: GameParams,
+    /// How to play the game
+    pub settings: GameSettings,
+}
+
+/// A message from a worker to the coordinator.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorkerMessage {
+    /// Ask for the next game
+    Request,
+    /// The assigned game finished
+    Completed(GameCompleted),
+    /// The assigned game failed and the worker restarted its engines
+    Failed { game_num: u64, error: String },
+}
+
+/// A message from the coordinator to a worker.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoordinatorMessage {
+    /// Play this game
+    Assign(Assignment),
+    /// There are no more games, disconnect
+    Done,
+}
+
+/// Send one message.
+pub async fn send(
+    writer: &mut (impl AsyncWrite + Send + Unpin),
+    message: &(impl Serialize + Sync),
+) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Receive one message, or `None` if the peer disconnected.
+pub async fn recv<T: DeserializeOwned>(
+    lines: &mut Lines<impl AsyncBufRead + Send + Unpin>,
+) -> Result<Option<T>> {
+    let Some(line) = lines.next_line().await? else {
+        return Ok(None);
+    };
+    let message =
+        serde_json::from_str(&line).wrap_err_with(|| format!("Invalid message: {line}"))?;
+    Ok(Some(message))
+}
@@ -0,0 +1,126 @@
+//! Remote workers, playing games handed out by a coordinator.
+
+use super::protocol::{self, CoordinatorMessage, WorkerMessage};
+use crate::engine::EngineConfig;
+use crate::game::GameRunner;
+use crate::match_runner::{EnginePair, GameCompleted};
+use crate::resources::{Resources, SearchSlots};
+use crate::schedule::GameParams;
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+/// Play games from the coordinator at `addr` on `workers` local engine pairs,
+/// until it has no more to hand out.
+///
+/// # Errors
+/// Returns an error if no engine pair could connect to the coordinator.
+pub async fn run_worker(
+    addr: &str,
+    stockfish: &EngineConfig,
+    reckless: &EngineConfig,
+    workers: usize,
+    resources: Resources,
+) -> Result<()> {
+    let threads = stockfish.threads().max(reckless.threads());
+    let search_slots = resources.search_slots(threads)?;
+    let mut tasks = JoinSet::new();
+    for worker_id in 0..workers {
+        let (addr, stockfish, reckless) = (addr.to_string(), stockfish.clone(), reckless.clone());
+        let search_slots = search_slots.clone();
+        tasks.spawn(async move {
+            play_assignments(
+                worker_id,
+                &addr,
+                &stockfish,
+                &reckless,
+                resources,
+                search_slots,
+            )
+            .await
+        });
+    }
+
+    let mut succeeded = false;
+    while let Some(result) = tasks.join_next().await {
+        match result? {
+            Ok(()) => succeeded = true,
+            Err(e) => tracing::error!(error = %e, "Worker failed"),
+        }
+    }
+    if !succeeded && workers > 0 {
+        return Err(eyre!("Every worker failed"));
+    }
+    Ok(())
+}
+
+/// Play assigned games on one engine pair over its own connection.
+async fn play_assignments(
+    worker_id: usize,
+    addr: &str,
+    stockfish: &EngineConfig,
+    reckless: &EngineConfig,
+    resources: Resources,
+    search_slots: Option<SearchSlots>,
+) -> Result<()> {
+    let stream = TcpStream::connect(addr)
+        .await
+        .wrap_err_with(|| format!("Failed to connect to coordinator at {addr}"))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut engines = EnginePair::spawn(worker_id, stockfish, reckless, resources).await?;
+    tracing::info!(worker = worker_id, %addr, "Connected to coordinator");
+
+    loop {
+        protocol::send(&mut writer, &WorkerMessage::Request).await?;
+        let Some(CoordinatorMessage::Assign(assignment)) = protocol::recv(&mut lines).await? else {
+            break;
+        };
+        let GameParams {
+            game_num,
+            stockfish_is_white,
+            opening,
+        } = assignment.params;
+        let ponder = assignment.settings.time_control.ponder;
+        let runner =
+            GameRunner::from_settings(assignment.settings).with_search_slots(search_slots.clone());
+        // Settings these engines can't play fail the game without a restart
+        if let Err(e) = runner.check().and_then(|()| resources.check_ponder(ponder)) {
+            tracing::warn!(worker = worker_id, error = %e, "Cannot play game");
+            let failed = WorkerMessage::Failed {
+                game_num,
+                error: e.to_string(),
+            };
+            protocol::send(&mut writer, &failed).await?;
+            continue;
+        }
+        let result = engines
+            .play_game(&runner, stockfish_is_white, opening.as_deref(), |_| {})
+            .await;
+
+        match result {
+            Ok(record) => {
+                let completed = GameCompleted {
+                    game_num,
+                    record,
+                    stockfish_is_white,
+                };
+                protocol::send(&mut writer, &WorkerMessage::Completed(completed)).await?;
+            }
+            Err(e) => {
+                tracing::warn!(worker = worker_id, error = %e, "Game failed, restarting engines");
+                let failed = WorkerMessage::Failed {
+                    game_num,
+                    error: e.to_string(),
+                };
+                protocol::send(&mut writer, &failed).await?;
+                engines.restart().await?;
+            }
+        }
+    }
+
+    tracing::info!(worker = worker_id, "No more games");
+    engines.quit().await;
+    Ok(())
+}
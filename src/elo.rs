@@ -0,0 +1,171 @@
+//! Elo estimation and sequential probability ratio test (SPRT) statistics.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of standard deviations for a 95% confidence interval.
+const Z_95: f64 = 1.959_964;
+
+/// Expected score for a player rated `elo` points above their opponent.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Elo difference implied by an expected score.
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// SPRT decision bounds `(lower, upper)` on the log-likelihood ratio for
+/// false positive rate `alpha` and false negative rate `beta`.
+#[must_use]
+pub fn sprt_bounds(alpha: f64, beta: f64) -> (f64, f64) {
+    ((beta / (1.0 - alpha)).ln(), ((1.0 - beta) / alpha).ln())
+}
+
+/// The hypotheses and error rates of an SPRT.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sprt {
+    /// Null hypothesis: the tested engine's Elo advantage
+    pub elo0: f64,
+    /// Alternative hypothesis: the tested engine's Elo advantage
+    pub elo1: f64,
+    /// False positive rate
+    pub alpha: f64,
+    /// False negative rate
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+impl Sprt {
+    /// Decision bounds `(lower, upper)` on the log-likelihood ratio.
+    #[must_use]
+    pub fn bounds(&self) -> (f64, f64) {
+        sprt_bounds(self.alpha, self.beta)
+    }
+}
+
+/// Wins, draws and losses from one engine's point of view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Wdl {
+    /// Games won
+    pub wins: u64,
+    /// Games drawn
+    pub draws: u64,
+    /// Games lost
+    pub losses: u64,
+}
+
+impl Wdl {
+    /// Total number of games.
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Mean score per game and its per-game variance.
+    #[allow(clippy::cast_precision_loss)]
+    fn score_and_variance(&self) -> Option<(f64, f64)> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let n = total as f64;
+        let (w, d, l) = (
+            self.wins as f64 / n,
+            self.draws as f64 / n,
+            self.losses as f64 / n,
+        );
+        let score = w + d / 2.0;
+        let variance = l.mul_add(
+            score.powi(2),
+            w.mul_add((1.0 - score).powi(2), d * (0.5 - score).powi(2)),
+        );
+        Some((score, variance))
+    }
+
+    /// Elo difference and the half-width of its 95% confidence interval.
+    ///
+    /// Returns `None` until the score is strictly between 0% and 100%.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (score, variance) = self.score_and_variance()?;
+        if score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let stderr = (variance / self.total() as f64).sqrt();
+        let low = Z_95.mul_add(-stderr, score).max(f64::EPSILON);
+        let high = Z_95.mul_add(stderr, score).min(1.0 - f64::EPSILON);
+        let margin = (elo_from_score(high) - elo_from_score(low)) / 2.0;
+        Some((elo_from_score(score), margin))
+    }
+
+    /// Log-likelihood ratio of H1 (`elo1`) against H0 (`elo0`), using the
+    /// normal approximation to the trinomial distribution.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let Some((score, variance)) = self.score_and_variance() else {
+            return 0.0;
+        };
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+        self.total() as f64 * (s1 - s0) * 2.0f64.mul_add(score, -s0 - s1) / (2.0 * variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elo_even_match() {
+        let wdl = Wdl {
+            wins: 100,
+            draws: 200,
+            losses: 100,
+        };
+        let (elo, margin) = wdl.elo().expect("Elo should be defined");
+        assert!(elo.abs() < 1e-9);
+        assert!(margin > 0.0 && margin < 30.0);
+        assert!(wdl.llr(0.0, 5.0) < 0.0);
+    }
+
+    #[test]
+    fn test_elo_undefined_for_perfect_score() {
+        let wdl = Wdl {
+            wins: 10,
+            draws: 0,
+            losses: 0,
+        };
+        assert!(wdl.elo().is_none());
+        assert!(Wdl::default().elo().is_none());
+    }
+
+    #[test]
+    fn test_llr_favours_stronger_engine() {
+        // 55% score is roughly +35 Elo
+        let wdl = Wdl {
+            wins: 3000,
+            draws: 5000,
+            losses: 2000,
+        };
+        let (elo, _) = wdl.elo().expect("Elo should be defined");
+        assert!((elo - 34.9).abs() < 0.5);
+        let (_, upper) = sprt_bounds(0.05, 0.05);
+        assert!(wdl.llr(0.0, 5.0) > upper);
+    }
+}
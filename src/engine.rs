@@ -1,5 +1,191 @@
-//! UCI chess engine communication module.
+//! Chess engine communication, over UCI or XBoard.
 
+mod any;
+mod config;
+mod connection;
+mod info;
+// Public only for the mock-uci-engine binary, not as part of the API
+#[doc(hidden)]
+pub mod mock;
+mod perft;
+mod search;
 mod uci;
+mod xboard;
 
+pub use any::AnyEngine;
+pub use config::{EngineConfig, OptionValue, Protocol};
+pub use connection::Transcript;
+pub use info::{EngineInfo, OptionKind, UciOption};
+pub use perft::Divide;
+pub use search::{PvLine, Score, SearchLimits, SearchResult};
 pub use uci::UciEngine;
+pub use xboard::XboardEngine;
+
+use color_eyre::eyre::{Result, eyre};
+
+/// A chess engine that games can be played against, whatever protocol it
+/// speaks.
+///
+/// Every method's future is `Send`, so engines can be driven from spawned
+/// tasks.
+pub trait Engine: Send {
+    /// What the engine reported about itself on startup.
+    fn info(&self) -> &EngineInfo;
+
+    /// The engine's process id, if it runs as a child process.
+    fn pid(&self) -> Option<u32>;
+
+    /// Log every later command and response to `transcript`.
+    fn set_transcript(&mut self, transcript: Transcript);
+
+    /// Set an option, e.g. `Hash` or `Threads`.
+    ///
+    /// Takes effect once the engine next reports ready.
+    ///
+    /// # Errors
+    /// Returns an error if sending the command fails.
+    fn set_option(&mut self, name: &str, value: &str) -> impl Future<Output = Result<()>> + Send;
+
+    /// Wait until the engine has processed every command sent so far.
+    ///
+    /// # Errors
+    /// Returns an error if the engine doesn't respond.
+    fn ready(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Tell the engine we're ready to start a new game.
+    ///
+    /// # Errors
+    /// Returns an error if the engine doesn't respond.
+    fn new_game(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Set the position using a list of UCI moves from `start_fen`, or from
+    /// the standard starting position if there is none.
+    ///
+    /// # Errors
+    /// Returns an error if sending the commands fails.
+    fn set_position_from(
+        &mut self,
+        start_fen: Option<&str>,
+        moves: &[String],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Play `moves`, in UCI notation, on from the position set last, so that
+    /// a game in progress needn't be sent again from the start.
+    ///
+    /// # Errors
+    /// Returns an error if no position has been set, a move is illegal or
+    /// sending the commands fails.
+    fn push_moves(&mut self, moves: &[String]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Set the position using a list of UCI moves from the starting position.
+    ///
+    /// # Errors
+    /// Returns an error if sending the commands fails.
+    fn set_position(&mut self, moves: &[String]) -> impl Future<Output = Result<()>> + Send {
+        self.set_position_from(None, moves)
+    }
+
+    /// Search within `limits`, returning the best move along with the
+    /// engine's final search report.
+    ///
+    /// # Errors
+    /// Returns an error if the engine fails to respond.
+    fn search(
+        &mut self,
+        limits: &SearchLimits,
+    ) -> impl Future<Output = Result<SearchResult>> + Send {
+        self.search_with(limits, |_| {})
+    }
+
+    /// Search like [`search`](Self::search), calling `on_info` with the
+    /// report so far whenever the engine sends search output.
+    ///
+    /// # Errors
+    /// Returns an error if the engine fails to respond.
+    fn search_with(
+        &mut self,
+        limits: &SearchLimits,
+        on_info: impl FnMut(&SearchResult) + Send,
+    ) -> impl Future<Output = Result<SearchResult>> + Send;
+
+    /// Get the best move from the engine with a time limit.
+    ///
+    /// # Errors
+    /// Returns an error if the engine fails to respond.
+    fn get_best_move(
+        &mut self,
+        movetime_ms: u64,
+    ) -> impl Future<Output = Result<SearchResult>> + Send {
+        async move { self.search(&SearchLimits::Movetime(movetime_ms)).await }
+    }
+
+    /// Whether the game runner can drive the engine's pondering.
+    fn can_ponder(&self) -> bool {
+        false
+    }
+
+    /// Start pondering on the position set last, which should end with the
+    /// opponent move the engine expects. The search runs until
+    /// [`ponder_hit`](Self::ponder_hit) or [`stop`](Self::stop).
+    ///
+    /// # Errors
+    /// Returns an error if sending the command fails, or the engine cannot
+    /// ponder.
+    fn go_ponder(&mut self, _limits: &SearchLimits) -> impl Future<Output = Result<()>> + Send {
+        async { Err(eyre!("Engine cannot ponder")) }
+    }
+
+    /// Tell a pondering engine that the expected move was played, and wait
+    /// for it to finish searching as if it had been told to `go`.
+    ///
+    /// # Errors
+    /// Returns an error if the engine fails to respond, or cannot ponder.
+    fn ponder_hit(&mut self) -> impl Future<Output = Result<SearchResult>> + Send {
+        async { Err(eyre!("Engine cannot ponder")) }
+    }
+
+    /// Stop a search, e.g. pondering on a move that wasn't played, and
+    /// discard its result.
+    ///
+    /// # Errors
+    /// Returns an error if the engine fails to respond, or cannot ponder.
+    fn stop(&mut self) -> impl Future<Output = Result<()>> + Send {
+        async { Err(eyre!("Engine cannot ponder")) }
+    }
+
+    /// Quit the engine gracefully, killing it if it doesn't exit in time.
+    ///
+    /// # Errors
+    /// Returns an error if the engine process cannot be killed.
+    fn quit(&mut self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Start an in-process mock engine and connect to it over a duplex pipe.
+///
+/// # Errors
+/// Returns an error if the mock fails the UCI handshake.
+#[cfg(test)]
+pub(crate) async fn spawn_mock(name: &str, behaviour: mock::MockBehaviour) -> Result<UciEngine> {
+    let (engine_side, mock_side) = tokio::io::duplex(64 * 1024);
+    let (mock_reader, mock_writer) = tokio::io::split(mock_side);
+    tokio::spawn(mock::serve(behaviour, mock_reader, mock_writer));
+    let (reader, writer) = tokio::io::split(engine_side);
+    UciEngine::from_io(name, reader, writer).await
+}
+
+/// Start an in-process mock engine speaking XBoard and connect to it over a
+/// duplex pipe.
+///
+/// # Errors
+/// Returns an error if the mock fails the XBoard handshake.
+#[cfg(test)]
+pub(crate) async fn spawn_xboard_mock(
+    name: &str,
+    behaviour: mock::MockBehaviour,
+) -> Result<XboardEngine> {
+    let (engine_side, mock_side) = tokio::io::duplex(64 * 1024);
+    let (mock_reader, mock_writer) = tokio::io::split(mock_side);
+    tokio::spawn(mock::serve(behaviour, mock_reader, mock_writer));
+    let (reader, writer) = tokio::io::split(engine_side);
+    XboardEngine::from_io(name, reader, writer).await
+}
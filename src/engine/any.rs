@@ -0,0 +1,124 @@
+//! An engine speaking whichever protocol its config names.
+
+use super::{
+    Engine, EngineConfig, EngineInfo, Protocol, SearchLimits, SearchResult, Transcript, UciEngine,
+    XboardEngine,
+};
+use color_eyre::eyre::Result;
+
+/// A UCI or XBoard engine, so both can play in the same match.
+pub enum AnyEngine {
+    /// An engine speaking UCI
+    Uci(UciEngine),
+    /// An engine speaking XBoard
+    Xboard(XboardEngine),
+}
+
+impl AnyEngine {
+    /// Spawn the engine at `path`, speaking `protocol`.
+    ///
+    /// # Errors
+    /// Returns an error if the engine cannot be spawned or fails the handshake.
+    pub async fn new(path: &str, protocol: Protocol, name: &str) -> Result<Self> {
+        Ok(match protocol {
+            Protocol::Uci => Self::Uci(UciEngine::new(path, name).await?),
+            Protocol::Xboard => Self::Xboard(XboardEngine::new(path, name).await?),
+        })
+    }
+
+    /// Spawn the engine described by `config` with its protocol and set its
+    /// options.
+    ///
+    /// # Errors
+    /// Returns an error if the engine cannot be spawned or fails the handshake.
+    pub async fn start(config: &EngineConfig, name: &str) -> Result<Self> {
+        Ok(match config.protocol {
+            Protocol::Uci => Self::Uci(UciEngine::start(config, name).await?),
+            Protocol::Xboard => Self::Xboard(XboardEngine::start(config, name).await?),
+        })
+    }
+}
+
+impl From<UciEngine> for AnyEngine {
+    fn from(engine: UciEngine) -> Self {
+        Self::Uci(engine)
+    }
+}
+
+impl From<XboardEngine> for AnyEngine {
+    fn from(engine: XboardEngine) -> Self {
+        Self::Xboard(engine)
+    }
+}
+
+/// Forward a call to whichever engine is inside.
+macro_rules! dispatch {
+    ($self:ident, $engine:ident => $call:expr) => {
+        match $self {
+            Self::Uci($engine) => $call,
+            Self::Xboard($engine) => $call,
+        }
+    };
+}
+
+impl Engine for AnyEngine {
+    fn info(&self) -> &EngineInfo {
+        dispatch!(self, engine => engine.info())
+    }
+
+    fn pid(&self) -> Option<u32> {
+        dispatch!(self, engine => engine.pid())
+    }
+
+    fn set_transcript(&mut self, transcript: Transcript) {
+        dispatch!(self, engine => engine.set_transcript(transcript));
+    }
+
+    async fn set_option(&mut self, name: &str, value: &str) -> Result<()> {
+        dispatch!(self, engine => engine.set_option(name, value).await)
+    }
+
+    async fn ready(&mut self) -> Result<()> {
+        dispatch!(self, engine => engine.ready().await)
+    }
+
+    async fn new_game(&mut self) -> Result<()> {
+        dispatch!(self, engine => engine.new_game().await)
+    }
+
+    async fn set_position_from(&mut self, start_fen: Option<&str>, moves: &[String]) -> Result<()> {
+        dispatch!(self, engine => engine.set_position_from(start_fen, moves).await)
+    }
+
+    async fn push_moves(&mut self, moves: &[String]) -> Result<()> {
+        dispatch!(self, engine => engine.push_moves(moves).await)
+    }
+
+    async fn search_with(
+        &mut self,
+        limits: &SearchLimits,
+        on_info: impl FnMut(&SearchResult) + Send,
+    ) -> Result<SearchResult> {
+        dispatch!(self, engine => engine.search_with(limits, on_info).await)
+    }
+
+    fn can_ponder(&self) -> bool {
+        dispatch!(self, engine => engine.can_ponder())
+    }
+
+    async fn go_ponder(&mut self, limits: &SearchLimits) -> Result<()> {
+        dispatch!(self, engine => engine.go_ponder(limits).await)
+    }
+
+    async fn ponder_hit(&mut self) -> Result<SearchResult> {
+        dispatch!(self, engine => engine.ponder_hit().await)
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        dispatch!(self, engine => engine.stop().await)
+    }
+
+    async fn quit(&mut self) -> Result<()> {
+        dispatch!(self, engine => engine.quit().await)
+    }
+}
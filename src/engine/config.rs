@@ -0,0 +1,86 @@
+//! How to start an engine.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The value of a UCI option, as written in a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    /// A check option
+    Bool(bool),
+    /// A spin option
+    Int(i64),
+    /// A string or combo option
+    Text(String),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Text(value) => f.write_str(value),
+        }
+    }
+}
+
+/// The protocol an engine speaks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    /// The Universal Chess Interface
+    #[default]
+    Uci,
+    /// The Chess Engine Communication Protocol of XBoard/WinBoard, version 2
+    Xboard,
+}
+
+/// An engine command and the options to start it with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Command that runs the engine
+    pub path: String,
+    /// The protocol the engine speaks
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// Options to set after startup, such as `Hash` or `Threads`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, OptionValue>,
+}
+
+impl EngineConfig {
+    /// An engine with default options.
+    #[must_use]
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            protocol: Protocol::Uci,
+            options: BTreeMap::new(),
+        }
+    }
+
+    /// The number of search threads the engine is configured for, from its
+    /// `Threads` option.
+    #[must_use]
+    pub fn threads(&self) -> usize {
+        match self.options.get("Threads") {
+            Some(OptionValue::Int(threads)) => usize::try_from(*threads).unwrap_or(1).max(1),
+            _ => 1,
+        }
+    }
+}
+
+impl From<&str> for EngineConfig {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<String> for EngineConfig {
+    fn from(path: String) -> Self {
+        Self::new(path)
+    }
+}
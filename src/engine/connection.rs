@@ -0,0 +1,157 @@
+//! The line-based pipe to an engine, whatever protocol it speaks.
+
+use color_eyre::eyre::{ContextCompat, Result, eyre};
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+
+/// How long an engine gets to exit after `quit` before it is killed.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A shared log of the lines exchanged with one or more engines.
+#[derive(Clone)]
+pub struct Transcript(Arc<Mutex<dyn Write + Send>>);
+
+impl Transcript {
+    /// Log to `writer`, e.g. a file.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(writer)))
+    }
+
+    /// Append one line, marked `>` if sent to the engine and `<` if received.
+    fn log(&self, engine: &str, direction: char, line: &str) {
+        let Ok(mut writer) = self.0.lock() else {
+            return;
+        };
+        if let Err(e) =
+            writeln!(writer, "{engine} {direction} {line}").and_then(|()| writer.flush())
+        {
+            tracing::warn!(%engine, error = %e, "Failed to write transcript");
+        }
+    }
+}
+
+/// Lines to and from an engine, usually a child process.
+pub struct Connection {
+    name: String,
+    transcript: Option<Transcript>,
+    process: Option<Child>,
+    stdin: Box<dyn AsyncWrite + Send + Unpin>,
+    stdout: BufReader<Box<dyn AsyncRead + Send + Unpin>>,
+}
+
+impl Connection {
+    /// Spawn the engine at `path`.
+    pub fn spawn(path: &str, name: &str) -> Result<Self> {
+        let mut process = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdin = process.stdin.take().context("Failed to get stdin")?;
+        let stdout = process.stdout.take().context("Failed to get stdout")?;
+        Ok(Self::new(
+            name,
+            Some(process),
+            Box::new(stdout),
+            Box::new(stdin),
+        ))
+    }
+
+    /// Talk to an engine over an arbitrary reader/writer pair.
+    pub fn from_io(
+        name: &str,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        Self::new(name, None, Box::new(reader), Box::new(writer))
+    }
+
+    fn new(
+        name: &str,
+        process: Option<Child>,
+        stdout: Box<dyn AsyncRead + Send + Unpin>,
+        stdin: Box<dyn AsyncWrite + Send + Unpin>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            transcript: None,
+            process,
+            stdin,
+            stdout: BufReader::new(stdout),
+        }
+    }
+
+    /// The name used for the engine in logs.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The engine's process id, if it runs as a child process.
+    pub fn pid(&self) -> Option<u32> {
+        self.process.as_ref().and_then(Child::id)
+    }
+
+    /// Log every later command and response to `transcript`.
+    pub fn set_transcript(&mut self, transcript: Transcript) {
+        self.transcript = Some(transcript);
+    }
+
+    /// Send a command to the engine.
+    pub async fn send(&mut self, command: &str) -> Result<()> {
+        tracing::trace!(engine = %self.name, %command, "Sending command");
+        if let Some(transcript) = &self.transcript {
+            transcript.log(&self.name, '>', command);
+        }
+        self.stdin.write_all(command.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Read a single trimmed response line from the engine.
+    pub async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        let bytes_read = self.stdout.read_line(&mut line).await?;
+        if bytes_read == 0 {
+            return Err(eyre!("Engine {} closed unexpectedly", self.name));
+        }
+        let trimmed = line.trim();
+        tracing::trace!(engine = %self.name, response = %trimmed, "Received");
+        if let Some(transcript) = &self.transcript {
+            transcript.log(&self.name, '<', trimmed);
+        }
+        Ok(trimmed.to_string())
+    }
+
+    /// Wait for a specific response line from the engine.
+    pub async fn wait_for(&mut self, expected: &str) -> Result<()> {
+        while self.read_line().await? != expected {}
+        Ok(())
+    }
+
+    /// Send `command` to make the engine exit, killing it if it doesn't in
+    /// time.
+    pub async fn quit(&mut self, command: &str) -> Result<()> {
+        // The engine may already be gone, in which case there is nothing to send to
+        if let Err(e) = self.send(command).await {
+            tracing::debug!(engine = %self.name, error = %e, "Failed to send quit");
+        }
+        let Some(process) = &mut self.process else {
+            return Ok(());
+        };
+        if tokio::time::timeout(QUIT_TIMEOUT, process.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(engine = %self.name, "Engine did not quit in time, killing it");
+            process.kill().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! What an engine reports about itself during the UCI handshake.
+
+use serde::{Deserialize, Serialize};
+
+/// The kind of value a UCI option takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionKind {
+    /// A boolean
+    Check,
+    /// An integer within `min..=max`
+    Spin,
+    /// One of a fixed list of strings
+    Combo,
+    /// An action with no value
+    Button,
+    /// Free text
+    String,
+}
+
+impl OptionKind {
+    fn parse(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "check" => Self::Check,
+            "spin" => Self::Spin,
+            "combo" => Self::Combo,
+            "button" => Self::Button,
+            "string" => Self::String,
+            _ => return None,
+        })
+    }
+}
+
+/// An option the engine offers, from an `option` line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UciOption {
+    /// The option's name, e.g. `Hash`
+    pub name: String,
+    /// The kind of value it takes
+    pub kind: OptionKind,
+    /// Its default value, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Smallest value of a spin option
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<i64>,
+    /// Largest value of a spin option
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+    /// Allowed values of a combo option
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vars: Vec<String>,
+}
+
+impl UciOption {
+    /// Parse the rest of an `option` line, e.g.
+    /// `name Hash type spin default 16 min 1 max 1024`.
+    #[must_use]
+    pub fn parse(line: &str) -> Option<Self> {
+        const KEYWORDS: [&str; 6] = ["name", "type", "default", "min", "max", "var"];
+
+        let mut fields: Vec<(&str, Vec<&str>)> = Vec::new();
+        for token in line.split_whitespace() {
+            match fields.last_mut() {
+                Some((_, value)) if !KEYWORDS.contains(&token) => value.push(token),
+                _ if KEYWORDS.contains(&token) => fields.push((token, Vec::new())),
+                _ => return None,
+            }
+        }
+
+        let mut option = Self {
+            name: String::new(),
+            kind: OptionKind::Button,
+            default: None,
+            min: None,
+            max: None,
+            vars: Vec::new(),
+        };
+        let mut kind = None;
+        for (keyword, value) in fields {
+            let value = value.join(" ");
+            match keyword {
+                "name" => option.name = value,
+                "type" => kind = OptionKind::parse(&value),
+                // An empty string default is written as `<empty>`
+                "default" if value == "<empty>" => option.default = Some(String::new()),
+                "default" => option.default = Some(value),
+                "min" => option.min = value.parse().ok(),
+                "max" => option.max = value.parse().ok(),
+                _ => option.vars.push(value),
+            }
+        }
+        option.kind = kind?;
+        (!option.name.is_empty()).then_some(option)
+    }
+}
+
+/// An engine's identity and options, as reported before `uciok`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineInfo {
+    /// The name from `id name`
+    pub name: Option<String>,
+    /// The author from `id author`
+    pub author: Option<String>,
+    /// Every option the engine offers, in the order it listed them
+    pub options: Vec<UciOption>,
+}
+
+impl EngineInfo {
+    /// Pick up an `id` or `option` line; other lines are ignored.
+    pub fn parse_line(&mut self, line: &str) {
+        if let Some(name) = line.strip_prefix("id name ") {
+            self.name = Some(name.to_string());
+        } else if let Some(author) = line.strip_prefix("id author ") {
+            self.author = Some(author.to_string());
+        } else if let Some(option) = line.strip_prefix("option ") {
+            let Some(option) = UciOption::parse(option) else {
+                tracing::debug!(line, "Ignoring malformed option");
+                return;
+            };
+            self.options.push(option);
+        }
+    }
+
+    /// The option called `name`, ignoring case as UCI does.
+    #[must_use]
+    pub fn option(&self, name: &str) -> Option<&UciOption> {
+        self.options
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_handshake() {
+        let mut info = EngineInfo::default();
+        for line in [
+            "id name Stockfish 17.1",
+            "id author the Stockfish developers (see AUTHORS file)",
+            "option name Debug Log File type string default <empty>",
+            "option name Threads type spin default 1 min 1 max 1024",
+            "option name Clear Hash type button",
+            "option name Ponder type check default false",
+            "option name Style type combo default Normal var Solid var Normal var Risky",
+            "option name Broken type",
+            "info string NNUE loaded",
+        ] {
+            info.parse_line(line);
+        }
+
+        assert_eq!(info.name.as_deref(), Some("Stockfish 17.1"));
+        assert_eq!(
+            info.author.as_deref(),
+            Some("the Stockfish developers (see AUTHORS file)")
+        );
+        assert_eq!(info.options.len(), 5);
+
+        let log = info.option("debug log file").expect("Missing option");
+        assert_eq!(log.kind, OptionKind::String);
+        assert_eq!(log.default.as_deref(), Some(""));
+
+        let threads = info.option("Threads").expect("Missing option");
+        assert_eq!(threads.kind, OptionKind::Spin);
+        assert_eq!(threads.default.as_deref(), Some("1"));
+        assert_eq!((threads.min, threads.max), (Some(1), Some(1024)));
+
+        let clear = info.option("Clear Hash").expect("Missing option");
+        assert_eq!(clear.kind, OptionKind::Button);
+        assert_eq!(clear.default, None);
+
+        let style = info.option("Style").expect("Missing option");
+        assert_eq!(style.vars, vec!["Solid", "Normal", "Risky"]);
+    }
+}
@@ -0,0 +1,427 @@
+//! A scriptable fake UCI engine for testing.
+//!
+//! The mock speaks enough UCI, or XBoard if the first command is `xboard`, to
+//! play games and can be told to misbehave in the ways real engines do:
+//! illegal moves, hangs, crashes, slow replies and garbage output. It runs
+//! over any reader/writer pair, so tests can use it in-process over a duplex
+//! pipe or as the `mock-uci-engine` binary.
+
+use super::Divide;
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess, Color, Position, Role};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+
+/// How the mock engine behaves when asked to search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockBehaviour {
+    /// Play random legal moves from a seeded generator
+    Random(u64),
+    /// Play the given UCI moves in order, one per ply counted by move
+    /// number, then random moves. XBoard replies pass SAN moves on as given
+    /// to test engines that send them
+    Script(Vec<String>),
+    /// Answer every search with an illegal move
+    IllegalMove,
+    /// Answer every search with `bestmove (none)`
+    NoMove,
+    /// Never answer a search
+    Hang,
+    /// Exit once the game reaches this ply, counted by move number
+    Crash(usize),
+    /// Play random legal moves, but only after sleeping this many milliseconds
+    Slow(u64),
+    /// Answer every search with lines that aren't valid UCI
+    Malformed,
+    /// Play random legal moves, but count one node too many below the last
+    /// move of a `go perft`
+    MiscountPerft,
+    /// Play random legal moves, but score positions by the material the side
+    /// to move can have after its best capture
+    Material,
+    /// Play random legal moves, and follow every XBoard move with this
+    /// result line, as engines claiming a draw or a mate do
+    Claim(String),
+}
+
+impl FromStr for MockBehaviour {
+    type Err = String;
+
+    /// Parse a behaviour such as `random`, `random:7`, `script:f2f3,e7e5`,
+    /// `illegal`, `none`, `hang`, `crash:10`, `slow:250`, `malformed`,
+    /// `miscount`, `material` or `claim:1/2-1/2 {Draw by repetition}`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+        let number = |default: u64| {
+            if arg.is_empty() {
+                Ok(default)
+            } else {
+                arg.parse::<u64>()
+                    .map_err(|e| format!("Invalid argument '{arg}' for {kind}: {e}"))
+            }
+        };
+        Ok(match kind {
+            "random" => Self::Random(number(0)?),
+            "script" => Self::Script(arg.split(',').map(str::to_string).collect()),
+            "illegal" => Self::IllegalMove,
+            "none" => Self::NoMove,
+            "hang" => Self::Hang,
+            "crash" => Self::Crash(usize::try_from(number(0)?).map_err(|e| e.to_string())?),
+            "slow" => Self::Slow(number(1000)?),
+            "malformed" => Self::Malformed,
+            "miscount" => Self::MiscountPerft,
+            "material" => Self::Material,
+            "claim" => Self::Claim(arg.to_string()),
+            _ => return Err(format!("Unknown mock behaviour '{s}'")),
+        })
+    }
+}
+
+/// A small deterministic generator, so the mock needs no RNG dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Play a move given in UCI notation.
+fn play_uci(position: &Chess, uci: &str) -> Option<Chess> {
+    let m = uci.parse::<UciMove>().ok()?.to_move(position).ok()?;
+    position.clone().play(&m).ok()
+}
+
+/// Play a move given in SAN.
+fn play_san(position: &Chess, san: &str) -> Option<Chess> {
+    let m = san.parse::<SanPlus>().ok()?.san.to_move(position).ok()?;
+    position.clone().play(&m).ok()
+}
+
+/// The ply of `position` counted from the start of a game, by its move number.
+fn ply_of(position: &Chess) -> usize {
+    let fullmoves = usize::try_from(position.fullmoves().get()).unwrap_or_default();
+    fullmoves.saturating_sub(1) * 2 + usize::from(position.turn() == Color::Black)
+}
+
+/// Parse a `position` command into the position and its ply.
+///
+/// Plies are counted by move number rather than from the position sent, as
+/// that may be part way through the game.
+fn parse_position(args: &str) -> Option<(Chess, usize)> {
+    let (setup, moves) = args.split_once(" moves ").unwrap_or((args, ""));
+    let mut position = if setup.trim() == "startpos" {
+        Chess::default()
+    } else {
+        let fen: Fen = setup.trim().strip_prefix("fen ")?.parse().ok()?;
+        fen.into_position(CastlingMode::Standard).ok()?
+    };
+    for uci in moves.split_whitespace() {
+        position = play_uci(&position, uci)?;
+    }
+    let plies = ply_of(&position);
+    Some((position, plies))
+}
+
+/// Pick a random legal move, or `(none)` if there are none.
+fn random_move(position: &Chess, rng: &mut SplitMix64) -> String {
+    let moves = position.legal_moves();
+    if moves.is_empty() {
+        return "(none)".to_string();
+    }
+    let index = usize::try_from(rng.next() % moves.len() as u64).unwrap_or_default();
+    moves[index].to_uci(CastlingMode::Standard).to_string()
+}
+
+/// Choose the move to answer a search with.
+async fn choose_move(
+    behaviour: &MockBehaviour,
+    position: &Chess,
+    plies: usize,
+    rng: &mut SplitMix64,
+) -> String {
+    match behaviour {
+        MockBehaviour::Script(script) => script
+            .get(plies)
+            .cloned()
+            .unwrap_or_else(|| random_move(position, rng)),
+        MockBehaviour::IllegalMove => "a1a1".to_string(),
+        MockBehaviour::NoMove => "(none)".to_string(),
+        MockBehaviour::Slow(delay_ms) => {
+            tokio::time::sleep(Duration::from_millis(*delay_ms)).await;
+            random_move(position, rng)
+        }
+        _ => random_move(position, rng),
+    }
+}
+
+/// The reply to `best_move` the engine expects, to ponder on: the next
+/// scripted move, or else the first legal one.
+fn ponder_move(
+    behaviour: &MockBehaviour,
+    position: &Chess,
+    plies: usize,
+    best_move: &str,
+) -> Option<String> {
+    let after = play_uci(position, best_move)?;
+    if let MockBehaviour::Script(script) = behaviour {
+        if let Some(reply) = script.get(plies + 1) {
+            return Some(reply.clone());
+        }
+    }
+    let reply = after.legal_moves().first()?.to_uci(CastlingMode::Standard);
+    Some(reply.to_string())
+}
+
+/// Material balance in centipawns from `color`'s point of view.
+fn material(position: &Chess, color: Color) -> i32 {
+    let board = position.board();
+    let side = |color: Color| -> i32 {
+        [
+            (Role::Pawn, 100),
+            (Role::Knight, 300),
+            (Role::Bishop, 300),
+            (Role::Rook, 500),
+            (Role::Queen, 900),
+        ]
+        .into_iter()
+        .map(|(role, value)| {
+            let count = board.by_piece(role.of(color)).count();
+            value * i32::try_from(count).unwrap_or_default()
+        })
+        .sum()
+    };
+    side(color) - side(!color)
+}
+
+/// The score the mock reports: zero, or for [`MockBehaviour::Material`] the
+/// material after the side to move's best move, which sees hanging pieces.
+fn score(behaviour: &MockBehaviour, position: &Chess) -> i32 {
+    if *behaviour != MockBehaviour::Material {
+        return 0;
+    }
+    let turn = position.turn();
+    position
+        .legal_moves()
+        .iter()
+        .map(|m| {
+            let mut after = position.clone();
+            after.play_unchecked(m);
+            material(&after, turn)
+        })
+        .max()
+        .unwrap_or_else(|| material(position, turn))
+}
+
+/// One `info` line per line of play with `MultiPV` set to `multipv`: the
+/// chosen move first, then the other legal moves in generation order, all
+/// with the same score.
+fn multipv_lines(position: &Chess, best_move: &str, multipv: usize, score: i32) -> String {
+    let moves = position.legal_moves();
+    let others = moves
+        .iter()
+        .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+        .filter(|uci| uci != best_move);
+    std::iter::once(best_move.to_string())
+        .chain(others)
+        .take(multipv)
+        .enumerate()
+        .map(|(index, uci)| {
+            format!(
+                "info depth 1 multipv {} score cp {score} nodes 20 nps 20000 time 1 pv {uci}",
+                index + 1
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The answer to `go perft`, in Stockfish's format.
+fn perft_response(behaviour: &MockBehaviour, position: &Chess, depth: u32) -> String {
+    let mut divide = Divide::of(position, depth);
+    if *behaviour == MockBehaviour::MiscountPerft {
+        if let Some(mut last) = divide.moves.last_entry() {
+            *last.get_mut() += 1;
+            divide.nodes += 1;
+        }
+    }
+    let mut response: Vec<String> = divide
+        .moves
+        .iter()
+        .map(|(uci, nodes)| format!("{uci}: {nodes}"))
+        .collect();
+    response.push(String::new());
+    response.push(format!("Nodes searched: {}", divide.nodes));
+    response.join("\n")
+}
+
+/// Serve UCI on `reader`/`writer` until `quit`, end of input or a scripted crash.
+///
+/// # Errors
+/// Returns an error if reading commands or writing responses fails.
+pub async fn serve<R, W>(behaviour: MockBehaviour, reader: R, mut writer: W) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let seed = match behaviour {
+        MockBehaviour::Random(seed) => seed,
+        _ => 0,
+    };
+    let mut rng = SplitMix64(seed);
+    let mut position = Chess::default();
+    let mut plies = 0;
+    let mut pondering = false;
+    let mut multipv = 1;
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let response = match command {
+            "uci" => "id name MockEngine 1.0\nid author Test Suite\n\
+                      option name Hash type spin default 16 min 1 max 1024\n\
+                      option name Threads type spin default 1 min 1 max 64\n\
+                      option name MultiPV type spin default 1 min 1 max 500\n\
+                      uciok"
+                .to_string(),
+            "xboard" => return serve_xboard(&behaviour, lines, writer, rng).await,
+            "isready" => "readyok".to_string(),
+            "position" => {
+                if let Some((parsed, played)) = parse_position(args) {
+                    position = parsed;
+                    plies = played;
+                }
+                continue;
+            }
+            // Pondering searches are answered once they are hit or stopped
+            "go" if args.starts_with("ponder") => {
+                pondering = true;
+                continue;
+            }
+            "ponderhit" | "stop" if !std::mem::take(&mut pondering) => continue,
+            "go" if args.starts_with("perft") => {
+                let depth = args
+                    .strip_prefix("perft")
+                    .and_then(|depth| depth.trim().parse().ok())
+                    .unwrap_or(1);
+                perft_response(&behaviour, &position, depth)
+            }
+            "go" | "ponderhit" | "stop" => match &behaviour {
+                MockBehaviour::Hang => continue,
+                MockBehaviour::Crash(at_ply) if plies >= *at_ply => return Ok(()),
+                MockBehaviour::Malformed => "info depth banana score\nbestmove zz99".to_string(),
+                _ => {
+                    let best_move = choose_move(&behaviour, &position, plies, &mut rng).await;
+                    let ponder = ponder_move(&behaviour, &position, plies, &best_move)
+                        .map(|reply| format!(" ponder {reply}"))
+                        .unwrap_or_default();
+                    let score = score(&behaviour, &position);
+                    if multipv > 1 {
+                        let lines = multipv_lines(&position, &best_move, multipv, score);
+                        format!("{lines}\nbestmove {best_move}{ponder}")
+                    } else {
+                        format!(
+                            "info depth 1 score cp {score} nodes 20 nps 20000 time 1 pv {best_move}\nbestmove {best_move}{ponder}"
+                        )
+                    }
+                }
+            },
+            "setoption" => {
+                if let Some(value) = args.strip_prefix("name MultiPV value ") {
+                    multipv = value.trim().parse().unwrap_or(1);
+                }
+                continue;
+            }
+            "quit" => return Ok(()),
+            // ucinewgame, setoption, ... need no reply
+            _ => continue,
+        };
+        writer.write_all(response.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+/// Serve XBoard on `lines`/`writer` until `quit`, end of input or a scripted
+/// crash.
+async fn serve_xboard<R, W>(
+    behaviour: &MockBehaviour,
+    mut lines: Lines<BufReader<R>>,
+    mut writer: W,
+    mut rng: SplitMix64,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut position = Chess::default();
+    let mut plies = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let response = match command {
+            "protover" => "feature myname=\"MockEngine 1.0\" setboard=1 usermove=1 ping=1 \
+                           memory=1 smp=1 option=\"Contempt -spin 0 -100 100\" done=1"
+                .to_string(),
+            "ping" => format!("pong {args}"),
+            "new" => {
+                position = Chess::default();
+                plies = 0;
+                continue;
+            }
+            "setboard" => {
+                if let Some((parsed, played)) = parse_position(&format!("fen {args}")) {
+                    position = parsed;
+                    plies = played;
+                }
+                continue;
+            }
+            "usermove" => match play_uci(&position, args) {
+                Some(after) => {
+                    position = after;
+                    plies += 1;
+                    continue;
+                }
+                None => format!("Illegal move: {args}"),
+            },
+            "go" => match behaviour {
+                MockBehaviour::Hang => continue,
+                MockBehaviour::Crash(at_ply) if plies >= *at_ply => return Ok(()),
+                MockBehaviour::Malformed => "1 banana\nmove zz99".to_string(),
+                MockBehaviour::NoMove => "resign".to_string(),
+                _ => {
+                    let best_move = choose_move(behaviour, &position, plies, &mut rng).await;
+                    if let Some(after) =
+                        play_uci(&position, &best_move).or_else(|| play_san(&position, &best_move))
+                    {
+                        position = after;
+                        plies += 1;
+                    }
+                    let reply = format!("1 0 0 20 {best_move}\nmove {best_move}");
+                    match behaviour {
+                        MockBehaviour::Claim(result) => format!("{reply}\n{result}"),
+                        _ => reply,
+                    }
+                }
+            },
+            "quit" => return Ok(()),
+            // force, post, easy, st, level, time, otim, memory, cores, option,
+            // accepted, ... need no reply
+            _ => continue,
+        };
+        writer.write_all(response.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
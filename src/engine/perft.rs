@@ -0,0 +1,87 @@
+//! Leaf node counts below each move, as `go perft` reports them.
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use shakmaty::{CastlingMode, Chess, Position};
+use std::collections::BTreeMap;
+
+/// Leaf nodes below each legal move of a position.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Divide {
+    /// Nodes below each move, by the move in UCI notation
+    pub moves: BTreeMap<String, u64>,
+    /// Nodes in total
+    pub nodes: u64,
+}
+
+impl Divide {
+    /// The divide of `position` to `depth` plies, by shakmaty's move generator.
+    #[must_use]
+    pub fn of(position: &Chess, depth: u32) -> Self {
+        if depth == 0 {
+            return Self {
+                moves: BTreeMap::new(),
+                nodes: 1,
+            };
+        }
+        let moves: BTreeMap<String, u64> = position
+            .legal_moves()
+            .into_iter()
+            .map(|m| {
+                let mut child = position.clone();
+                child.play_unchecked(&m);
+                let uci = m.to_uci(CastlingMode::Standard).to_string();
+                (uci, shakmaty::perft(&child, depth - 1))
+            })
+            .collect();
+        Self {
+            nodes: moves.values().sum(),
+            moves,
+        }
+    }
+
+    /// Add a line of an engine's `go perft` output, such as `e2e4: 20`,
+    /// returning whether it was the `Nodes searched` total that ends it.
+    ///
+    /// # Errors
+    /// Returns an error if the line is not a count.
+    pub fn read_line(&mut self, line: &str) -> Result<bool> {
+        let (key, count) = line
+            .split_once(':')
+            .ok_or_else(|| eyre!("Unexpected perft line '{line}'"))?;
+        let count: u64 = count
+            .trim()
+            .parse()
+            .wrap_err_with(|| format!("Invalid perft count in '{line}'"))?;
+        if key.trim() == "Nodes searched" {
+            self.nodes = count;
+            return Ok(true);
+        }
+        self.moves.insert(key.trim().to_string(), count);
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divide_of_start_position() {
+        let divide = Divide::of(&Chess::default(), 3);
+        assert_eq!(divide.nodes, 8902);
+        assert_eq!(divide.moves.len(), 20);
+        assert_eq!(divide.moves["e2e4"], 600);
+        assert_eq!(Divide::of(&Chess::default(), 0).nodes, 1);
+    }
+
+    #[test]
+    fn test_read_perft_lines() {
+        let mut divide = Divide::default();
+        assert!(!divide.read_line("e2e4: 20").expect("Valid line"));
+        assert!(divide.read_line("Nodes searched: 20").expect("Valid line"));
+        assert_eq!(divide.moves["e2e4"], 20);
+        assert_eq!(divide.nodes, 20);
+        assert!(divide.read_line("e2e4 20").is_err());
+        assert!(divide.read_line("e2e4: many").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Search results reported by an engine.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// An engine evaluation, from the point of view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Score {
+    /// Evaluation in centipawns
+    Cp(i32),
+    /// Mate in the given number of moves (negative if the side to move is mated)
+    Mate(i32),
+}
+
+impl fmt::Display for Score {
+    /// Pawns with a sign, e.g. `+0.35`, or moves to mate, e.g. `-M2`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Cp(cp) => write!(f, "{:+.2}", f64::from(cp) / 100.0),
+            Self::Mate(mate) if mate < 0 => write!(f, "-M{}", -mate),
+            Self::Mate(mate) => write!(f, "+M{mate}"),
+        }
+    }
+}
+
+/// How long an engine may search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchLimits {
+    /// A fixed time in milliseconds
+    Movetime(u64),
+    /// A fixed depth in plies, however long it takes
+    Depth(u32),
+    /// A fixed number of nodes, however long it takes
+    Nodes(u64),
+    /// Both clocks and increments in milliseconds, for the engine to manage
+    Clock {
+        /// White's remaining time
+        wtime_ms: u64,
+        /// Black's remaining time
+        btime_ms: u64,
+        /// White's increment per move
+        winc_ms: u64,
+        /// Black's increment per move
+        binc_ms: u64,
+    },
+}
+
+impl SearchLimits {
+    /// The `go` command for these limits.
+    pub(crate) fn go_command(&self) -> String {
+        match self {
+            Self::Movetime(movetime_ms) => format!("go movetime {movetime_ms}"),
+            Self::Depth(depth) => format!("go depth {depth}"),
+            Self::Nodes(nodes) => format!("go nodes {nodes}"),
+            Self::Clock {
+                wtime_ms,
+                btime_ms,
+                winc_ms,
+                binc_ms,
+            } => format!("go wtime {wtime_ms} btime {btime_ms} winc {winc_ms} binc {binc_ms}"),
+        }
+    }
+}
+
+/// One of the lines an engine searches with `MultiPV`, as last reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PvLine {
+    /// The line's rank, 1 for the best
+    pub multipv: u32,
+    /// The line's score
+    pub score: Option<Score>,
+    /// The depth it was reported at
+    pub depth: Option<u32>,
+    /// The moves of the line
+    pub pv: Vec<String>,
+}
+
+/// The outcome of a single `go` command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The move the engine chose, in UCI notation
+    pub best_move: String,
+    /// The reply the engine expects, if it reported one
+    pub ponder: Option<String>,
+    /// The last score reported before `bestmove` for the best line
+    pub score: Option<Score>,
+    /// The last completed search depth
+    pub depth: Option<u32>,
+    /// Nodes searched
+    pub nodes: Option<u64>,
+    /// Search speed in nodes per second
+    pub nps: Option<u64>,
+    /// How long the engine says it searched, in milliseconds
+    pub time_ms: Option<u64>,
+    /// The last principal variation reported for the best line
+    pub pv: Vec<String>,
+    /// Every line the engine numbered with `multipv`, best first
+    pub lines: Vec<PvLine>,
+}
+
+impl SearchResult {
+    /// Update the result from the fields of a UCI `info` line.
+    pub(crate) fn update_from_info(&mut self, info: &str) {
+        let mut multipv = None;
+        let mut score = None;
+        let mut pv = None;
+        let mut tokens = info.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => self.depth = tokens.next().and_then(|t| t.parse().ok()),
+                "nodes" => self.nodes = tokens.next().and_then(|t| t.parse().ok()),
+                "nps" => self.nps = tokens.next().and_then(|t| t.parse().ok()),
+                "time" => self.time_ms = tokens.next().and_then(|t| t.parse().ok()),
+                "multipv" => multipv = tokens.next().and_then(|t| t.parse::<u32>().ok()),
+                "score" => {
+                    let kind = tokens.next();
+                    let value = tokens.next().and_then(|t| t.parse().ok());
+                    score = match (kind, value) {
+                        (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                        (Some("mate"), Some(mate)) => Some(Score::Mate(mate)),
+                        _ => score,
+                    };
+                }
+                // The PV runs to the end of the line
+                "pv" => {
+                    pv = Some(tokens.by_ref().map(str::to_string).collect::<Vec<_>>());
+                }
+                // Free text runs to the end of the line and may contain keywords
+                "string" => break,
+                _ => {}
+            }
+        }
+
+        // The other lines say nothing about the move the engine will play
+        if multipv.unwrap_or(1) == 1 {
+            self.score = score.or(self.score);
+            if let Some(pv) = &pv {
+                self.pv.clone_from(pv);
+            }
+        }
+        let Some(multipv) = multipv.filter(|_| score.is_some() || pv.is_some()) else {
+            return;
+        };
+        let index = match self
+            .lines
+            .binary_search_by_key(&multipv, |line| line.multipv)
+        {
+            Ok(index) => index,
+            Err(index) => {
+                let line = PvLine {
+                    multipv,
+                    ..PvLine::default()
+                };
+                self.lines.insert(index, line);
+                index
+            }
+        };
+        let line = &mut self.lines[index];
+        line.depth = self.depth;
+        line.score = score.or(line.score);
+        if let Some(pv) = pv {
+            line.pv = pv;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_from_info() {
+        let mut result = SearchResult::default();
+        result.update_from_info(
+            "depth 12 seldepth 18 multipv 1 score cp 31 nodes 123456 nps 987654 time 125 pv e2e4 e7e5 g1f3",
+        );
+        assert_eq!(result.depth, Some(12));
+        assert_eq!(result.score, Some(Score::Cp(31)));
+        assert_eq!(result.nodes, Some(123_456));
+        assert_eq!(result.nps, Some(987_654));
+        assert_eq!(result.time_ms, Some(125));
+        assert_eq!(result.pv, vec!["e2e4", "e7e5", "g1f3"]);
+
+        result.update_from_info("depth 13 score mate -3 pv a7a6");
+        assert_eq!(result.score, Some(Score::Mate(-3)));
+        assert_eq!(result.pv, vec!["a7a6"]);
+
+        result.update_from_info("string depth 99 score cp 0");
+        assert_eq!(result.depth, Some(13));
+    }
+
+    #[test]
+    fn test_update_from_multipv_info() {
+        let mut result = SearchResult::default();
+        for info in [
+            "depth 10 multipv 1 score cp 30 pv e2e4 e7e5",
+            "depth 10 multipv 2 score cp 25 pv d2d4 d7d5",
+            "depth 11 multipv 2 score cp 20 pv g1f3 d7d5",
+            "depth 11 multipv 1 score cp 28 pv e2e4 c7c5",
+            "depth 11 currmove b1c3 currmovenumber 3",
+        ] {
+            result.update_from_info(info);
+        }
+        assert_eq!(result.score, Some(Score::Cp(28)));
+        assert_eq!(result.pv, vec!["e2e4", "c7c5"]);
+        assert_eq!(
+            result.lines,
+            vec![
+                PvLine {
+                    multipv: 1,
+                    score: Some(Score::Cp(28)),
+                    depth: Some(11),
+                    pv: vec!["e2e4".to_string(), "c7c5".to_string()],
+                },
+                PvLine {
+                    multipv: 2,
+                    score: Some(Score::Cp(20)),
+                    depth: Some(11),
+                    pv: vec!["g1f3".to_string(), "d7d5".to_string()],
+                },
+            ]
+        );
+    }
+}
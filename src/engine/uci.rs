@@ -1,16 +1,87 @@
 //! UCI protocol implementation for chess engine communication.
 
+use super::connection::Connection;
+use super::{
+    Divide, Engine, EngineConfig, EngineInfo, OptionValue, SearchLimits, SearchResult, Transcript,
+};
 use color_eyre::eyre::{ContextCompat, Result, eyre};
-use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess, EnPassantMode, Position};
+use tokio::io::{AsyncRead, AsyncWrite};
 
-/// A UCI chess engine process.
+/// The last position sent to an engine.
+///
+/// Positions before the last capture or pawn move can never repeat, so the
+/// engine only needs the moves since then. Starting `position` from there
+/// keeps each command short however long the game gets.
+struct SentPosition {
+    start_fen: Option<String>,
+    moves: Vec<String>,
+    position: Chess,
+    /// FEN after the last capture or pawn move, and how many moves led to it
+    base: Option<(String, usize)>,
+}
+
+impl SentPosition {
+    fn new(start_fen: Option<&str>) -> Option<Self> {
+        let position = match start_fen {
+            Some(fen) => fen
+                .parse::<Fen>()
+                .ok()?
+                .into_position(CastlingMode::Standard)
+                .ok()?,
+            None => Chess::default(),
+        };
+        Some(Self {
+            start_fen: start_fen.map(str::to_string),
+            moves: Vec::new(),
+            position,
+            base: None,
+        })
+    }
+
+    /// Play `uci`, or return `None` if it is not a legal move here.
+    fn play(&mut self, uci: &str) -> Option<()> {
+        let m = uci.parse::<UciMove>().ok()?.to_move(&self.position).ok()?;
+        self.position = self.position.clone().play(&m).ok()?;
+        self.moves.push(uci.to_string());
+        if m.is_zeroing() {
+            let fen = Fen::from_position(self.position.clone(), EnPassantMode::Legal);
+            self.base = Some((fen.to_string(), self.moves.len()));
+        }
+        Some(())
+    }
+
+    /// The `position` command for the moves played so far.
+    fn command(&self) -> String {
+        match &self.base {
+            Some((fen, played)) => position_command(Some(fen), &self.moves[*played..]),
+            None => position_command(self.start_fen.as_deref(), &self.moves),
+        }
+    }
+}
+
+/// The `position` command for `moves` from `start_fen`, or from the standard
+/// starting position if there is none.
+fn position_command(start_fen: Option<&str>, moves: &[String]) -> String {
+    let mut command = start_fen.map_or_else(
+        || "position startpos".to_string(),
+        |fen| format!("position fen {fen}"),
+    );
+    if !moves.is_empty() {
+        command.push_str(" moves ");
+        command.push_str(&moves.join(" "));
+    }
+    command
+}
+
+/// A UCI chess engine, usually a child process.
 pub struct UciEngine {
-    name: String,
-    _process: Child,
-    stdin: ChildStdin,
-    stdout: BufReader<ChildStdout>,
+    io: Connection,
+    info: EngineInfo,
+    /// What the engine was last told, if the moves were all legal
+    sent: Option<SentPosition>,
 }
 
 impl UciEngine {
@@ -19,115 +90,355 @@ impl UciEngine {
     /// # Errors
     /// Returns an error if the engine cannot be spawned or doesn't respond to UCI.
     pub async fn new(path: &str, name: &str) -> Result<Self> {
-        let mut process = Command::new(path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?;
+        Self::init(Connection::spawn(path, name)?).await
+    }
 
-        let stdin = process.stdin.take().context("Failed to get stdin")?;
-        let stdout = process.stdout.take().context("Failed to get stdout")?;
-        let stdout = BufReader::new(stdout);
+    /// Spawn the engine described by `config` and set its options, with
+    /// `MultiPV` checked as [`set_multipv`](Self::set_multipv) does.
+    ///
+    /// # Errors
+    /// Returns an error if the engine cannot be spawned, doesn't respond to
+    /// UCI or can't report the lines asked for.
+    pub async fn start(config: &EngineConfig, name: &str) -> Result<Self> {
+        let mut engine = Self::new(&config.path, name).await?;
+        for (option, value) in &config.options {
+            match value {
+                OptionValue::Int(lines) if option.eq_ignore_ascii_case("MultiPV") => {
+                    let lines = u32::try_from(*lines)
+                        .map_err(|_| eyre!("Invalid MultiPV {lines} for {name}"))?;
+                    engine.set_multipv(lines).await?;
+                }
+                _ => engine.set_option(option, &value.to_string()).await?,
+            }
+        }
+        Ok(engine)
+    }
+
+    /// Talk UCI to an engine over an arbitrary reader/writer pair, such as an
+    /// in-process mock.
+    ///
+    /// # Errors
+    /// Returns an error if the engine doesn't respond to UCI.
+    pub async fn from_io(
+        name: &str,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Result<Self> {
+        Self::init(Connection::from_io(name, reader, writer)).await
+    }
 
+    async fn init(io: Connection) -> Result<Self> {
         let mut engine = Self {
-            name: name.to_string(),
-            _process: process,
-            stdin,
-            stdout,
+            io,
+            info: EngineInfo::default(),
+            sent: None,
         };
 
-        // Initialize UCI protocol
-        engine.send("uci").await?;
-        engine.wait_for("uciok").await?;
+        // Initialize UCI protocol, picking up the engine's identity on the way
+        engine.io.send("uci").await?;
+        loop {
+            let line = engine.io.read_line().await?;
+            if line == "uciok" {
+                break;
+            }
+            engine.info.parse_line(&line);
+        }
+        tracing::debug!(
+            engine = %engine.io.name(),
+            id_name = engine.info.name.as_deref().unwrap_or("?"),
+            options = engine.info.options.len(),
+            "Engine started"
+        );
 
         Ok(engine)
     }
 
-    /// Send a command to the engine.
-    async fn send(&mut self, command: &str) -> Result<()> {
-        tracing::trace!(engine = %self.name, %command, "Sending command");
-        self.stdin.write_all(command.as_bytes()).await?;
-        self.stdin.write_all(b"\n").await?;
-        self.stdin.flush().await?;
-        Ok(())
+    /// The name the engine reported with `id name`, if any.
+    #[must_use]
+    pub fn id_name(&self) -> Option<&str> {
+        self.info.name.as_deref()
     }
 
-    /// Wait for a specific response line from the engine.
-    async fn wait_for(&mut self, expected: &str) -> Result<()> {
-        let mut line = String::new();
-        loop {
-            line.clear();
-            let bytes_read = self.stdout.read_line(&mut line).await?;
-            if bytes_read == 0 {
-                return Err(eyre!("Engine {} closed unexpectedly", self.name));
-            }
-            let trimmed = line.trim();
-            tracing::trace!(engine = %self.name, response = %trimmed, "Received");
-            if trimmed == expected {
-                return Ok(());
-            }
-        }
+    /// The author the engine reported with `id author`, if any.
+    #[must_use]
+    pub fn id_author(&self) -> Option<&str> {
+        self.info.author.as_deref()
     }
 
-    /// Tell the engine we're ready to start a new game.
+    /// Have the engine search its `lines` best moves rather than one, each
+    /// reported in [`SearchResult::lines`].
     ///
     /// # Errors
-    /// Returns an error if the engine doesn't respond.
-    pub async fn new_game(&mut self) -> Result<()> {
-        self.send("ucinewgame").await?;
-        self.send("isready").await?;
-        self.wait_for("readyok").await
+    /// Returns an error if the engine has no `MultiPV` option, or its limits
+    /// don't allow `lines`.
+    pub async fn set_multipv(&mut self, lines: u32) -> Result<()> {
+        let option = self
+            .info
+            .option("MultiPV")
+            .ok_or_else(|| eyre!("{} has no MultiPV option", self.io.name()))?;
+        let (min, max) = (option.min.unwrap_or(1), option.max.unwrap_or(i64::MAX));
+        if !(min..=max).contains(&i64::from(lines)) {
+            return Err(eyre!(
+                "{} allows MultiPV from {min} to {max}, not {lines}",
+                self.io.name()
+            ));
+        }
+        self.set_option("MultiPV", &lines.to_string()).await
     }
 
-    /// Set the position using a list of UCI moves from the starting position.
+    /// Count the leaf nodes below each legal move of `fen` to `depth` plies
+    /// with `go perft`, an extension Stockfish and Reckless support.
     ///
     /// # Errors
-    /// Returns an error if sending the command fails.
-    pub async fn set_position(&mut self, moves: &[String]) -> Result<()> {
-        let command = if moves.is_empty() {
-            "position startpos".to_string()
-        } else {
-            format!("position startpos moves {}", moves.join(" "))
-        };
-        self.send(&command).await
+    /// Returns an error if the engine fails or answers with something other
+    /// than a divide.
+    pub async fn perft(&mut self, fen: &str, depth: u32) -> Result<Divide> {
+        self.set_position_from(Some(fen), &[]).await?;
+        self.io.send(&format!("go perft {depth}")).await?;
+        let mut divide = Divide::default();
+        loop {
+            let line = self.io.read_line().await?;
+            if line.is_empty() || line.starts_with("info ") {
+                continue;
+            }
+            if divide.read_line(&line)? {
+                return Ok(divide);
+            }
+        }
     }
 
-    /// Get the best move from the engine with a time limit.
-    ///
-    /// # Errors
-    /// Returns an error if the engine fails to respond or returns an invalid move.
-    pub async fn get_best_move(&mut self, movetime_ms: u64) -> Result<String> {
-        self.send(&format!("go movetime {movetime_ms}")).await?;
-
-        let mut line = String::new();
+    /// Read `info` lines until `bestmove`, passing the report so far to
+    /// `on_info` after each one.
+    async fn read_search(
+        &mut self,
+        mut on_info: impl FnMut(&SearchResult) + Send,
+    ) -> Result<SearchResult> {
+        let mut result = SearchResult::default();
         loop {
-            line.clear();
-            let bytes_read = self.stdout.read_line(&mut line).await?;
-            if bytes_read == 0 {
-                return Err(eyre!("Engine {} closed unexpectedly", self.name));
-            }
-            let trimmed = line.trim();
-            tracing::trace!(engine = %self.name, response = %trimmed, "Received");
+            let line = self.io.read_line().await?;
 
-            if let Some(rest) = trimmed.strip_prefix("bestmove ") {
+            if let Some(info) = line.strip_prefix("info ") {
+                result.update_from_info(info);
+                on_info(&result);
+            } else if let Some(rest) = line.strip_prefix("bestmove ") {
                 // bestmove format: "bestmove e2e4" or "bestmove e2e4 ponder d7d5"
-                let best_move = rest.split_whitespace().next().context("Empty bestmove")?;
-                return Ok(best_move.to_string());
+                let mut tokens = rest.split_whitespace();
+                let best_move = tokens.next().context("Empty bestmove")?;
+                result.best_move = best_move.to_string();
+                if tokens.next() == Some("ponder") {
+                    result.ponder = tokens.next().map(str::to_string);
+                }
+                return Ok(result);
             }
         }
     }
+}
+
+impl Engine for UciEngine {
+    fn info(&self) -> &EngineInfo {
+        &self.info
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.io.pid()
+    }
 
-    /// Quit the engine gracefully.
-    pub async fn quit(&mut self) -> Result<()> {
-        self.send("quit").await
+    fn set_transcript(&mut self, transcript: Transcript) {
+        self.io.set_transcript(transcript);
+    }
+
+    async fn set_option(&mut self, name: &str, value: &str) -> Result<()> {
+        self.io
+            .send(&format!("setoption name {name} value {value}"))
+            .await
+    }
+
+    async fn ready(&mut self) -> Result<()> {
+        self.io.send("isready").await?;
+        self.io.wait_for("readyok").await
+    }
+
+    async fn new_game(&mut self) -> Result<()> {
+        self.io.send("ucinewgame").await?;
+        self.ready().await
+    }
+
+    /// Only the moves since the last capture or pawn move are sent.
+    async fn set_position_from(&mut self, start_fen: Option<&str>, moves: &[String]) -> Result<()> {
+        let mut sent = SentPosition::new(start_fen);
+        let legal = sent
+            .as_mut()
+            .is_some_and(|sent| moves.iter().all(|uci| sent.play(uci).is_some()));
+        self.sent = sent.filter(|_| legal);
+        // Leave it to the engine to complain about an illegal position
+        let command = self
+            .sent
+            .as_ref()
+            .map_or_else(|| position_command(start_fen, moves), SentPosition::command);
+        self.io.send(&command).await
+    }
+
+    /// Only the moves pushed are checked, against the position sent last.
+    async fn push_moves(&mut self, moves: &[String]) -> Result<()> {
+        let mut sent = self
+            .sent
+            .take()
+            .context("No legal position to play moves from")?;
+        for uci in moves {
+            sent.play(uci)
+                .with_context(|| format!("Illegal move '{uci}'"))?;
+        }
+        let command = self.sent.insert(sent).command();
+        self.io.send(&command).await
+    }
+
+    async fn search_with(
+        &mut self,
+        limits: &SearchLimits,
+        on_info: impl FnMut(&SearchResult) + Send,
+    ) -> Result<SearchResult> {
+        self.io.send(&limits.go_command()).await?;
+        self.read_search(on_info).await
+    }
+
+    fn can_ponder(&self) -> bool {
+        true
+    }
+
+    async fn go_ponder(&mut self, limits: &SearchLimits) -> Result<()> {
+        let go = limits.go_command();
+        let limits = go.strip_prefix("go").unwrap_or(&go);
+        self.io.send(&format!("go ponder{limits}")).await
+    }
+
+    async fn ponder_hit(&mut self) -> Result<SearchResult> {
+        self.io.send("ponderhit").await?;
+        self.read_search(|_| {}).await
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.io.send("stop").await?;
+        self.read_search(|_| {}).await.map(drop)
+    }
+
+    async fn quit(&mut self) -> Result<()> {
+        self.io.quit("quit").await
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::mock::MockBehaviour;
+    use crate::engine::{Score, spawn_mock};
 
     #[tokio::test]
+    async fn test_mock_uci_init() {
+        let mut engine = spawn_mock("mock", MockBehaviour::Random(0))
+            .await
+            .expect("Failed to init mock");
+        assert_eq!(engine.id_name(), Some("MockEngine 1.0"));
+        assert_eq!(engine.id_author(), Some("Test Suite"));
+        let hash = engine.info().option("Hash").expect("Missing Hash option");
+        assert_eq!((hash.min, hash.max), (Some(1), Some(1024)));
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    #[tokio::test]
+    async fn test_mock_get_move() {
+        let mut engine = spawn_mock("mock", MockBehaviour::Random(0))
+            .await
+            .expect("Failed to init mock");
+        engine.new_game().await.expect("Failed new_game");
+        engine
+            .set_position(&["e2e4".to_string()])
+            .await
+            .expect("Failed set_position");
+        let search = engine.get_best_move(10).await.expect("Failed to get move");
+        assert!(search.best_move.len() >= 4 && search.best_move.len() <= 5);
+        assert_eq!(search.score, Some(Score::Cp(0)));
+        assert_eq!(search.depth, Some(1));
+        assert_eq!(search.nps, Some(20_000));
+        assert_eq!(search.pv, vec![search.best_move.clone()]);
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    #[tokio::test]
+    async fn test_mock_multipv_lines() {
+        let mut engine = spawn_mock("mock", MockBehaviour::Random(0))
+            .await
+            .expect("Failed to init mock");
+        assert!(engine.set_multipv(501).await.is_err());
+        engine.set_multipv(3).await.expect("Failed to set MultiPV");
+        engine.set_position(&[]).await.expect("Failed set_position");
+        let search = engine.get_best_move(10).await.expect("Failed to get move");
+        let ranks: Vec<u32> = search.lines.iter().map(|line| line.multipv).collect();
+        assert_eq!(ranks, vec![1, 2, 3]);
+        assert_eq!(search.lines[0].pv, vec![search.best_move.clone()]);
+        assert_eq!(search.pv, vec![search.best_move.clone()]);
+        assert!(search.lines[1].pv[0] != search.lines[2].pv[0]);
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    #[test]
+    fn test_position_starts_after_last_zeroing_move() {
+        let mut sent = SentPosition::new(None).expect("Failed to set up position");
+        for uci in ["g1f3", "g8f6"] {
+            sent.play(uci).expect("Illegal move");
+        }
+        assert_eq!(sent.command(), "position startpos moves g1f3 g8f6");
+
+        // 2. e4 Nxe4 3. Ng1 Nf6
+        for uci in ["e2e4", "f6e4", "f3g1", "e4f6"] {
+            sent.play(uci).expect("Illegal move");
+        }
+        assert_eq!(
+            sent.command(),
+            "position fen rnbqkb1r/pppppppp/8/8/4n3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3 moves f3g1 e4f6"
+        );
+        assert!(sent.play("e1e3").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_push_moves_follows_on_from_the_position() {
+        let mut engine = spawn_mock("mock", MockBehaviour::Random(0))
+            .await
+            .expect("Failed to init mock");
+        let e5 = ["e7e5".to_string()];
+        assert!(engine.push_moves(&e5).await.is_err());
+
+        engine
+            .set_position(&["e2e4".to_string()])
+            .await
+            .expect("Failed set_position");
+        engine.push_moves(&e5).await.expect("Failed push_moves");
+        let moves = &engine.sent.as_ref().expect("No position sent").moves;
+        assert_eq!(moves, &["e2e4", "e7e5"]);
+
+        // An illegal move leaves no position to follow on from
+        assert!(engine.push_moves(&e5).await.is_err());
+        assert!(engine.push_moves(&[]).await.is_err());
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    #[tokio::test]
+    async fn test_mock_crash_is_an_error() {
+        let mut engine = spawn_mock("mock", MockBehaviour::Crash(0))
+            .await
+            .expect("Failed to init mock");
+        engine.set_position(&[]).await.expect("Failed set_position");
+        let error = engine
+            .get_best_move(10)
+            .await
+            .expect_err("Crash not detected");
+        assert!(
+            error.to_string().contains("mock closed unexpectedly"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires stockfish on PATH"]
     async fn test_stockfish_uci_init() {
         let mut engine = UciEngine::new("stockfish", "stockfish")
             .await
@@ -136,6 +447,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires reckless on PATH"]
     async fn test_reckless_uci_init() {
         let mut engine = UciEngine::new("reckless", "reckless")
             .await
@@ -144,6 +456,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires stockfish on PATH"]
     async fn test_stockfish_new_game() {
         let mut engine = UciEngine::new("stockfish", "stockfish")
             .await
@@ -153,13 +466,18 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires stockfish on PATH"]
     async fn test_stockfish_get_move() {
         let mut engine = UciEngine::new("stockfish", "stockfish")
             .await
             .expect("Failed to init stockfish");
         engine.new_game().await.expect("Failed new_game");
         engine.set_position(&[]).await.expect("Failed set_position");
-        let best_move = engine.get_best_move(100).await.expect("Failed to get move");
+        let best_move = engine
+            .get_best_move(100)
+            .await
+            .expect("Failed to get move")
+            .best_move;
         assert!(!best_move.is_empty());
         // Valid UCI move format: 4-5 chars like e2e4 or e7e8q
         assert!(best_move.len() >= 4 && best_move.len() <= 5);
@@ -167,13 +485,18 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires reckless on PATH"]
     async fn test_reckless_get_move() {
         let mut engine = UciEngine::new("reckless", "reckless")
             .await
             .expect("Failed to init reckless");
         engine.new_game().await.expect("Failed new_game");
         engine.set_position(&[]).await.expect("Failed set_position");
-        let best_move = engine.get_best_move(100).await.expect("Failed to get move");
+        let best_move = engine
+            .get_best_move(100)
+            .await
+            .expect("Failed to get move")
+            .best_move;
         assert!(!best_move.is_empty());
         assert!(best_move.len() >= 4 && best_move.len() <= 5);
         engine.quit().await.expect("Failed to quit");
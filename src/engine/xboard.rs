@@ -0,0 +1,879 @@
+//! XBoard (CECP version 2) protocol implementation.
+//!
+//! XBoard engines keep their own board and clocks, so unlike UCI the
+//! position is sent move by move and the engine is kept in `force` mode
+//! between searches.
+
+use super::connection::Connection;
+use super::{
+    Engine, EngineConfig, EngineInfo, OptionKind, Score, SearchLimits, SearchResult, Transcript,
+    UciOption,
+};
+use color_eyre::eyre::{ContextCompat, Result, eyre};
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess, Position};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// FEN of the standard starting position, for engines told to `setboard`.
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// What starts a game: the standard position with the engine waiting for
+/// moves, reporting its thinking and not pondering.
+const NEW_GAME: [&str; 4] = ["new", "force", "post", "easy"];
+
+/// How long to wait for `feature` lines before assuming a version 1 engine,
+/// which sends none.
+const FEATURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long an engine that sent `feature done=0` may take to finish starting.
+const FEATURE_DONE_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Mate scores are reported as 100000 plus the number of moves to mate.
+const MATE_SCORE: i32 = 100_000;
+
+/// The protocol features the engine announced that change what we send.
+#[derive(Debug, Default)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "Each is an independent feature flag"
+)]
+struct Features {
+    ping: bool,
+    setboard: bool,
+    usermove: bool,
+    memory: bool,
+    smp: bool,
+}
+
+/// The position the engine has been given.
+struct Board {
+    start_fen: Option<String>,
+    moves: Vec<String>,
+}
+
+impl Board {
+    /// Whether White is to move.
+    fn white_to_move(&self) -> bool {
+        let white_starts = self
+            .start_fen
+            .as_deref()
+            .and_then(|fen| fen.split_whitespace().nth(1))
+            != Some("b");
+        white_starts == (self.moves.len() % 2 == 0)
+    }
+
+    /// The position on the board, or `None` if it cannot be set up.
+    fn position(&self) -> Option<Chess> {
+        let mut position = match &self.start_fen {
+            Some(fen) => fen
+                .parse::<Fen>()
+                .ok()?
+                .into_position(CastlingMode::Standard)
+                .ok()?,
+            None => Chess::default(),
+        };
+        for uci in &self.moves {
+            let m = uci.parse::<UciMove>().ok()?.to_move(&position).ok()?;
+            position.play_unchecked(&m);
+        }
+        Some(position)
+    }
+}
+
+/// An XBoard chess engine, usually a child process.
+pub struct XboardEngine {
+    io: Connection,
+    info: EngineInfo,
+    features: Features,
+    pings: u32,
+    /// The position the engine holds, once `new_game` has been sent
+    board: Option<Board>,
+    /// The increment last sent with `level`
+    level_inc_ms: Option<u64>,
+    /// Whether a depth cap sent with `sd` is still in force
+    depth_capped: bool,
+    /// The move the engine played in its last search, which is already on
+    /// its board when the game is pushed on
+    own_move: Option<String>,
+}
+
+impl XboardEngine {
+    /// Spawn a new XBoard engine process.
+    ///
+    /// # Errors
+    /// Returns an error if the engine cannot be spawned or the handshake fails.
+    pub async fn new(path: &str, name: &str) -> Result<Self> {
+        Self::init(Connection::spawn(path, name)?).await
+    }
+
+    /// Spawn the engine described by `config` and set its options.
+    ///
+    /// # Errors
+    /// Returns an error if the engine cannot be spawned or the handshake fails.
+    pub async fn start(config: &EngineConfig, name: &str) -> Result<Self> {
+        let mut engine = Self::new(&config.path, name).await?;
+        for (option, value) in &config.options {
+            engine.set_option(option, &value.to_string()).await?;
+        }
+        Ok(engine)
+    }
+
+    /// Talk XBoard to an engine over an arbitrary reader/writer pair, such as
+    /// an in-process mock.
+    ///
+    /// # Errors
+    /// Returns an error if the handshake fails.
+    pub async fn from_io(
+        name: &str,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Result<Self> {
+        Self::init(Connection::from_io(name, reader, writer)).await
+    }
+
+    async fn init(io: Connection) -> Result<Self> {
+        let mut engine = Self {
+            io,
+            info: EngineInfo::default(),
+            features: Features::default(),
+            pings: 0,
+            board: None,
+            level_inc_ms: None,
+            depth_capped: false,
+            own_move: None,
+        };
+
+        engine.io.send("xboard").await?;
+        engine.io.send("protover 2").await?;
+        let mut timeout = FEATURE_TIMEOUT;
+        loop {
+            let Ok(line) = tokio::time::timeout(timeout, engine.io.read_line()).await else {
+                tracing::debug!(engine = %engine.io.name(), "No feature done=1, assuming protocol version 1");
+                break;
+            };
+            let line = line?;
+            let Some(features) = line.strip_prefix("feature ") else {
+                continue;
+            };
+            let mut done = None;
+            for (key, value) in parse_features(features) {
+                let accepted = engine.feature(key, value, &mut done);
+                let reply = if accepted { "accepted" } else { "rejected" };
+                engine.io.send(&format!("{reply} {key}")).await?;
+            }
+            match done {
+                Some(true) => break,
+                Some(false) => timeout = FEATURE_DONE_TIMEOUT,
+                None => {}
+            }
+        }
+        tracing::debug!(
+            engine = %engine.io.name(),
+            id_name = engine.info.name.as_deref().unwrap_or("?"),
+            options = engine.info.options.len(),
+            "Engine started"
+        );
+
+        Ok(engine)
+    }
+
+    /// Record one announced feature, returning whether it is accepted.
+    fn feature(&mut self, key: &str, value: &str, done: &mut Option<bool>) -> bool {
+        let on = value == "1";
+        match key {
+            "done" => *done = Some(on),
+            "myname" => self.info.name = Some(value.to_string()),
+            "ping" => self.features.ping = on,
+            "setboard" => self.features.setboard = on,
+            "usermove" => self.features.usermove = on,
+            "memory" => self.features.memory = on,
+            "smp" => self.features.smp = on,
+            // Moves are always sent in coordinate notation
+            "san" => return !on,
+            "option" => {
+                let Some(option) = parse_option(value) else {
+                    tracing::debug!(value, "Ignoring malformed option");
+                    return false;
+                };
+                self.info.options.push(option);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// The command that makes `uci` on the engine's board.
+    fn move_command(&self, uci: &str) -> String {
+        if self.features.usermove {
+            format!("usermove {uci}")
+        } else {
+            uci.to_string()
+        }
+    }
+
+    /// Send the clock or fixed time for the next search.
+    async fn send_limits(&mut self, limits: &SearchLimits, white_to_move: bool) -> Result<()> {
+        if self.depth_capped && !matches!(limits, SearchLimits::Depth(_)) {
+            self.lift_depth_cap().await?;
+        }
+        match *limits {
+            SearchLimits::Movetime(movetime_ms) => {
+                // `st` takes whole seconds, and rounding would give the engine
+                // time its forfeit and overrun checks don't allow for
+                if movetime_ms == 0 || movetime_ms % 1000 != 0 {
+                    return Err(eyre!(
+                        "XBoard engines can only be given whole seconds per move, not {movetime_ms} ms"
+                    ));
+                }
+                self.level_inc_ms = None;
+                self.io.send(&format!("st {}", movetime_ms / 1000)).await
+            }
+            SearchLimits::Depth(depth) => {
+                // XBoard has no unlimited time, so this caps the depth of
+                // whatever time control the engine has
+                self.depth_capped = true;
+                self.io.send(&format!("sd {depth}")).await
+            }
+            SearchLimits::Nodes(_) => Err(eyre!(
+                "XBoard engines cannot search a fixed number of nodes"
+            )),
+            SearchLimits::Clock {
+                wtime_ms,
+                btime_ms,
+                winc_ms,
+                binc_ms,
+            } => {
+                let (own_ms, other_ms, inc_ms) = if white_to_move {
+                    (wtime_ms, btime_ms, winc_ms)
+                } else {
+                    (btime_ms, wtime_ms, binc_ms)
+                };
+                if self.level_inc_ms != Some(inc_ms) {
+                    // The base only matters until `time` and `otim` correct it
+                    let seconds = own_ms / 1000;
+                    let increment = format_seconds(inc_ms);
+                    self.io
+                        .send(&format!(
+                            "level 0 {}:{:02} {increment}",
+                            seconds / 60,
+                            seconds % 60
+                        ))
+                        .await?;
+                    self.level_inc_ms = Some(inc_ms);
+                }
+                self.io.send(&format!("time {}", own_ms / 10)).await?;
+                self.io.send(&format!("otim {}", other_ms / 10)).await
+            }
+        }
+    }
+
+    /// Lift the cap of an earlier `sd`, which only `new` does, and set the
+    /// board up again.
+    async fn lift_depth_cap(&mut self) -> Result<()> {
+        for command in NEW_GAME {
+            self.io.send(command).await?;
+        }
+        self.level_inc_ms = None;
+        self.depth_capped = false;
+        if let Some(board) = self.board.take() {
+            self.set_position_from(board.start_fen.as_deref(), &board.moves)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Read thinking output until the engine moves or gives up, passing the
+    /// report so far to `on_info` after each thinking line.
+    async fn read_search(
+        &mut self,
+        mut on_info: impl FnMut(&SearchResult) + Send,
+    ) -> Result<SearchResult> {
+        let mut result = SearchResult::default();
+        loop {
+            let line = self.io.read_line().await?;
+            if let Some(best_move) = line.strip_prefix("move ") {
+                let position = self.board.as_ref().and_then(Board::position);
+                result.best_move = normalise_move(position.as_ref(), best_move.trim());
+                return Ok(result);
+            }
+            let white_to_move = self.board.as_ref().is_none_or(Board::white_to_move);
+            match concedes(&line, white_to_move) {
+                Some(true) => {
+                    result.best_move = "(none)".to_string();
+                    return Ok(result);
+                }
+                // A claimed draw or win is for the referee to judge, and the
+                // engine still owes a move
+                Some(false) => {
+                    tracing::debug!(engine = %self.io.name(), line, "Ignoring claim");
+                    continue;
+                }
+                None => {}
+            }
+            if line.starts_with("Illegal move") || line.starts_with("Error") {
+                return Err(eyre!(
+                    "Engine {} rejected a command: {line}",
+                    self.io.name()
+                ));
+            }
+            if update_from_thinking(&mut result, &line) {
+                on_info(&result);
+            }
+        }
+    }
+}
+
+impl Engine for XboardEngine {
+    fn info(&self) -> &EngineInfo {
+        &self.info
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.io.pid()
+    }
+
+    fn set_transcript(&mut self, transcript: Transcript) {
+        self.io.set_transcript(transcript);
+    }
+
+    /// `Hash` and `Threads` map to the `memory` and `cores` commands; other
+    /// options are set with `option`.
+    async fn set_option(&mut self, name: &str, value: &str) -> Result<()> {
+        let command = if name.eq_ignore_ascii_case("Hash") {
+            self.features.memory.then(|| format!("memory {value}"))
+        } else if name.eq_ignore_ascii_case("Threads") {
+            self.features.smp.then(|| format!("cores {value}"))
+        } else {
+            Some(format!("option {name}={value}"))
+        };
+        let Some(command) = command else {
+            tracing::warn!(engine = %self.io.name(), option = name, "Engine does not support option");
+            return Ok(());
+        };
+        self.io.send(&command).await
+    }
+
+    async fn ready(&mut self) -> Result<()> {
+        if !self.features.ping {
+            return Ok(());
+        }
+        self.pings += 1;
+        let pong = format!("pong {}", self.pings);
+        self.io.send(&format!("ping {}", self.pings)).await?;
+        self.io.wait_for(&pong).await
+    }
+
+    async fn new_game(&mut self) -> Result<()> {
+        for command in NEW_GAME {
+            self.io.send(command).await?;
+        }
+        self.board = Some(Board {
+            start_fen: None,
+            moves: Vec::new(),
+        });
+        self.level_inc_ms = None;
+        self.depth_capped = false;
+        self.own_move = None;
+        self.ready().await
+    }
+
+    /// Only the moves played since the last call are sent if the new
+    /// position follows on from it; otherwise the board is set up again.
+    ///
+    /// Anything the engine sent after its last move, such as a result
+    /// claimed after it, is read first so that it can't end the next search.
+    async fn set_position_from(&mut self, start_fen: Option<&str>, moves: &[String]) -> Result<()> {
+        self.ready().await?;
+        self.own_move = None;
+        let continues = self.board.as_ref().is_some_and(|board| {
+            board.start_fen.as_deref() == start_fen && moves.starts_with(&board.moves)
+        });
+        let sent = if continues {
+            self.board.as_ref().map_or(0, |board| board.moves.len())
+        } else {
+            if self.features.setboard {
+                self.io
+                    .send(&format!("setboard {}", start_fen.unwrap_or(STARTPOS)))
+                    .await?;
+            } else if start_fen.is_none() {
+                self.io.send("new").await?;
+                self.io.send("force").await?;
+                self.level_inc_ms = None;
+                self.depth_capped = false;
+            } else {
+                return Err(eyre!("Engine {} does not support setboard", self.io.name()));
+            }
+            0
+        };
+        for uci in &moves[sent..] {
+            self.io.send(&self.move_command(uci)).await?;
+        }
+        self.board = Some(Board {
+            start_fen: start_fen.map(str::to_string),
+            moves: moves.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// The engine's own move from the last search is skipped, since it is
+    /// already on its board; moves that don't follow it set the board up
+    /// again.
+    async fn push_moves(&mut self, moves: &[String]) -> Result<()> {
+        let mut board = self
+            .board
+            .take()
+            .context("No position to play moves from")?;
+        let moves = match (self.own_move.take(), moves.split_first()) {
+            (None, _) => moves,
+            (Some(own), Some((first, rest))) if own == *first => rest,
+            (Some(_), _) => {
+                board.moves.pop();
+                board.moves.extend_from_slice(moves);
+                return self
+                    .set_position_from(board.start_fen.as_deref(), &board.moves)
+                    .await;
+            }
+        };
+        self.ready().await?;
+        for uci in moves {
+            self.io.send(&self.move_command(uci)).await?;
+            board.moves.push(uci.clone());
+        }
+        self.board = Some(board);
+        Ok(())
+    }
+
+    async fn search_with(
+        &mut self,
+        limits: &SearchLimits,
+        on_info: impl FnMut(&SearchResult) + Send,
+    ) -> Result<SearchResult> {
+        let white_to_move = self.board.as_ref().is_none_or(Board::white_to_move);
+        self.send_limits(limits, white_to_move).await?;
+        self.io.send("go").await?;
+        let result = self.read_search(on_info).await?;
+        // Stop the engine from playing on, which it would after the opponent moves
+        self.io.send("force").await?;
+        if let Some(board) = &mut self.board {
+            if result.best_move != "(none)" {
+                board.moves.push(result.best_move.clone());
+                self.own_move = Some(result.best_move.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    async fn quit(&mut self) -> Result<()> {
+        self.io.quit("quit").await
+    }
+}
+
+/// Split a `feature` line into key/value pairs, unquoting string values.
+fn parse_features(line: &str) -> Vec<(&str, &str)> {
+    let mut features = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, after) = after.strip_prefix('"').map_or_else(
+            || after.split_once(' ').unwrap_or((after, "")),
+            |quoted| quoted.split_once('"').unwrap_or((quoted, "")),
+        );
+        features.push((key.trim(), value));
+        rest = after.trim_start();
+    }
+    features
+}
+
+/// Parse the value of an `option` feature, e.g. `Hash -spin 16 1 1024`.
+fn parse_option(value: &str) -> Option<UciOption> {
+    let (name, rest) = value.split_once(" -")?;
+    let (kind, args) = rest.split_once(' ').unwrap_or((rest, ""));
+    let mut option = UciOption {
+        name: name.trim().to_string(),
+        kind: OptionKind::Button,
+        default: None,
+        min: None,
+        max: None,
+        vars: Vec::new(),
+    };
+    match kind {
+        "check" | "string" | "file" | "path" => {
+            option.kind = if kind == "check" {
+                OptionKind::Check
+            } else {
+                OptionKind::String
+            };
+            option.default = Some(match args.trim() {
+                "0" if kind == "check" => "false".to_string(),
+                "1" if kind == "check" => "true".to_string(),
+                default => default.to_string(),
+            });
+        }
+        "spin" | "slider" => {
+            let mut numbers = args.split_whitespace();
+            option.kind = OptionKind::Spin;
+            option.default = numbers.next().map(str::to_string);
+            option.min = numbers.next().and_then(|n| n.parse().ok());
+            option.max = numbers.next().and_then(|n| n.parse().ok());
+        }
+        "combo" => {
+            option.kind = OptionKind::Combo;
+            for var in args.split("///").map(str::trim) {
+                // The default is marked with a leading `*`
+                if let Some(default) = var.strip_prefix('*') {
+                    option.default = Some(default.to_string());
+                    option.vars.push(default.to_string());
+                } else {
+                    option.vars.push(var.to_string());
+                }
+            }
+        }
+        "button" | "save" | "reset" => {}
+        _ => return None,
+    }
+    (!option.name.is_empty()).then_some(option)
+}
+
+/// Update the result from a thinking line, `ply score time nodes pv...`,
+/// where time is in centiseconds, returning whether it was one. Other lines
+/// are ignored.
+///
+/// The PV is kept in whatever notation the engine uses, often SAN.
+fn update_from_thinking(result: &mut SearchResult, line: &str) -> bool {
+    let mut tokens = line.split_whitespace();
+    // Some engines mark the depth of a fail high or low with a suffix
+    let Some(depth) = tokens
+        .next()
+        .and_then(|t| t.trim_end_matches(['+', '-', '&', '.']).parse().ok())
+    else {
+        return false;
+    };
+    let (Some(score), Some(time_cs), Some(nodes)) = (
+        tokens.next().and_then(|t| t.parse::<i32>().ok()),
+        tokens.next().and_then(|t| t.parse::<u64>().ok()),
+        tokens.next().and_then(|t| t.parse::<u64>().ok()),
+    ) else {
+        return false;
+    };
+    result.depth = Some(depth);
+    result.score = Some(if score.abs() >= MATE_SCORE {
+        Score::Mate(score.signum() * (score.abs() - MATE_SCORE))
+    } else {
+        Score::Cp(score)
+    });
+    result.nodes = Some(nodes);
+    result.time_ms = Some(time_cs * 10);
+    result.nps = (time_cs > 0).then(|| nodes.saturating_mul(100) / time_cs);
+    result.pv = tokens.map(str::to_string).collect();
+    true
+}
+
+/// Whether a line gives up the game for the side to move: `Some(true)` for
+/// `resign` or a result the side to move loses, `Some(false)` for a draw or a
+/// win it claims, and `None` if the line isn't a result.
+fn concedes(line: &str, white_to_move: bool) -> Option<bool> {
+    match line.split_whitespace().next()? {
+        "resign" => Some(true),
+        "1-0" => Some(!white_to_move),
+        "0-1" => Some(white_to_move),
+        "1/2-1/2" => Some(false),
+        _ => None,
+    }
+}
+
+/// The move in a `move` reply in UCI notation, reading it as SAN if it isn't
+/// UCI, as engines that ignore `feature san=0` send. Text that is neither is
+/// returned as is, to be rejected as an illegal move.
+fn normalise_move(position: Option<&Chess>, text: &str) -> String {
+    let Some(position) = position else {
+        return text.to_string();
+    };
+    let m = text
+        .parse::<UciMove>()
+        .ok()
+        .and_then(|uci| uci.to_move(position).ok())
+        .or_else(|| text.parse::<SanPlus>().ok()?.san.to_move(position).ok());
+    m.map_or_else(
+        || text.to_string(),
+        |m| m.to_uci(CastlingMode::Standard).to_string(),
+    )
+}
+
+/// Milliseconds as seconds, without a fraction when it would be zero.
+fn format_seconds(ms: u64) -> String {
+    if ms % 1000 == 0 {
+        (ms / 1000).to_string()
+    } else {
+        format!("{}.{:03}", ms / 1000, ms % 1000)
+            .trim_end_matches('0')
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::MockBehaviour;
+    use crate::engine::spawn_xboard_mock;
+
+    #[test]
+    fn test_parse_features() {
+        let features = parse_features(
+            r#"myname="Crafty 25.2" setboard=1 option="Style -combo Solid /// *Normal" done=1"#,
+        );
+        assert_eq!(
+            features,
+            vec![
+                ("myname", "Crafty 25.2"),
+                ("setboard", "1"),
+                ("option", "Style -combo Solid /// *Normal"),
+                ("done", "1"),
+            ]
+        );
+
+        let style = parse_option(features[2].1).expect("Failed to parse option");
+        assert_eq!(style.kind, OptionKind::Combo);
+        assert_eq!(style.default.as_deref(), Some("Normal"));
+        assert_eq!(style.vars, vec!["Solid", "Normal"]);
+        let hash = parse_option("Hash -spin 16 1 1024").expect("Failed to parse option");
+        assert_eq!((hash.min, hash.max), (Some(1), Some(1024)));
+        assert!(parse_option("Broken").is_none());
+    }
+
+    #[test]
+    fn test_parse_thinking() {
+        let mut result = SearchResult::default();
+        assert!(update_from_thinking(
+            &mut result,
+            "9 -156 250 500000 Nf3 Nc6 Bb5"
+        ));
+        assert_eq!(result.depth, Some(9));
+        assert_eq!(result.score, Some(Score::Cp(-156)));
+        assert_eq!(result.nodes, Some(500_000));
+        assert_eq!(result.nps, Some(200_000));
+        assert_eq!(result.time_ms, Some(2500));
+        assert_eq!(result.pv, vec!["Nf3", "Nc6", "Bb5"]);
+
+        assert!(update_from_thinking(&mut result, "12 100003 10 900 Qh5"));
+        assert_eq!(result.score, Some(Score::Mate(3)));
+        assert!(!update_from_thinking(&mut result, "telluser thinking hard"));
+        assert_eq!(result.depth, Some(12));
+        assert_eq!(format_seconds(2500), "2.5");
+        assert_eq!(format_seconds(3000), "3");
+    }
+
+    #[tokio::test]
+    async fn test_mock_xboard_game() {
+        let mut engine = spawn_xboard_mock(
+            "mock",
+            MockBehaviour::Script(vec![
+                "e2e4".to_string(),
+                "e7e5".to_string(),
+                "g1f3".to_string(),
+            ]),
+        )
+        .await
+        .expect("Failed to init mock");
+        assert_eq!(engine.info().name.as_deref(), Some("MockEngine 1.0"));
+        assert!(engine.info().option("Contempt").is_some());
+        engine
+            .set_option("Hash", "64")
+            .await
+            .expect("Failed set_option");
+
+        engine.new_game().await.expect("Failed new_game");
+        engine.set_position(&[]).await.expect("Failed set_position");
+        let search = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        assert_eq!(search.best_move, "e2e4");
+        assert_eq!(search.depth, Some(1));
+        assert_eq!(search.pv, vec!["e2e4"]);
+
+        // Only the reply is sent, as the engine already played its own move
+        let moves = ["e2e4".to_string(), "e7e5".to_string()];
+        engine
+            .set_position(&moves)
+            .await
+            .expect("Failed set_position");
+        let search = engine
+            .search(&SearchLimits::Clock {
+                wtime_ms: 60_000,
+                btime_ms: 60_000,
+                winc_ms: 500,
+                binc_ms: 500,
+            })
+            .await
+            .expect("Failed to get move");
+        assert_eq!(search.best_move, "g1f3");
+
+        // A position that doesn't follow on is set up from scratch
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        engine
+            .set_position_from(Some(fen), &[])
+            .await
+            .expect("Failed set_position");
+        let search = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        assert_eq!(search.best_move, "e2e4");
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    #[test]
+    fn test_result_lines() {
+        assert_eq!(concedes("resign", true), Some(true));
+        assert_eq!(concedes("0-1 {White resigns}", true), Some(true));
+        assert_eq!(concedes("1-0 {White mates}", true), Some(false));
+        assert_eq!(concedes("1-0 {Black resigns}", false), Some(true));
+        assert_eq!(concedes("1/2-1/2 {Draw by repetition}", false), Some(false));
+        assert_eq!(concedes("12 30 100 5000 e4", true), None);
+    }
+
+    #[test]
+    fn test_normalise_move() {
+        let position = Chess::default();
+        assert_eq!(normalise_move(Some(&position), "g1f3"), "g1f3");
+        assert_eq!(normalise_move(Some(&position), "Nf3"), "g1f3");
+        assert_eq!(normalise_move(Some(&position), "zz99"), "zz99");
+        assert_eq!(normalise_move(None, "Nf3"), "Nf3");
+    }
+
+    #[tokio::test]
+    async fn test_mock_xboard_san_castling_reply() {
+        let mut engine = spawn_xboard_mock("mock", MockBehaviour::Script(vec!["O-O".to_string()]))
+            .await
+            .expect("Failed to init mock");
+        engine.new_game().await.expect("Failed new_game");
+        let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+        engine
+            .set_position_from(Some(fen), &[])
+            .await
+            .expect("Failed set_position");
+        let search = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        assert_eq!(search.best_move, "e1g1");
+
+        // The board follows on from the normalised move
+        let moves = ["e1g1".to_string(), "a7a6".to_string()];
+        engine
+            .set_position_from(Some(fen), &moves)
+            .await
+            .expect("Failed set_position");
+        let search = engine
+            .search(&SearchLimits::Depth(3))
+            .await
+            .expect("Failed to get move");
+        assert!(search.best_move.len() == 4, "{}", search.best_move);
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    /// Search the start position and then, with whatever the engine sent
+    /// after its move still to read, a position with Black to move.
+    async fn claim_searches(claim: &str) -> (String, String) {
+        let mut engine = spawn_xboard_mock("mock", MockBehaviour::Claim(claim.to_string()))
+            .await
+            .expect("Failed to init mock");
+        engine.new_game().await.expect("Failed new_game");
+        let white = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        engine
+            .set_position_from(Some("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1"), &[])
+            .await
+            .expect("Failed set_position");
+        let black = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        engine.quit().await.expect("Failed to quit mock");
+        (white.best_move, black.best_move)
+    }
+
+    #[tokio::test]
+    async fn test_mock_xboard_draw_claim_is_not_a_loss() {
+        let (white, black) = claim_searches("1/2-1/2 {Draw by repetition}").await;
+        assert_ne!(white, "(none)");
+        assert_ne!(black, "(none)");
+    }
+
+    #[tokio::test]
+    async fn test_mock_xboard_win_claim_does_not_end_the_next_search() {
+        // Left unread, White's claim would look like Black giving up
+        let (white, black) = claim_searches("1-0 {White mates}").await;
+        assert_ne!(white, "(none)");
+        assert_ne!(black, "(none)");
+    }
+
+    /// A transcript writer whose contents tests can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().expect("Poisoned").extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_xboard_depth_cap_is_lifted_with_new() {
+        let mut engine = spawn_xboard_mock("mock", MockBehaviour::Random(1))
+            .await
+            .expect("Failed to init mock");
+        let buffer = SharedBuffer::default();
+        engine.set_transcript(Transcript::new(buffer.clone()));
+        engine.new_game().await.expect("Failed new_game");
+        let first = engine
+            .search(&SearchLimits::Depth(3))
+            .await
+            .expect("Failed to get move");
+        let search = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        assert_ne!(search.best_move, "(none)");
+
+        let transcript = String::from_utf8(buffer.0.lock().expect("Poisoned").clone())
+            .expect("Transcript is not UTF-8");
+        let lifted = format!(
+            "mock > new\nmock > force\nmock > post\nmock > easy\nmock > ping 2\nmock < pong 2\nmock > setboard {STARTPOS}\nmock > usermove {}\nmock > st 1\n",
+            first.best_move
+        );
+        assert!(transcript.contains(&lifted), "{transcript}");
+        assert!(!transcript.contains("sd 0"), "{transcript}");
+
+        // `st` can't grant part of a second, so the engine isn't given more
+        let error = engine
+            .get_best_move(100)
+            .await
+            .expect_err("Rounded movetime");
+        assert!(error.to_string().contains("whole seconds"), "{error}");
+        engine.quit().await.expect("Failed to quit mock");
+    }
+
+    #[tokio::test]
+    async fn test_mock_xboard_resign_is_no_move() {
+        let mut engine = spawn_xboard_mock("mock", MockBehaviour::NoMove)
+            .await
+            .expect("Failed to init mock");
+        engine.new_game().await.expect("Failed new_game");
+        let search = engine
+            .get_best_move(1000)
+            .await
+            .expect("Failed to get move");
+        assert_eq!(search.best_move, "(none)");
+        engine.quit().await.expect("Failed to quit mock");
+    }
+}
@@ -0,0 +1,284 @@
+//! EPD records: a position with opcodes such as `bm`, `id` or `ce`.
+
+use crate::engine::Score;
+use crate::openings;
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use shakmaty::fen::Fen;
+use shakmaty::san::{San, SanPlus};
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess};
+use std::fmt;
+use std::path::Path;
+
+/// Evaluation written as `ce` for a mate, as EPD has no mate score of its own.
+const MATE_CE: i32 = 32_000;
+
+/// One line of an EPD file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Epd {
+    /// The position as a full FEN, with move counters from `hmvc`/`fmvn` if
+    /// the line had them
+    pub fen: String,
+    /// Opcodes and their operands, in the order they appeared
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    /// Parse an EPD line, or a FEN line with no opcodes.
+    ///
+    /// # Errors
+    /// Returns an error if the line does not describe a legal position.
+    pub fn parse(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // A FEN line carries the move counters that EPD leaves out
+        let is_fen = fields.len() == 6 && fields[4..].iter().all(|f| f.parse::<u32>().is_ok());
+        let (fen, operations) = if is_fen {
+            (openings::fen_from_line(line)?, Vec::new())
+        } else {
+            let board = fields.get(..4).map(|f| f.join(" ")).unwrap_or_default();
+            let mut rest = line.trim_start();
+            for _ in 0..4 {
+                rest = rest
+                    .split_once(char::is_whitespace)
+                    .map_or("", |(_, rest)| rest.trim_start());
+            }
+            (openings::fen_from_line(&board)?, parse_operations(rest))
+        };
+
+        let mut epd = Self { fen, operations };
+        // Move counters given as opcodes replace the defaults
+        if let (Some(halfmoves), Some(fullmoves)) = (epd.operand("hmvc"), epd.operand("fmvn")) {
+            let board: Vec<&str> = epd.fen.split_whitespace().take(4).collect();
+            epd.fen =
+                openings::fen_from_line(&format!("{} {halfmoves} {fullmoves}", board.join(" ")))?;
+        }
+        Ok(epd)
+    }
+
+    /// Load every record of an EPD file, skipping blank lines and `#` comments.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or has an invalid line.
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let records = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                Self::parse(line).wrap_err_with(|| format!("{}:{number}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if records.is_empty() {
+            return Err(eyre!("No positions in {}", path.display()));
+        }
+        Ok(records)
+    }
+
+    /// The position.
+    ///
+    /// # Errors
+    /// Returns an error if the FEN is not a legal position, which can't
+    /// happen for parsed records.
+    pub fn position(&self) -> Result<Chess> {
+        self.fen
+            .parse::<Fen>()
+            .map_err(|e| eyre!("Invalid FEN '{}': {e}", self.fen))?
+            .into_position(CastlingMode::Standard)
+            .map_err(|e| eyre!("Illegal position '{}': {e}", self.fen))
+    }
+
+    /// The operands of `opcode`, if the record has it.
+    #[must_use]
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    /// The first operand of `opcode`, e.g. the name given by `id`.
+    #[must_use]
+    pub fn operand(&self, opcode: &str) -> Option<&str> {
+        self.operands(opcode)?.first().map(String::as_str)
+    }
+
+    /// Set `opcode`'s operands, replacing any it had.
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|(op, _)| op == opcode) {
+            Some((_, existing)) => *existing = operands,
+            None => self.operations.push((opcode.to_string(), operands)),
+        }
+    }
+
+    /// Record an engine's verdict: `bm` and `pv` in SAN, `ce` in centipawns
+    /// and `dm` for a mate it found.
+    ///
+    /// # Errors
+    /// Returns an error if the position is invalid.
+    pub fn set_analysis(
+        &mut self,
+        best_move: &str,
+        score: Option<Score>,
+        pv: &[String],
+    ) -> Result<()> {
+        let position = self.position()?;
+        if let Some(san) = san_line(&position, &[best_move.to_string()]).pop() {
+            self.set("bm", vec![san]);
+        }
+        match score {
+            Some(Score::Cp(cp)) => self.set("ce", vec![cp.to_string()]),
+            Some(Score::Mate(moves)) => {
+                let ce = if moves > 0 { MATE_CE } else { -MATE_CE };
+                self.set("ce", vec![ce.to_string()]);
+                if moves > 0 {
+                    self.set("dm", vec![moves.to_string()]);
+                }
+            }
+            None => {}
+        }
+        let pv = san_line(&position, pv);
+        if !pv.is_empty() {
+            self.set("pv", pv);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Epd {
+    /// The EPD line, with the move counters left out.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let board: Vec<&str> = self.fen.split_whitespace().take(4).collect();
+        write!(f, "{}", board.join(" "))?;
+        for (opcode, operands) in &self.operations {
+            write!(f, " {opcode}")?;
+            for operand in operands {
+                if is_string_opcode(opcode) || operand.contains(char::is_whitespace) {
+                    write!(f, " \"{operand}\"")?;
+                } else {
+                    write!(f, " {operand}")?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `opcode` takes a quoted string, like `id` and the comments `c0`-`c9`.
+fn is_string_opcode(opcode: &str) -> bool {
+    opcode == "id"
+        || opcode.len() == 2 && opcode.starts_with('c') && opcode.as_bytes()[1].is_ascii_digit()
+}
+
+/// Split `op operand...;` operations, keeping quoted operands whole.
+fn parse_operations(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars();
+    let mut token = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                token.extend(chars.by_ref().take_while(|&c| c != '"'));
+                tokens.push(std::mem::take(&mut token));
+            }
+            ';' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                let mut operation = std::mem::take(&mut tokens).into_iter();
+                if let Some(opcode) = operation.next() {
+                    operations.push((opcode, operation.collect()));
+                }
+            }
+            c if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    operations
+}
+
+/// `moves` in SAN, played on from `position`, up to the first one that isn't
+/// a legal UCI move.
+#[must_use]
+pub fn san_line(position: &Chess, moves: &[String]) -> Vec<String> {
+    let mut position = position.clone();
+    let mut line = Vec::new();
+    for uci in moves {
+        let Some(m) = uci
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci| uci.to_move(&position).ok())
+        else {
+            break;
+        };
+        line.push(SanPlus::from_move_and_play_unchecked(&mut position, &m).to_string());
+    }
+    line
+}
+
+/// `moves` in SAN as UCI moves in `position`, or `None` if any is illegal.
+#[must_use]
+pub fn uci_moves(position: &Chess, moves: &[String]) -> Option<Vec<String>> {
+    moves
+        .iter()
+        .map(|san| {
+            let m = san.parse::<San>().ok()?.to_move(position).ok()?;
+            Some(m.to_uci(CastlingMode::Standard).to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_write_epd() {
+        let line = r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001"; c0 "mate in 3";"#;
+        let mut epd = Epd::parse(line).expect("Failed to parse EPD");
+        assert_eq!(
+            epd.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.operand("id"), Some("WAC.001"));
+        assert_eq!(epd.operand("c0"), Some("mate in 3"));
+        let position = epd.position().expect("Invalid position");
+        let bm = epd.operands("bm").expect("No bm").to_vec();
+        assert_eq!(uci_moves(&position, &bm), Some(vec!["g3g6".to_string()]));
+        assert_eq!(epd.to_string(), line);
+
+        epd.set_analysis(
+            "g3g6",
+            Some(Score::Mate(3)),
+            &["g3g6".to_string(), "f7g6".to_string(), "zz99".to_string()],
+        )
+        .expect("Failed to set analysis");
+        assert_eq!(
+            epd.to_string(),
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001"; c0 "mate in 3"; ce 32000; dm 3; pv Qg6 fxg6;"#
+        );
+    }
+
+    #[test]
+    fn test_parse_fen_and_move_counters() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let epd = Epd::parse(fen).expect("Failed to parse FEN");
+        assert_eq!(epd.fen, fen);
+        assert!(epd.operations.is_empty());
+
+        let epd = Epd::parse(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - hmvc 2; fmvn 3;",
+        )
+        .expect("Failed to parse EPD");
+        assert_eq!(epd.fen, fen);
+        assert!(Epd::parse("8/8/8/8/8/8/8/8 w - - bm e4;").is_err());
+    }
+}
@@ -1,7 +1,12 @@
 //! Game state and result tracking.
 
+mod record;
 mod result;
 mod runner;
+mod settings;
 
-pub use result::GameResult;
-pub use runner::GameRunner;
+pub use record::{GameRecord, PlyRecord};
+pub use result::{GameResult, Termination};
+pub(crate) use runner::start_position;
+pub use runner::{GameRunner, MovePlayed, game_over};
+pub use settings::{Adjudication, DrawAdjudication, GameSettings, ResignAdjudication, TimeControl};
@@ -0,0 +1,61 @@
+//! Full records of played games.
+
+use crate::engine::Score;
+use crate::game::{GameResult, Termination};
+use serde::{Deserialize, Serialize};
+
+/// A single half-move and what the engine thought of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlyRecord {
+    /// The move in UCI notation
+    pub uci: String,
+    /// The mover's evaluation before playing the move, from its own point of view
+    pub score: Option<Score>,
+    /// The depth the engine reached
+    pub depth: Option<u32>,
+    /// Wall-clock time from `go` to `bestmove`, in milliseconds
+    pub time_ms: u64,
+    /// How long the engine says it searched, in milliseconds, as of its
+    /// last `info time`; the rest of `time_ms` passed after that report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_ms: Option<u64>,
+    /// How long sending the position took, in microseconds, unless the
+    /// engine was already pondering on it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_us: Option<u64>,
+}
+
+/// Everything needed to reconstruct a finished game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameRecord {
+    /// The final result
+    pub result: GameResult,
+    /// Why the game ended
+    pub termination: Termination,
+    /// Moves played from the starting position
+    pub plies: Vec<PlyRecord>,
+    /// The starting position, if not the standard one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_fen: Option<String>,
+}
+
+impl GameRecord {
+    /// The moves of the game as a space separated UCI string.
+    #[must_use]
+    pub fn uci_moves(&self) -> String {
+        self.plies
+            .iter()
+            .map(|ply| ply.uci.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Whether White made the first move, i.e. moves on even plies.
+    #[must_use]
+    pub fn white_moves_first(&self) -> bool {
+        self.start_fen
+            .as_deref()
+            .and_then(|fen| fen.split_whitespace().nth(1))
+            .is_none_or(|turn| turn == "w")
+    }
+}
@@ -12,3 +12,69 @@ pub enum GameResult {
     /// The game was a draw
     Draw,
 }
+
+impl GameResult {
+    /// The result in PGN notation, e.g. `1-0`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+        }
+    }
+}
+
+/// How a game came to an end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Termination {
+    /// The side to move was checkmated
+    Checkmate,
+    /// The side to move had no legal moves and was not in check
+    Stalemate,
+    /// Neither side has enough material to deliver mate
+    InsufficientMaterial,
+    /// 100 half-moves without a capture or pawn move
+    FiftyMoveRule,
+    /// The game reached the configured move limit
+    MaxMoves,
+    /// An engine reported no move (`bestmove (none)`)
+    NoMove,
+    /// An engine took longer than its time allowance to move
+    TimeForfeit,
+    /// Both engines agreed the position was level for long enough
+    DrawAdjudication,
+    /// Both engines agreed one side was lost for long enough
+    ResignAdjudication,
+}
+
+impl Termination {
+    /// Every termination, in declaration order.
+    pub const ALL: [Self; 9] = [
+        Self::Checkmate,
+        Self::Stalemate,
+        Self::InsufficientMaterial,
+        Self::FiftyMoveRule,
+        Self::MaxMoves,
+        Self::NoMove,
+        Self::TimeForfeit,
+        Self::DrawAdjudication,
+        Self::ResignAdjudication,
+    ];
+
+    /// A stable lowercase name, used for storage and display.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Checkmate => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::InsufficientMaterial => "insufficient_material",
+            Self::FiftyMoveRule => "fifty_move_rule",
+            Self::MaxMoves => "max_moves",
+            Self::NoMove => "no_move",
+            Self::TimeForfeit => "time_forfeit",
+            Self::DrawAdjudication => "draw_adjudication",
+            Self::ResignAdjudication => "resign_adjudication",
+        }
+    }
+}
@@ -1,62 +1,391 @@
 //! Game runner - plays a single game between two engines.
 
-use crate::engine::UciEngine;
-use crate::game::GameResult;
+use crate::engine::{Engine, Score, SearchLimits};
+use crate::game::{
+    Adjudication, GameRecord, GameResult, GameSettings, PlyRecord, Termination, TimeControl,
+};
+use crate::resources::SearchSlots;
 use color_eyre::eyre::{Result, eyre};
-use shakmaty::{Chess, Color, Position, uci::UciMove};
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess, Color, Position, uci::UciMove};
+use std::time::Instant;
 
-/// Runs chess games between two UCI engines.
+/// Evaluation used in place of a mate score when adjudicating.
+const MATE_CP: i32 = 32_000;
+
+/// A move that has just been played, as reported to [`GameRunner::play_game_with`].
+pub struct MovePlayed<'a> {
+    /// Number of half-moves played so far
+    pub ply: usize,
+    /// The move, in UCI notation
+    pub uci: &'a str,
+    /// The position after the move
+    pub position: &'a Chess,
+    /// Search speed the mover reported, in nodes per second
+    pub nps: Option<u64>,
+}
+
+/// How the game ends in `position` by the rules alone, if it does.
+#[must_use]
+pub fn game_over(position: &Chess) -> Option<(GameResult, Termination)> {
+    if position.is_checkmate() {
+        // The side to move is checkmated, so the other side wins
+        let result = if position.turn() == Color::White {
+            GameResult::BlackWins
+        } else {
+            GameResult::WhiteWins
+        };
+        return Some((result, Termination::Checkmate));
+    }
+    if position.is_stalemate() {
+        return Some((GameResult::Draw, Termination::Stalemate));
+    }
+    if position.is_insufficient_material() {
+        return Some((GameResult::Draw, Termination::InsufficientMaterial));
+    }
+    // shakmaty tracks the halfmove clock for the 50-move rule
+    if position.halfmoves() >= 100 {
+        return Some((GameResult::Draw, Termination::FiftyMoveRule));
+    }
+    None
+}
+
+/// Parse a starting position.
+pub fn start_position(fen: &str) -> Result<Chess> {
+    fen.parse::<Fen>()
+        .map_err(|e| eyre!("Invalid FEN '{fen}': {e}"))?
+        .into_position(CastlingMode::Standard)
+        .map_err(|e| eyre!("Illegal position '{fen}': {e}"))
+}
+
+/// Each side's remaining time, when playing with a clock.
+struct Clocks {
+    time_control: TimeControl,
+    /// Remaining time of White and Black
+    remaining: Option<[u64; 2]>,
+}
+
+impl Clocks {
+    fn new(time_control: TimeControl) -> Self {
+        Self {
+            time_control,
+            remaining: time_control.base_ms.map(|base_ms| [base_ms, base_ms]),
+        }
+    }
+
+    /// The limits for the next search.
+    const fn limits(&self) -> SearchLimits {
+        match self.remaining {
+            Some([wtime_ms, btime_ms]) => SearchLimits::Clock {
+                wtime_ms,
+                btime_ms,
+                winc_ms: self.time_control.increment_ms,
+                binc_ms: self.time_control.increment_ms,
+            },
+            None => SearchLimits::Movetime(self.time_control.movetime_ms),
+        }
+    }
+
+    /// How long `side` may take before forfeiting, if there is a limit.
+    fn allowed_ms(&self, side: Color) -> Option<u64> {
+        let margin_ms = self.time_control.margin_ms;
+        if let Some(remaining) = self.remaining {
+            return Some(remaining[side.fold_wb(0, 1)].saturating_add(margin_ms.unwrap_or(0)));
+        }
+        margin_ms.map(|margin_ms| self.time_control.movetime_ms.saturating_add(margin_ms))
+    }
+
+    /// Take `time_ms` off `side`'s clock and add the increment.
+    fn charge(&mut self, side: Color, time_ms: u64) {
+        if let Some(remaining) = &mut self.remaining {
+            let clock = &mut remaining[side.fold_wb(0, 1)];
+            *clock = clock.saturating_sub(time_ms) + self.time_control.increment_ms;
+        }
+    }
+}
+
+/// What each side's engine has been told, so that only new moves need
+/// sending and pondering engines are handled.
+#[derive(Default)]
+struct EngineStates {
+    /// Opponent moves White and Black are pondering on
+    expected: [Option<String>; 2],
+    /// How many moves of the game White and Black's engines hold, or `None`
+    /// if they need the whole game sent
+    sent: [Option<usize>; 2],
+}
+
+impl EngineStates {
+    /// Get `engine`, playing `side`, ready to search after `moves`.
+    ///
+    /// Returns how many microseconds sending the position took, or `None`
+    /// if the engine was pondering on the move just played, in which case
+    /// its search carries on after a `ponderhit`. An engine that pondered
+    /// on another move is stopped and given the actual position.
+    async fn prepare<E: Engine>(
+        &mut self,
+        engine: &mut E,
+        side: Color,
+        start_fen: Option<&str>,
+        moves: &[String],
+    ) -> Result<Option<u64>> {
+        let index = side.fold_wb(0, 1);
+        if let Some(expected) = self.expected[index].take() {
+            if moves.last() == Some(&expected) {
+                return Ok(None);
+            }
+            engine.stop().await?;
+            self.sent[index] = None;
+        }
+        let started = Instant::now();
+        self.send(engine, side, start_fen, moves, None).await?;
+        Ok(Some(
+            u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX),
+        ))
+    }
+
+    /// Have `engine`, which just moved for `side`, ponder on `reply`.
+    async fn start<E: Engine>(
+        &mut self,
+        engine: &mut E,
+        side: Color,
+        start_fen: Option<&str>,
+        moves: &[String],
+        reply: String,
+        limits: &SearchLimits,
+    ) -> Result<()> {
+        self.send(engine, side, start_fen, moves, Some(&reply))
+            .await?;
+        engine.go_ponder(limits).await?;
+        self.expected[side.fold_wb(0, 1)] = Some(reply);
+        Ok(())
+    }
+
+    /// Bring `engine`, playing `side`, up to `moves` followed by `reply`,
+    /// sending only the moves it doesn't have yet.
+    async fn send<E: Engine>(
+        &mut self,
+        engine: &mut E,
+        side: Color,
+        start_fen: Option<&str>,
+        moves: &[String],
+        reply: Option<&String>,
+    ) -> Result<()> {
+        let sent = &mut self.sent[side.fold_wb(0, 1)];
+        if let Some(count) = *sent {
+            let mut new = moves[count..].to_vec();
+            new.extend(reply.cloned());
+            engine.push_moves(&new).await?;
+        } else {
+            let mut line = moves.to_vec();
+            line.extend(reply.cloned());
+            engine.set_position_from(start_fen, &line).await?;
+        }
+        *sent = Some(moves.len() + usize::from(reply.is_some()));
+        Ok(())
+    }
+
+    /// Stop whichever engines are still pondering.
+    async fn stop_all<E: Engine>(&mut self, white: &mut E, black: &mut E) -> Result<()> {
+        for (expected, engine) in self.expected.iter_mut().zip([white, black]) {
+            if expected.take().is_some() {
+                engine.stop().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs chess games between two engines.
 pub struct GameRunner {
-    movetime_ms: u64,
-    max_moves: u32,
+    settings: GameSettings,
+    search_slots: Option<SearchSlots>,
 }
 
 impl GameRunner {
     /// Create a new game runner.
     #[must_use]
     pub const fn new(movetime_ms: u64, max_moves: u32) -> Self {
+        Self::from_settings(GameSettings {
+            time_control: TimeControl {
+                movetime_ms,
+                base_ms: None,
+                increment_ms: 0,
+                margin_ms: None,
+                ponder: false,
+            },
+            adjudication: Adjudication {
+                max_moves,
+                draw: None,
+                resign: None,
+            },
+        })
+    }
+
+    /// Create a game runner with full time control and adjudication settings.
+    #[must_use]
+    pub const fn from_settings(settings: GameSettings) -> Self {
         Self {
-            movetime_ms,
-            max_moves,
+            settings,
+            search_slots: None,
+        }
+    }
+
+    /// Wait for a free slot in `slots` before every search, so that the
+    /// searches of all runners sharing them stay within the cores.
+    ///
+    /// Engines can't ponder as well, which [`check`](Self::check) rejects.
+    #[must_use]
+    pub fn with_search_slots(mut self, slots: Option<SearchSlots>) -> Self {
+        self.search_slots = slots;
+        self
+    }
+
+    /// Forfeit engines that take more than `margin_ms` longer than the movetime.
+    #[must_use]
+    pub const fn with_time_margin(mut self, margin_ms: u64) -> Self {
+        self.settings.time_control.margin_ms = Some(margin_ms);
+        self
+    }
+
+    /// Check that games can be played as set up.
+    ///
+    /// # Errors
+    /// Returns an error if engines are to ponder while searches share
+    /// slots: a pondering engine holding a slot could keep its opponent from
+    /// ever getting one, and without one it would search on cores promised
+    /// to another engine.
+    pub fn check(&self) -> Result<()> {
+        if self.settings.time_control.ponder && self.search_slots.is_some() {
+            return Err(eyre!(
+                "Pondering cannot be combined with searches sharing the cores"
+            ));
         }
+        Ok(())
     }
 
     /// Play a single game between white and black engines.
     ///
     /// # Errors
     /// Returns an error if engine communication fails or produces invalid moves.
-    pub async fn play_game(
+    pub async fn play_game<E: Engine>(&self, white: &mut E, black: &mut E) -> Result<GameRecord> {
+        self.play_game_with(white, black, |_| {}).await
+    }
+
+    /// Play a single game, calling `on_move` after every move.
+    ///
+    /// # Errors
+    /// Returns an error if engine communication fails or produces invalid moves.
+    pub async fn play_game_with<E: Engine>(
         &self,
-        white: &mut UciEngine,
-        black: &mut UciEngine,
-    ) -> Result<GameResult> {
-        let mut position = Chess::default();
-        let mut moves: Vec<String> = Vec::new();
+        white: &mut E,
+        black: &mut E,
+        on_move: impl FnMut(MovePlayed<'_>) + Send,
+    ) -> Result<GameRecord> {
+        self.play_game_from(white, black, None, on_move).await
+    }
+
+    /// Play a single game from `start_fen` (or the standard starting
+    /// position), calling `on_move` after every move.
+    ///
+    /// # Errors
+    /// Returns an error if the runner fails its [`check`](Self::check), the
+    /// FEN is invalid, or engine communication fails or produces invalid
+    /// moves.
+    pub async fn play_game_from<E: Engine>(
+        &self,
+        white: &mut E,
+        black: &mut E,
+        start_fen: Option<&str>,
+        on_move: impl FnMut(MovePlayed<'_>) + Send,
+    ) -> Result<GameRecord> {
+        self.check()?;
+        let position = start_fen.map_or_else(|| Ok(Chess::default()), start_position)?;
+        let mut plies: Vec<PlyRecord> = Vec::new();
 
         // Initialize both engines for a new game
-        white.new_game().await?;
-        black.new_game().await?;
+        for engine in [&mut *white, &mut *black] {
+            if self.settings.time_control.ponder && engine.can_ponder() {
+                engine.set_option("Ponder", "true").await?;
+            }
+            engine.new_game().await?;
+        }
 
-        for move_num in 0..self.max_moves {
-            let is_white_turn = position.turn() == Color::White;
+        let mut states = EngineStates::default();
+        let (result, termination) = self
+            .play_moves(
+                [&mut *white, &mut *black],
+                position,
+                start_fen,
+                &mut states,
+                &mut plies,
+                on_move,
+            )
+            .await?;
+        // An engine left pondering on a move that never came would answer
+        // the next game's first search with a stale move
+        states.stop_all(white, black).await?;
+        Ok(GameRecord {
+            result,
+            termination,
+            plies,
+            start_fen: start_fen.map(str::to_string),
+        })
+    }
+
+    /// Play moves until the game ends, recording them in `plies`.
+    async fn play_moves<E: Engine>(
+        &self,
+        [white, black]: [&mut E; 2],
+        mut position: Chess,
+        start_fen: Option<&str>,
+        states: &mut EngineStates,
+        plies: &mut Vec<PlyRecord>,
+        mut on_move: impl FnMut(MovePlayed<'_>) + Send,
+    ) -> Result<(GameResult, Termination)> {
+        let mut moves: Vec<String> = Vec::new();
+        let mut clocks = Clocks::new(self.settings.time_control);
+
+        for move_num in 0..self.settings.adjudication.max_moves {
+            let side = position.turn();
 
             // Set position and get best move from the current player
-            let uci_move_str = if is_white_turn {
-                white.set_position(&moves).await?;
-                white.get_best_move(self.movetime_ms).await?
+            let engine = if side == Color::White {
+                &mut *white
+            } else {
+                &mut *black
+            };
+            let position_sent = states.prepare(engine, side, start_fen, &moves).await?;
+            // Time spent waiting for a slot is not charged to the engine
+            let slot = match &self.search_slots {
+                Some(slots) => Some(slots.acquire().await?),
+                None => None,
+            };
+            let started = Instant::now();
+            let search = if position_sent.is_some() {
+                engine.search(&clocks.limits()).await?
             } else {
-                black.set_position(&moves).await?;
-                black.get_best_move(self.movetime_ms).await?
+                engine.ponder_hit().await?
             };
+            let time_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+            drop(slot);
+            let uci_move_str = search.best_move;
 
-            // Handle special case: engine resigns or can't move
+            // The side to move loses if it resigns, can't move or overruns its time
+            let loss = if side == Color::White {
+                GameResult::BlackWins
+            } else {
+                GameResult::WhiteWins
+            };
             if uci_move_str == "(none)" || uci_move_str.is_empty() {
-                return Ok(if position.turn() == Color::White {
-                    GameResult::BlackWins
-                } else {
-                    GameResult::WhiteWins
-                });
+                return Ok((loss, Termination::NoMove));
             }
+            let allowed_ms = clocks.allowed_ms(side);
+            if allowed_ms.is_some_and(|allowed_ms| time_ms > allowed_ms) {
+                tracing::debug!(time_ms, ?allowed_ms, "Engine lost on time");
+                return Ok((loss, Termination::TimeForfeit));
+            }
+            clocks.charge(side, time_ms);
 
             // Parse and validate the move
             let uci_move: UciMove = uci_move_str
@@ -71,6 +400,14 @@ impl GameRunner {
             position = position
                 .play(&chess_move)
                 .map_err(|e| eyre!("Failed to apply move '{uci_move_str}': {e}"))?;
+            plies.push(PlyRecord {
+                uci: uci_move_str.clone(),
+                score: search.score,
+                depth: search.depth,
+                time_ms,
+                search_ms: search.time_ms,
+                position_us: position_sent,
+            });
             moves.push(uci_move_str);
 
             tracing::trace!(
@@ -78,42 +415,445 @@ impl GameRunner {
                 last_move = %moves.last().unwrap_or(&String::new()),
                 "Move played"
             );
+            on_move(MovePlayed {
+                ply: moves.len(),
+                uci: moves.last().map_or("", String::as_str),
+                position: &position,
+                nps: search.nps,
+            });
 
             // Check for game end
-            if position.is_checkmate() {
-                // The side to move is checkmated, so the other side wins
-                return Ok(if position.turn() == Color::White {
-                    GameResult::BlackWins
-                } else {
-                    GameResult::WhiteWins
-                });
+            if let Some(outcome) = game_over(&position) {
+                return Ok(outcome);
             }
 
-            if position.is_stalemate() || position.is_insufficient_material() {
-                return Ok(GameResult::Draw);
+            if let Some(outcome) = self.adjudicate(&position, plies) {
+                return Ok(outcome);
             }
 
-            // Check for draw by repetition or 50-move rule
-            // shakmaty handles halfmoves counter for 50-move rule
-            if position.halfmoves() >= 100 {
-                return Ok(GameResult::Draw);
+            // Think on the opponent's time about the reply the engine expects
+            let ponder_move = search
+                .ponder
+                .filter(|_| self.settings.time_control.ponder && engine.can_ponder())
+                .filter(|reply| {
+                    reply
+                        .parse::<UciMove>()
+                        .is_ok_and(|reply| reply.to_move(&position).is_ok())
+                });
+            if let Some(ponder_move) = ponder_move {
+                states
+                    .start(
+                        engine,
+                        side,
+                        start_fen,
+                        &moves,
+                        ponder_move,
+                        &clocks.limits(),
+                    )
+                    .await?;
             }
         }
 
         // Max moves reached - declare draw
         tracing::debug!(
             "Game reached max moves ({}) - declaring draw",
-            self.max_moves
+            self.settings.adjudication.max_moves
         );
-        Ok(GameResult::Draw)
+        Ok((GameResult::Draw, Termination::MaxMoves))
+    }
+
+    /// Apply draw and resign adjudication to the scores of the last moves.
+    fn adjudicate(
+        &self,
+        position: &Chess,
+        plies: &[PlyRecord],
+    ) -> Option<(GameResult, Termination)> {
+        // Scores of the last `moves` moves of each side, newest first, from
+        // the point of view of whoever made them
+        let recent = |moves: u32| -> Option<Vec<i32>> {
+            let count = usize::try_from(moves).ok()?.checked_mul(2)?;
+            if count == 0 || plies.len() < count {
+                return None;
+            }
+            plies
+                .iter()
+                .rev()
+                .take(count)
+                .map(|ply| ply.score.map(score_cp))
+                .collect()
+        };
+
+        let adjudication = &self.settings.adjudication;
+        if let Some(draw) = adjudication.draw {
+            let level = position.fullmoves().get() >= draw.after_move
+                && recent(draw.moves)
+                    .is_some_and(|scores| scores.iter().all(|cp| cp.abs() <= draw.score_cp));
+            if level {
+                return Some((GameResult::Draw, Termination::DrawAdjudication));
+            }
+        }
+
+        if let Some(resign) = adjudication.resign {
+            let scores = recent(resign.moves)?;
+            // Even entries are the last mover's scores, odd ones the opponent's
+            let mover: Vec<i32> = scores.iter().step_by(2).copied().collect();
+            let opponent: Vec<i32> = scores.iter().skip(1).step_by(2).copied().collect();
+            let agree = |losing: &[i32], winning: &[i32]| {
+                losing.iter().all(|&cp| cp <= -resign.score_cp)
+                    && winning.iter().all(|&cp| cp >= resign.score_cp)
+            };
+            // The side to move did not make the last move
+            let mover_is_white = position.turn() == Color::Black;
+            let mover_loses = if agree(&mover, &opponent) {
+                true
+            } else if agree(&opponent, &mover) {
+                false
+            } else {
+                return None;
+            };
+            let result = if mover_loses == mover_is_white {
+                GameResult::BlackWins
+            } else {
+                GameResult::WhiteWins
+            };
+            return Some((result, Termination::ResignAdjudication));
+        }
+        None
+    }
+}
+
+/// A score in centipawns, with mates as large scores.
+const fn score_cp(score: Score) -> i32 {
+    match score {
+        Score::Cp(cp) => cp,
+        Score::Mate(mate) if mate > 0 => MATE_CP,
+        Score::Mate(_) => -MATE_CP,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::mock::MockBehaviour;
+    use crate::engine::{AnyEngine, UciEngine, spawn_mock, spawn_xboard_mock};
+    use crate::game::DrawAdjudication;
+
+    async fn mock_pair(white: MockBehaviour, black: MockBehaviour) -> (UciEngine, UciEngine) {
+        let white = spawn_mock("white", white)
+            .await
+            .expect("Failed to init white mock");
+        let black = spawn_mock("black", black)
+            .await
+            .expect("Failed to init black mock");
+        (white, black)
+    }
+
+    fn script(moves: &str) -> MockBehaviour {
+        MockBehaviour::Script(moves.split(' ').map(str::to_string).collect())
+    }
+
+    #[tokio::test]
+    async fn test_scripted_fools_mate() {
+        let fools_mate = "f2f3 e7e5 g2g4 d8h4";
+        let (mut white, mut black) = mock_pair(script(fools_mate), script(fools_mate)).await;
+
+        let record = GameRunner::new(10, 100)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+
+        assert_eq!(record.result, GameResult::BlackWins);
+        assert_eq!(record.termination, Termination::Checkmate);
+        assert_eq!(record.uci_moves(), fools_mate);
+        assert!(record.plies.iter().all(|ply| ply.depth == Some(1)));
+    }
+
+    #[tokio::test]
+    async fn test_uci_against_xboard() {
+        let fools_mate = "f2f3 e7e5 g2g4 d8h4";
+        let white = spawn_mock("white", script(fools_mate))
+            .await
+            .expect("Failed to init white mock");
+        let black = spawn_xboard_mock("black", script(fools_mate))
+            .await
+            .expect("Failed to init black mock");
+        let (mut white, mut black) = (AnyEngine::from(white), AnyEngine::from(black));
+        let buffer = SharedBuffer::default();
+        black.set_transcript(crate::engine::Transcript::new(buffer.clone()));
+
+        let mut settings = GameSettings::default();
+        settings.time_control.base_ms = Some(10_000);
+        settings.time_control.increment_ms = 100;
+        // XBoard engines can't be driven to ponder, so this only affects White
+        settings.time_control.ponder = true;
+        let record = GameRunner::from_settings(settings)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert_eq!(record.result, GameResult::BlackWins);
+        assert_eq!(record.uci_moves(), fools_mate);
+
+        let transcript = String::from_utf8(buffer.0.lock().expect("Poisoned").clone())
+            .expect("Transcript is not UTF-8");
+        assert!(transcript.contains("black > usermove f2f3\nblack > level 0 0:10 0.1\n"));
+        // Black's own move is already on its board, so only White's reply is sent
+        assert!(transcript.contains("black > usermove g2g4\nblack > time "));
+        assert!(!transcript.contains("black > usermove e7e5"));
+        assert!(!transcript.contains("Ponder"));
+    }
+
+    /// A transcript writer whose contents tests can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().expect("Poisoned").extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A transcript writer that holds up every `position` command.
+    struct SlowPositions(std::time::Duration);
+
+    impl std::io::Write for SlowPositions {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            // The line is written apart from the engine's name and direction
+            if buf.starts_with(b"position ") {
+                std::thread::sleep(self.0);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_position_send_time_is_recorded() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(1), MockBehaviour::Random(2)).await;
+        let transcript =
+            crate::engine::Transcript::new(SlowPositions(std::time::Duration::from_millis(5)));
+        white.set_transcript(transcript.clone());
+        black.set_transcript(transcript);
+
+        let record = GameRunner::new(10, 6)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert!(
+            record
+                .plies
+                .iter()
+                .all(|ply| ply.position_us.is_some_and(|us| us >= 5_000)),
+            "{:?}",
+            record.plies
+        );
+
+        let stats = crate::MatchStats::default();
+        stats.record(&record, true);
+        assert!(stats.position_send_us().is_some_and(|us| us >= 5_000.0));
+    }
+
+    #[tokio::test]
+    async fn test_ponder_hits_and_misses() {
+        // White expects 1... d5 but gets 1... e5; Black expects 2. Nf3 and gets it
+        let (mut white, mut black) =
+            mock_pair(script("e2e4 d7d5 g1f3 b8c6"), script("e2e4 e7e5 g1f3 b8c6")).await;
+        let buffer = SharedBuffer::default();
+        let transcript = crate::engine::Transcript::new(buffer.clone());
+        white.set_transcript(transcript.clone());
+        black.set_transcript(transcript);
+
+        let mut settings = GameSettings::default();
+        settings.time_control.base_ms = Some(10_000);
+        settings.time_control.ponder = true;
+        settings.adjudication.max_moves = 4;
+        let record = GameRunner::from_settings(settings)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert_eq!(record.uci_moves(), "e2e4 e7e5 g1f3 b8c6");
+
+        let transcript = String::from_utf8(buffer.0.lock().expect("Poisoned").clone())
+            .expect("Transcript is not UTF-8");
+        assert!(transcript.contains("white > setoption name Ponder value true\n"));
+        // Both moves reset the halfmove clock, so the position is sent as a FEN
+        assert!(transcript.contains(
+            "white > position fen rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2\nwhite > go ponder wtime "
+        ));
+        assert_eq!(
+            transcript.matches("black > ponderhit").count(),
+            1,
+            "{transcript}"
+        );
+        // White is stopped after its miss and again after the last move
+        assert_eq!(
+            transcript.matches("white > stop").count(),
+            2,
+            "{transcript}"
+        );
+        assert_eq!(
+            transcript.matches("black > stop").count(),
+            1,
+            "{transcript}"
+        );
+
+        // Both engines are idle and ready for another game
+        let record = GameRunner::new(10, 2)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Second game failed");
+        assert_eq!(record.plies.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pondering_with_search_slots_is_rejected() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(1), MockBehaviour::Random(2)).await;
+        let mut settings = GameSettings::default();
+        settings.time_control.ponder = true;
+        let runner = GameRunner::from_settings(settings)
+            .with_search_slots(Some(crate::resources::SearchSlots::new(2, 1)));
+        let error = runner
+            .play_game(&mut white, &mut black)
+            .await
+            .expect_err("Pondered outside the slots");
+        assert!(error.to_string().contains("Pondering"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_random_game_completes() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(1), MockBehaviour::Random(2)).await;
+
+        let mut observed = 0;
+        let record = GameRunner::new(10, 40)
+            .play_game_with(&mut white, &mut black, |played| {
+                observed += 1;
+                assert_eq!(played.ply, observed);
+            })
+            .await
+            .expect("Game failed");
+
+        assert!(record.plies.len() <= 40);
+        assert_eq!(record.plies.len(), observed);
+    }
+
+    #[tokio::test]
+    async fn test_illegal_move_is_an_error() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::IllegalMove, MockBehaviour::Random(0)).await;
+
+        let error = GameRunner::new(10, 40)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect_err("Illegal move accepted");
+        assert!(error.to_string().contains("Illegal move 'a1a1'"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_output_is_an_error() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(0), MockBehaviour::Malformed).await;
+
+        let error = GameRunner::new(10, 40)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect_err("Malformed move accepted");
+        assert!(error.to_string().contains("zz99"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_crash_is_an_error() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(0), MockBehaviour::Crash(5)).await;
+
+        let error = GameRunner::new(10, 40)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect_err("Crash not detected");
+        assert!(error.to_string().contains("closed unexpectedly"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_no_move_loses() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(0), MockBehaviour::NoMove).await;
+
+        let record = GameRunner::new(10, 40)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert_eq!(record.result, GameResult::WhiteWins);
+        assert_eq!(record.termination, Termination::NoMove);
+        assert_eq!(record.plies.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_engine_forfeits_on_time() {
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(0), MockBehaviour::Slow(200)).await;
+
+        let record = GameRunner::new(10, 40)
+            .with_time_margin(50)
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert_eq!(record.result, GameResult::WhiteWins);
+        assert_eq!(record.termination, Termination::TimeForfeit);
+        assert_eq!(record.plies.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_clock_forfeit_and_draw_adjudication() {
+        let settings = GameSettings {
+            time_control: TimeControl {
+                base_ms: Some(100),
+                margin_ms: Some(20),
+                ..TimeControl::default()
+            },
+            adjudication: Adjudication {
+                draw: Some(DrawAdjudication {
+                    after_move: 3,
+                    moves: 2,
+                    score_cp: 10,
+                }),
+                ..Adjudication::default()
+            },
+        };
+        let runner = GameRunner::from_settings(settings);
+
+        // The mock always reports a level score
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(1), MockBehaviour::Random(2)).await;
+        let record = runner
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert_eq!(record.termination, Termination::DrawAdjudication);
+        assert_eq!(record.plies.len(), 4);
+
+        // Black's clock runs out on its second move
+        let (mut white, mut black) =
+            mock_pair(MockBehaviour::Random(1), MockBehaviour::Slow(70)).await;
+        let record = runner
+            .play_game(&mut white, &mut black)
+            .await
+            .expect("Game failed");
+        assert_eq!(record.result, GameResult::WhiteWins);
+        assert_eq!(record.termination, Termination::TimeForfeit);
+        assert_eq!(record.plies.len(), 3);
+    }
 
     #[tokio::test]
+    #[ignore = "requires stockfish and reckless on PATH"]
     async fn test_play_single_game() {
         let mut stockfish = UciEngine::new("stockfish", "stockfish")
             .await
@@ -134,6 +874,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires stockfish and reckless on PATH"]
     async fn test_play_game_reversed_colors() {
         let mut stockfish = UciEngine::new("stockfish", "stockfish")
             .await
@@ -0,0 +1,108 @@
+//! How games are played: time control and adjudication.
+
+use serde::{Deserialize, Serialize};
+
+/// How much time engines get to move.
+///
+/// With `base_ms` set, each side has a clock starting at `base_ms` that gains
+/// `increment_ms` per move; otherwise every move gets `movetime_ms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeControl {
+    /// Time limit per move in milliseconds, without a clock
+    pub movetime_ms: u64,
+    /// Starting clock time per side in milliseconds
+    pub base_ms: Option<u64>,
+    /// Time added to the mover's clock after each move, in milliseconds
+    pub increment_ms: u64,
+    /// How far over its time an engine may go before forfeiting. Without a
+    /// clock, engines never forfeit unless this is set.
+    pub margin_ms: Option<u64>,
+    /// Let engines think on their opponent's time, about the reply they
+    /// expect. Only the time from `ponderhit` to `bestmove` is charged.
+    pub ponder: bool,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            movetime_ms: 100,
+            base_ms: None,
+            increment_ms: 0,
+            margin_ms: None,
+            ponder: false,
+        }
+    }
+}
+
+/// Declare a draw once both engines agree the position is level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DrawAdjudication {
+    /// First full move at which a draw may be declared
+    pub after_move: u32,
+    /// Consecutive moves per side the score must stay level for
+    pub moves: u32,
+    /// Largest absolute score, in centipawns, that counts as level
+    pub score_cp: i32,
+}
+
+/// Declare a win once both engines agree one side is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResignAdjudication {
+    /// Consecutive moves per side the score must stay decisive for
+    pub moves: u32,
+    /// Smallest score, in centipawns, that counts as decisive
+    pub score_cp: i32,
+}
+
+/// When to end a game early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Adjudication {
+    /// Moves before the game is declared a draw
+    pub max_moves: u32,
+    /// Draw adjudication, if enabled
+    pub draw: Option<DrawAdjudication>,
+    /// Resign adjudication, if enabled
+    pub resign: Option<ResignAdjudication>,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            max_moves: 500,
+            draw: None,
+            resign: None,
+        }
+    }
+}
+
+/// How each game of a match is played.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSettings {
+    /// How much time engines get
+    pub time_control: TimeControl,
+    /// When to end games early
+    pub adjudication: Adjudication,
+}
+
+impl GameSettings {
+    /// The longest a game can last while both engines keep within their
+    /// time, in milliseconds, not counting communication.
+    #[must_use]
+    pub fn longest_game_ms(&self) -> u64 {
+        let plies = u64::from(self.adjudication.max_moves);
+        let time_control = &self.time_control;
+        let margin_ms = time_control.margin_ms.unwrap_or(0);
+        time_control.base_ms.map_or_else(
+            || plies.saturating_mul(time_control.movetime_ms + margin_ms),
+            |base_ms| {
+                base_ms
+                    .saturating_mul(2)
+                    .saturating_add(plies.saturating_mul(time_control.increment_ms + margin_ms))
+            },
+        )
+    }
+}
@@ -0,0 +1,49 @@
+//! Reckless vs Stockfish - Chess Engine Battle
+//!
+//! Runs games between Stockfish and Reckless chess engines via UCI protocol.
+//! Besides the command-line tool, the crate can be embedded: a [`Match`]
+//! plays games on a pool of workers and streams the finished games back.
+//!
+//! ```no_run
+//! use reckless_vs_stockfish::Match;
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn example() {
+//! let mut games = Match::new("stockfish", "reckless")
+//!     .with_games(10)
+//!     .with_workers(2)
+//!     .play();
+//! while let Some(record) = games.next().await {
+//!     println!("{:?} by {}", record.result, record.termination.as_str());
+//! }
+//! # }
+//! ```
+
+pub mod analysis;
+pub mod blunders;
+pub mod book;
+pub mod config;
+pub mod db;
+pub mod distributed;
+pub mod elo;
+pub mod engine;
+pub mod epd;
+pub mod game;
+pub mod match_runner;
+pub mod metrics;
+pub mod openings;
+pub mod output;
+pub mod perft;
+pub mod pgn;
+pub mod replay;
+pub mod resources;
+pub mod schedule;
+pub mod shutdown;
+pub mod similarity;
+pub mod testsuite;
+pub mod tui;
+pub mod tune;
+
+pub use engine::{AnyEngine, Engine, UciEngine, XboardEngine};
+pub use game::{GameRecord, GameRunner};
+pub use match_runner::{Match, MatchStats, RunningMatch};
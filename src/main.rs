@@ -2,211 +2,1434 @@
 //!
 //! Runs games between Stockfish and Reckless chess engines via UCI protocol.
 
-use clap::Parser;
-use color_eyre::eyre::Result;
-use game::{GameResult, GameRunner};
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{Result, eyre};
+use reckless_vs_stockfish::config::MatchConfig;
+use reckless_vs_stockfish::db::{self, EngineMetadata, GameFilter, Outcome, ResultsDb, Side};
+use reckless_vs_stockfish::engine::{
+    AnyEngine, Engine, EngineConfig, OptionValue, Protocol, Score, SearchLimits, Transcript,
+};
+use reckless_vs_stockfish::epd::{self, Epd};
+use reckless_vs_stockfish::match_runner::WorkerEvent;
+use reckless_vs_stockfish::output::GameFiles;
+use reckless_vs_stockfish::resources::Concurrency;
+use reckless_vs_stockfish::tui::{self, Action, Tui};
+use reckless_vs_stockfish::{
+    MatchStats, RunningMatch, UciEngine, analysis, blunders, book, distributed, metrics, perft,
+    pgn, replay, shutdown, similarity, testsuite, tune,
+};
+use std::fs::File;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
-mod engine;
-mod game;
-
 /// Run chess engine matches between Stockfish and Reckless
-#[derive(Parser, Debug, Clone)]
-#[command(version, about)]
+#[derive(Parser, Debug)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Search a results database for stored games
+    Query(QueryArgs),
+    /// Hand the match's games out to remote workers instead of playing them here
+    Coordinator(CoordinatorArgs),
+    /// Play games handed out by a coordinator
+    Worker(WorkerArgs),
+    /// Print the fully resolved match configuration and exit
+    PrintConfig(PrintConfigArgs),
+    /// Re-check a stored game, and optionally play it again live
+    Replay(ReplayArgs),
+    /// Search every position of an EPD file and report the engine's verdicts
+    Analyse(AnalyseArgs),
+    /// Score an engine on the `bm`/`am` positions of an EPD test suite such as WAC or STS
+    Testsuite(TestsuiteArgs),
+    /// Compare an engine's `go perft` node counts with the move generator's
+    Perft(PerftArgs),
+    /// Re-analyse stored games with a referee engine and flag the moves that lost most
+    Blunders(BlundersArgs),
+    /// Measure how often engines choose the same move in the same positions
+    Similarity(SimilarityArgs),
+    /// Tune Reckless's spin options with SPSA in games against Stockfish
+    Tune(TuneArgs),
+    /// Grow a balanced opening book from the lines an engine rates close to its best
+    BookGen(BookGenArgs),
+}
+
+/// Settings for a match. Flags override values from the configuration file.
+#[derive(clap::Args, Debug, Clone)]
 struct Args {
-    /// Number of games to play
-    #[arg(short, long, default_value = "1000000")]
-    games: u64,
-
-    /// Path to stockfish engine
-    #[arg(long, default_value = "stockfish")]
-    stockfish_path: String,
-
-    /// Path to reckless engine
-    #[arg(long, default_value = "reckless")]
-    reckless_path: String,
-
-    /// Time limit per move in milliseconds
-    #[arg(long, default_value = "100")]
-    movetime_ms: u64,
-
-    /// Maximum moves per game before declaring a draw
-    #[arg(long, default_value = "500")]
-    max_moves: u32,
-
-    /// Number of parallel workers (engine pairs)
-    #[arg(short, long, default_value = "12")]
-    workers: usize,
-}
-
-/// Statistics for a match (thread-safe).
-#[derive(Debug, Default)]
-struct MatchStats {
-    stockfish_wins: AtomicU64,
-    reckless_wins: AtomicU64,
-    draws: AtomicU64,
-    stockfish_white_wins: AtomicU64,
-    stockfish_black_wins: AtomicU64,
-    reckless_white_wins: AtomicU64,
-    reckless_black_wins: AtomicU64,
-    games_completed: AtomicU64,
-}
-
-impl MatchStats {
-    fn record(&self, result: GameResult, stockfish_is_white: bool) {
-        match result {
-            GameResult::WhiteWins => {
-                if stockfish_is_white {
-                    self.stockfish_wins.fetch_add(1, Ordering::Relaxed);
-                    self.stockfish_white_wins.fetch_add(1, Ordering::Relaxed);
-                } else {
-                    self.reckless_wins.fetch_add(1, Ordering::Relaxed);
-                    self.reckless_white_wins.fetch_add(1, Ordering::Relaxed);
-                }
-            }
-            GameResult::BlackWins => {
-                if stockfish_is_white {
-                    self.reckless_wins.fetch_add(1, Ordering::Relaxed);
-                    self.reckless_black_wins.fetch_add(1, Ordering::Relaxed);
-                } else {
-                    self.stockfish_wins.fetch_add(1, Ordering::Relaxed);
-                    self.stockfish_black_wins.fetch_add(1, Ordering::Relaxed);
-                }
-            }
-            GameResult::Draw => {
-                self.draws.fetch_add(1, Ordering::Relaxed);
-            }
+    /// TOML or YAML file with the match settings
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Number of games to play [default: 1000000]
+    #[arg(short, long)]
+    games: Option<u64>,
+
+    /// Path to stockfish engine [default: stockfish]
+    #[arg(long)]
+    stockfish_path: Option<String>,
+
+    /// Path to reckless engine [default: reckless]
+    #[arg(long)]
+    reckless_path: Option<String>,
+
+    /// Time limit per move in milliseconds [default: 100]
+    #[arg(long)]
+    movetime_ms: Option<u64>,
+
+    /// Maximum moves per game before declaring a draw [default: 500]
+    #[arg(long)]
+    max_moves: Option<u32>,
+
+    /// Number of parallel workers (engine pairs) [default: 12]
+    #[arg(short, long)]
+    workers: Option<usize>,
+
+    /// Seed for opening order and colours, so every game can be reproduced
+    /// [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Database file to store games, moves and evaluations in
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// PGN file to append finished games to
+    #[arg(long)]
+    pgn: Option<PathBuf>,
+
+    /// JSON lines file to append finished games to
+    #[arg(long)]
+    jsonl: Option<PathBuf>,
+
+    /// EPD or FEN file of starting positions, each played with both colours
+    #[arg(long)]
+    openings: Option<PathBuf>,
+
+    /// Pin each worker's engines to cores of their own (Linux only)
+    #[arg(long)]
+    pin_cores: bool,
+
+    /// Largest address space of an engine process, in MiB (Linux only)
+    #[arg(long)]
+    memory_limit_mb: Option<u64>,
+
+    /// CPU time an engine process may use over its lifetime, in seconds (Linux only)
+    #[arg(long)]
+    cpu_limit_secs: Option<u64>,
+
+    /// Let every worker search at will, or share the cores between all
+    /// workers' searches so that more workers than cores can play [default: pairs]
+    #[arg(long, value_enum)]
+    concurrency: Option<Concurrency>,
+
+    /// Forfeit an engine that exceeds its time by more than this many milliseconds
+    #[arg(long)]
+    time_margin_ms: Option<u64>,
+
+    /// Let engines think on their opponent's time, unless searches share the cores
+    #[arg(long)]
+    ponder: bool,
+
+    /// Address to serve Prometheus metrics on, e.g. 127.0.0.1:9100
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Show a live dashboard instead of log output
+    #[arg(long)]
+    tui: bool,
+
+    /// SPRT null hypothesis: Reckless's Elo advantage over Stockfish [default: 0]
+    #[arg(long, allow_negative_numbers = true)]
+    sprt_elo0: Option<f64>,
+
+    /// SPRT alternative hypothesis: Reckless's Elo advantage over Stockfish [default: 5]
+    #[arg(long, allow_negative_numbers = true)]
+    sprt_elo1: Option<f64>,
+}
+
+/// Read the configuration file, if there is one.
+fn load_config(path: Option<&Path>) -> Result<MatchConfig> {
+    path.map_or_else(|| Ok(MatchConfig::default()), MatchConfig::load)
+}
+
+impl Args {
+    /// The configuration file's settings with these flags applied on top.
+    fn resolve(&self) -> Result<MatchConfig> {
+        let mut config = load_config(self.config.as_deref())?;
+        if let Some(games) = self.games {
+            config.games = games;
         }
-        self.games_completed.fetch_add(1, Ordering::Relaxed);
+        if let Some(workers) = self.workers {
+            config.workers = workers;
+        }
+        // Fix the seed now so it is recorded with the rest of the settings
+        config.seed = Some(self.seed.or(config.seed).unwrap_or_else(rand::random));
+        if let Some(path) = &self.stockfish_path {
+            config.engines.stockfish.path.clone_from(path);
+        }
+        if let Some(path) = &self.reckless_path {
+            config.engines.reckless.path.clone_from(path);
+        }
+        if let Some(movetime_ms) = self.movetime_ms {
+            config.time_control.movetime_ms = movetime_ms;
+        }
+        if let Some(margin_ms) = self.time_margin_ms {
+            config.time_control.margin_ms = Some(margin_ms);
+        }
+        config.time_control.ponder |= self.ponder;
+        if let Some(max_moves) = self.max_moves {
+            config.adjudication.max_moves = max_moves;
+        }
+        if self.openings.is_some() {
+            config.openings.clone_from(&self.openings);
+        }
+        if self.db.is_some() {
+            config.output.db.clone_from(&self.db);
+        }
+        if self.pgn.is_some() {
+            config.output.pgn.clone_from(&self.pgn);
+        }
+        if self.jsonl.is_some() {
+            config.output.jsonl.clone_from(&self.jsonl);
+        }
+        if self.metrics_addr.is_some() {
+            config.metrics_addr = self.metrics_addr;
+        }
+        config.tui |= self.tui;
+        config.resources.pin_cores |= self.pin_cores;
+        if self.memory_limit_mb.is_some() {
+            config.resources.memory_mb = self.memory_limit_mb;
+        }
+        if self.cpu_limit_secs.is_some() {
+            config.resources.cpu_seconds = self.cpu_limit_secs;
+        }
+        if let Some(concurrency) = self.concurrency {
+            config.resources.concurrency = concurrency;
+        }
+        if let Some(elo0) = self.sprt_elo0 {
+            config.sprt.elo0 = elo0;
+        }
+        if let Some(elo1) = self.sprt_elo1 {
+            config.sprt.elo1 = elo1;
+        }
+        // A ponder search holding a slot could keep its opponent from ever
+        // getting one, and without one it would search on cores promised to
+        // another engine
+        if config.time_control.ponder && config.resources.concurrency == Concurrency::Searches {
+            return Err(eyre!(
+                "Pondering cannot be combined with searches sharing the cores"
+            ));
+        }
+        config.resources.check_ponder(config.time_control.ponder)?;
+        Ok(config)
     }
+}
+
+/// Settings for the `coordinator` subcommand.
+#[derive(clap::Args, Debug)]
+struct CoordinatorArgs {
+    /// Address to accept worker connections on
+    #[arg(long, default_value = "0.0.0.0:7878")]
+    listen: SocketAddr,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+/// Settings for the `worker` subcommand.
+#[derive(clap::Args, Debug)]
+struct WorkerArgs {
+    /// Address of the coordinator, e.g. 10.0.0.1:7878
+    #[arg(long)]
+    coordinator: String,
+
+    /// TOML or YAML file to take the engines and their options from
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Path to stockfish engine [default: stockfish]
+    #[arg(long)]
+    stockfish_path: Option<String>,
+
+    /// Path to reckless engine [default: reckless]
+    #[arg(long)]
+    reckless_path: Option<String>,
+
+    /// Number of parallel workers (engine pairs) [default: 12]
+    #[arg(short, long)]
+    workers: Option<usize>,
+}
+
+/// Output format for `print-config`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+/// Settings for the `print-config` subcommand.
+#[derive(clap::Args, Debug)]
+struct PrintConfigArgs {
+    /// Format to print the configuration in
+    #[arg(long, value_enum, default_value = "toml")]
+    format: ConfigFormat,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+/// Settings for the `replay` subcommand.
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// Number of the game to replay
+    #[arg(long)]
+    game: u64,
+
+    /// Database file the game is stored in
+    #[arg(long, default_value = "results.db")]
+    db: PathBuf,
 
-    fn total_games(&self) -> u64 {
-        self.games_completed.load(Ordering::Relaxed)
+    /// Run the game belongs to [default: the latest run with that game]
+    #[arg(long)]
+    run: Option<i64>,
+
+    /// Also play the game again with the run's engines and settings
+    #[arg(long)]
+    live: bool,
+
+    /// File to log every UCI line of the live game to
+    #[arg(long, requires = "live")]
+    transcript: Option<PathBuf>,
+}
+
+/// One of the two engines of the configuration.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum EngineName {
+    Stockfish,
+    Reckless,
+}
+
+impl EngineName {
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Stockfish => "stockfish",
+            Self::Reckless => "reckless",
+        }
+    }
+
+    /// This engine's settings in `config`.
+    const fn config(self, config: &mut MatchConfig) -> &mut EngineConfig {
+        match self {
+            Self::Stockfish => &mut config.engines.stockfish,
+            Self::Reckless => &mut config.engines.reckless,
+        }
     }
+}
+
+/// The engine a subcommand works with, and where its settings come from.
+#[derive(clap::Args, Debug)]
+struct EngineChoice {
+    /// Which engine to use
+    #[arg(long, value_enum, default_value = "reckless")]
+    engine: EngineName,
 
-    #[allow(clippy::cast_precision_loss)]
-    fn print_summary(&self) {
-        let total = self.total_games();
-        let stockfish_wins = self.stockfish_wins.load(Ordering::Relaxed);
-        let reckless_wins = self.reckless_wins.load(Ordering::Relaxed);
-        let draws = self.draws.load(Ordering::Relaxed);
+    /// TOML or YAML file to take the engine and its options from
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Path to the engine [default: from the configuration file]
+    #[arg(long)]
+    engine_path: Option<String>,
+}
 
-        if total == 0 {
-            tracing::info!("No games played");
-            return;
+impl EngineChoice {
+    /// The configuration, with `--engine-path` applied to the chosen engine.
+    fn load(&self) -> Result<MatchConfig> {
+        let mut config = load_config(self.config.as_deref())?;
+        if let Some(path) = &self.engine_path {
+            self.engine.config(&mut config).path.clone_from(path);
         }
+        Ok(config)
+    }
+}
+
+/// The positions, engine and limits of the `analyse` and `testsuite` subcommands.
+#[derive(clap::Args, Debug)]
+struct EpdSearchArgs {
+    /// EPD or FEN file of positions to search
+    #[arg(long)]
+    epd: PathBuf,
+
+    #[command(flatten)]
+    choice: EngineChoice,
 
-        let stockfish_pct = (stockfish_wins as f64 / total as f64) * 100.0;
-        let reckless_pct = (reckless_wins as f64 / total as f64) * 100.0;
-        let draw_pct = (draws as f64 / total as f64) * 100.0;
+    /// Search every position to this depth
+    #[arg(
+        long,
+        required_unless_present = "movetime_ms",
+        conflicts_with = "movetime_ms"
+    )]
+    depth: Option<u32>,
 
-        tracing::info!("=== Match Results ===");
+    /// Search every position for this many milliseconds
+    #[arg(long)]
+    movetime_ms: Option<u64>,
+
+    /// Number of engines searching in parallel [default: from the configuration file]
+    #[arg(short, long)]
+    workers: Option<usize>,
+}
+
+impl EpdSearchArgs {
+    /// The engine's settings, the number of workers, the limits and the
+    /// positions to search.
+    fn load(&self) -> Result<(EngineConfig, usize, SearchLimits, Vec<Epd>)> {
+        let mut config = self.choice.load()?;
+        let workers = self.workers.unwrap_or(config.workers);
+        let engine = self.choice.engine.config(&mut config);
+        let limits = match (self.depth, self.movetime_ms) {
+            (Some(depth), _) => SearchLimits::Depth(depth),
+            (None, Some(movetime_ms)) => SearchLimits::Movetime(movetime_ms),
+            (None, None) => return Err(eyre!("Give --depth or --movetime-ms")),
+        };
+        let positions = Epd::load(&self.epd)?;
         tracing::info!(
-            "Total games: {total}, Stockfish: {stockfish_wins} ({stockfish_pct:.1}%), Reckless: {reckless_wins} ({reckless_pct:.1}%), Draws: {draws} ({draw_pct:.1}%)"
+            positions = positions.len(),
+            workers,
+            engine = self.choice.engine.label(),
+            "Searching"
         );
+        Ok((engine.clone(), workers, limits, positions))
+    }
+}
+
+/// Settings for the `analyse` subcommand.
+#[derive(clap::Args, Debug)]
+struct AnalyseArgs {
+    #[command(flatten)]
+    search: EpdSearchArgs,
+
+    /// JSON lines file to write the results to
+    #[arg(long)]
+    jsonl: Option<PathBuf>,
+
+    /// EPD file to write the positions to, with the engine's `bm`, `ce` and `pv`
+    #[arg(long)]
+    out_epd: Option<PathBuf>,
+
+    /// Have the engine report this many lines in every position, not just the best
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    multipv: Option<u32>,
+}
+
+/// Settings for the `perft` subcommand.
+#[derive(clap::Args, Debug)]
+struct PerftArgs {
+    #[command(flatten)]
+    choice: EngineChoice,
+
+    /// Plies to count to
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    depth: u32,
+
+    /// EPD or FEN file of positions to check [default: the standard perft positions]
+    #[arg(long)]
+    epd: Option<PathBuf>,
+
+    /// A position to check, instead of the standard ones
+    #[arg(long, conflicts_with = "epd")]
+    fen: Vec<String>,
+}
+
+/// Settings for the `blunders` subcommand.
+#[derive(clap::Args, Debug)]
+struct BlundersArgs {
+    /// PGN or JSON lines file of games, as written with --pgn or --jsonl
+    #[arg(long)]
+    games: PathBuf,
+
+    #[command(flatten)]
+    choice: EngineChoice,
+
+    /// Depth the referee searches every position to
+    #[arg(long, default_value_t = 12)]
+    depth: u32,
+
+    /// Flag moves that lose more than this many centipawns
+    #[arg(long, default_value_t = 200)]
+    threshold_cp: i32,
+
+    /// Number of referees searching in parallel [default: from the configuration file]
+    #[arg(short, long)]
+    workers: Option<usize>,
+
+    /// PGN file to write the games to, with blunders marked `??`
+    #[arg(long)]
+    out_pgn: Option<PathBuf>,
+}
+
+/// Settings for the `book-gen` subcommand.
+#[derive(clap::Args, Debug)]
+struct BookGenArgs {
+    #[command(flatten)]
+    choice: EngineChoice,
+
+    /// Plies from the starting position to the book's positions
+    #[arg(long, default_value_t = 8)]
+    plies: usize,
+
+    /// Lines the engine reports in every position
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    multipv: u32,
+
+    /// Follow lines scored at most this many centipawns below the best one
+    #[arg(long, default_value_t = 30)]
+    margin_cp: i32,
+
+    /// Keep positions scored at most this many centipawns from level
+    #[arg(long, default_value_t = 50)]
+    window_cp: i32,
+
+    /// Search every position to this depth
+    #[arg(long, conflicts_with = "movetime_ms")]
+    depth: Option<u32>,
+
+    /// Search every position for this many milliseconds
+    #[arg(long)]
+    movetime_ms: Option<u64>,
+
+    /// File to write the book to, as PGN if it ends in .pgn and EPD otherwise
+    #[arg(long)]
+    out: PathBuf,
+}
+
+/// Settings for the `similarity` subcommand.
+#[derive(clap::Args, Debug)]
+struct SimilarityArgs {
+    /// EPD or FEN file of positions to search
+    #[arg(long)]
+    epd: PathBuf,
+
+    /// An engine to compare, as LABEL=PATH [default: the configuration's two engines]
+    #[arg(long = "engine", value_name = "LABEL=PATH")]
+    engines: Vec<String>,
+
+    /// A UCI option for one of the engines, as LABEL:NAME=VALUE
+    #[arg(long = "option", value_name = "LABEL:NAME=VALUE")]
+    options: Vec<String>,
+
+    /// TOML or YAML file to take the engines and their options from
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Search every position for this many nodes
+    #[arg(
+        long,
+        required_unless_present = "movetime_ms",
+        conflicts_with = "movetime_ms"
+    )]
+    nodes: Option<u64>,
+
+    /// Search every position for this many milliseconds
+    #[arg(long)]
+    movetime_ms: Option<u64>,
+
+    /// Number of copies of each engine searching in parallel [default: from the configuration file]
+    #[arg(short, long)]
+    workers: Option<usize>,
+}
+
+impl SimilarityArgs {
+    /// The engines to compare, with their options applied.
+    fn contestants(&self, config: &MatchConfig) -> Result<Vec<similarity::Contestant>> {
+        let mut contestants = if self.engines.is_empty() {
+            vec![
+                similarity::Contestant {
+                    label: "stockfish".to_string(),
+                    config: config.engines.stockfish.clone(),
+                },
+                similarity::Contestant {
+                    label: "reckless".to_string(),
+                    config: config.engines.reckless.clone(),
+                },
+            ]
+        } else {
+            self.engines
+                .iter()
+                .map(|spec| similarity::Contestant::parse(spec))
+                .collect::<Result<_>>()?
+        };
+        for option in &self.options {
+            let (label, setting) = option
+                .split_once(':')
+                .ok_or_else(|| eyre!("Expected LABEL:NAME=VALUE, got '{option}'"))?;
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| eyre!("Expected LABEL:NAME=VALUE, got '{option}'"))?;
+            let contestant = contestants
+                .iter_mut()
+                .find(|contestant| contestant.label == label)
+                .ok_or_else(|| eyre!("No engine labelled '{label}'"))?;
+            contestant
+                .config
+                .options
+                .insert(name.to_string(), OptionValue::Text(value.to_string()));
+        }
+        Ok(contestants)
+    }
+}
+
+/// Settings for the `tune` subcommand.
+#[derive(clap::Args, Debug)]
+struct TuneArgs {
+    /// A spin option of Reckless to tune, optionally with how far to perturb it
+    /// by the end of the run [default: a twentieth of its range]
+    #[arg(long = "param", value_name = "NAME[:C_END]", required = true)]
+    params: Vec<String>,
+
+    /// Number of SPSA iterations to plan the run for, or to extend a resumed run to
+    /// [default: 1000, or the checkpoint's]
+    #[arg(long)]
+    iterations: Option<u64>,
+
+    /// Game pairs against Stockfish for each side of each iteration
+    #[arg(long, default_value_t = 1)]
+    pairs: u64,
+
+    /// File the run is saved to after every iteration, and resumed from if it exists
+    #[arg(long, default_value = "spsa.json")]
+    checkpoint: PathBuf,
+
+    /// CSV file to write each iteration's values to at the end
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    /// TOML or YAML file with the engines, time control and openings
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Path to stockfish engine [default: stockfish]
+    #[arg(long)]
+    stockfish_path: Option<String>,
+
+    /// Path to reckless engine [default: reckless]
+    #[arg(long)]
+    reckless_path: Option<String>,
+
+    /// Time limit per move in milliseconds [default: 100]
+    #[arg(long)]
+    movetime_ms: Option<u64>,
+
+    /// Maximum moves per game before declaring a draw [default: 500]
+    #[arg(long)]
+    max_moves: Option<u32>,
+
+    /// Number of parallel workers (engine pairs), split between the two sides [default: 12]
+    #[arg(short, long)]
+    workers: Option<usize>,
+
+    /// Seed for the perturbations and openings [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl TuneArgs {
+    /// The configuration file's settings with these flags applied on top.
+    fn resolve(&self) -> Result<MatchConfig> {
+        let mut config = load_config(self.config.as_deref())?;
+        if let Some(path) = &self.stockfish_path {
+            config.engines.stockfish.path.clone_from(path);
+        }
+        if let Some(path) = &self.reckless_path {
+            config.engines.reckless.path.clone_from(path);
+        }
+        if let Some(movetime_ms) = self.movetime_ms {
+            config.time_control.movetime_ms = movetime_ms;
+        }
+        if let Some(max_moves) = self.max_moves {
+            config.adjudication.max_moves = max_moves;
+        }
+        if let Some(workers) = self.workers {
+            config.workers = workers;
+        }
+        config.seed = Some(self.seed.or(config.seed).unwrap_or_else(rand::random));
+        config.tui = false;
+        Ok(config)
+    }
+
+    /// The names of the options to tune, and how far to perturb them by the
+    /// end of the run if given.
+    fn param_specs(&self) -> Result<Vec<(&str, Option<f64>)>> {
+        self.params
+            .iter()
+            .map(|spec| match spec.split_once(':') {
+                Some((name, c_end)) => c_end
+                    .parse()
+                    .map(|c_end| (name, Some(c_end)))
+                    .map_err(|e| eyre!("Invalid C_END in '{spec}': {e}")),
+                None => Ok((spec.as_str(), None)),
+            })
+            .collect()
+    }
+
+    /// The parameters to tune, starting from Reckless's defaults.
+    async fn params(&self, config: &MatchConfig) -> Result<Vec<tune::Param>> {
+        let specs = self.param_specs()?;
+        let mut reckless = AnyEngine::start(&config.engines.reckless, "reckless").await?;
+        let params = specs
+            .into_iter()
+            .map(|(name, c_end)| tune::Param::from_engine(reckless.info(), name, c_end))
+            .collect();
+        reckless.quit().await.ok();
+        params
+    }
+}
+
+/// Settings for the `testsuite` subcommand.
+#[derive(clap::Args, Debug)]
+struct TestsuiteArgs {
+    #[command(flatten)]
+    search: EpdSearchArgs,
+}
+
+/// Filters for the `query` subcommand.
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    /// Database file to search
+    #[arg(long, default_value = "results.db")]
+    db: PathBuf,
+
+    /// Only games played by this engine (`stockfish` or `reckless`)
+    #[arg(long)]
+    engine: Option<String>,
+
+    /// Only games where the engine played this colour
+    #[arg(long, value_enum, requires = "engine")]
+    color: Option<Side>,
+
+    /// Only games with this outcome for --engine, which a win or loss needs
+    #[arg(long, value_enum)]
+    outcome: Option<Outcome>,
+
+    /// Only games in this opening: a name like `sicilian` or UCI moves like "e2e4 c7c5".
+    /// Games started from a FEN never match
+    #[arg(long)]
+    opening: Option<String>,
+
+    /// Only games where the engine's eval dropped by more than this many
+    /// centipawns between two of its moves
+    #[arg(long)]
+    eval_drop: Option<i32>,
+
+    /// Maximum number of games to list
+    #[arg(long)]
+    limit: Option<u64>,
+}
+
+/// Describe the engines taking part in a run, starting each one briefly to
+/// learn its identity.
+async fn probe_engines(config: &MatchConfig) -> Result<Vec<EngineMetadata>> {
+    let mut engines = Vec::new();
+    for (label, engine_config) in [
+        ("stockfish", &config.engines.stockfish),
+        ("reckless", &config.engines.reckless),
+    ] {
+        let path = &engine_config.path;
+        let mut engine = AnyEngine::new(path, engine_config.protocol, label).await?;
+        let options = if engine_config.options.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&engine_config.options)?)
+        };
+        let binary_sha256 = db::binary_sha256(path)
+            .inspect_err(
+                |e| tracing::warn!(engine = label, error = %e, "Cannot hash engine binary"),
+            )
+            .ok();
+        let info = engine.info();
         tracing::info!(
-            "Stockfish as White: {} wins, as Black: {} wins",
-            self.stockfish_white_wins.load(Ordering::Relaxed),
-            self.stockfish_black_wins.load(Ordering::Relaxed)
+            engine = label,
+            name = info.name.as_deref().unwrap_or("?"),
+            author = info.author.as_deref().unwrap_or("?"),
+            sha256 = binary_sha256.as_deref().unwrap_or("?"),
+            "Engine identified"
         );
-        tracing::info!(
-            "Reckless as White: {} wins, as Black: {} wins",
-            self.reckless_white_wins.load(Ordering::Relaxed),
-            self.reckless_black_wins.load(Ordering::Relaxed)
+        engines.push(EngineMetadata {
+            label: label.to_string(),
+            path: path.clone(),
+            id_name: info.name.clone(),
+            id_author: info.author.clone(),
+            options,
+            binary_sha256,
+            uci_options: Some(serde_json::to_string(&info.options)?),
+        });
+        engine.quit().await.ok();
+    }
+    Ok(engines)
+}
+
+/// Play games for a coordinator, with engines from the configuration file
+/// and flags.
+async fn run_worker(worker: WorkerArgs) -> Result<()> {
+    let mut config = load_config(worker.config.as_deref())?;
+    if let Some(path) = worker.stockfish_path {
+        config.engines.stockfish.path = path;
+    }
+    if let Some(path) = worker.reckless_path {
+        config.engines.reckless.path = path;
+    }
+    let workers = worker.workers.unwrap_or(config.workers);
+    config.resources.check(workers, config.threads())?;
+    distributed::run_worker(
+        &worker.coordinator,
+        &config.engines.stockfish,
+        &config.engines.reckless,
+        workers,
+        config.resources,
+    )
+    .await
+}
+
+/// Re-check a stored game against its moves and its run's settings, and
+/// optionally play it again.
+async fn run_replay(args: ReplayArgs) -> Result<()> {
+    let db = ResultsDb::open(&args.db)?;
+    let game = db
+        .find_game(args.run, args.game)?
+        .ok_or_else(|| eyre!("No game {} in {}", args.game, args.db.display()))?;
+    println!(
+        "run {} game {}: {} vs {} {} by {} in {} plies",
+        game.run_id,
+        game.game_num,
+        game.white,
+        game.black,
+        game.result,
+        game.termination,
+        game.ply_count
+    );
+
+    // Runs from before configuration files stored settings in another shape
+    let config = serde_json::from_str::<MatchConfig>(&db.run_settings(game.run_id)?)
+        .inspect_err(|e| tracing::warn!(error = %e, "Cannot read the run's settings"))
+        .ok();
+    let verification = replay::verify(&game, config.as_ref().map(|c| c.adjudication.max_moves))?;
+    println!(
+        "Moves and result verified, final position {}",
+        verification.final_fen()
+    );
+
+    let Some(config) = config else {
+        if args.live {
+            return Err(eyre!("Cannot replay live without the run's settings"));
+        }
+        return Ok(());
+    };
+    let params = config.to_match()?.schedule().game(game.game_num);
+    replay::check_params(&game, &params)?;
+    println!(
+        "Opening and colours match seed {}",
+        config.seed.unwrap_or_default()
+    );
+
+    if args.live {
+        let transcript = args
+            .transcript
+            .as_deref()
+            .map(File::create)
+            .transpose()?
+            .map(Transcript::new);
+        let record = replay::rerun(&config, &params, transcript).await?;
+        println!(
+            "Replayed live: {} by {} in {} plies",
+            record.result.as_str(),
+            record.termination.as_str(),
+            record.plies.len()
         );
+        match replay::first_difference(&game, &record) {
+            Some(ply) => println!("Moves differ from ply {ply}"),
+            None => println!("Moves are identical"),
+        }
     }
+    Ok(())
+}
 
-    fn print_progress(&self) {
-        let total = self.total_games();
-        let stockfish_wins = self.stockfish_wins.load(Ordering::Relaxed);
-        let reckless_wins = self.reckless_wins.load(Ordering::Relaxed);
-        let draws = self.draws.load(Ordering::Relaxed);
+/// Analyse the positions of an EPD file, printing each verdict as it comes
+/// in and writing them in file order as soon as the positions before them
+/// are done.
+async fn run_analyse(args: AnalyseArgs) -> Result<()> {
+    let (mut engine, workers, limits, positions) = args.search.load()?;
+    if let Some(multipv) = args.multipv {
+        if engine.protocol != Protocol::Uci {
+            return Err(eyre!("--multipv needs a UCI engine"));
+        }
+        // Checked against the engine's limits as it starts
+        engine
+            .options
+            .insert("MultiPV".to_string(), OptionValue::Int(multipv.into()));
+    }
+    // Line buffered, so that a run cut short keeps everything analysed so far
+    let create = |path: &Option<PathBuf>| path.as_ref().map(File::create).transpose();
+    let mut jsonl = create(&args.jsonl)?.map(std::io::LineWriter::new);
+    let mut out_epd = create(&args.out_epd)?.map(std::io::LineWriter::new);
+    let results = analysis::analyse(
+        &engine,
+        args.search.choice.engine.label(),
+        positions,
+        limits,
+        workers,
+        |analysis| {
+            let name = analysis
+                .epd
+                .operand("id")
+                .unwrap_or(&analysis.epd.fen)
+                .to_string();
+            let pv = analysis.san_pv().unwrap_or_default().join(" ");
+            println!(
+                "{name}: {} {} depth {} pv {pv}",
+                analysis
+                    .epd
+                    .operand("bm")
+                    .unwrap_or(&analysis.search.best_move),
+                analysis
+                    .search
+                    .score
+                    .map_or_else(|| "?".to_string(), |score| score.to_string()),
+                analysis
+                    .search
+                    .depth
+                    .map_or_else(|| "?".to_string(), |depth| depth.to_string()),
+            );
+            let position = analysis.epd.position().unwrap_or_default();
+            for line in analysis.search.lines.iter().skip(1) {
+                println!(
+                    "  line {}: {} pv {}",
+                    line.multipv,
+                    line.score
+                        .map_or_else(|| "?".to_string(), |score| score.to_string()),
+                    epd::san_line(&position, &line.pv).join(" ")
+                );
+            }
+        },
+        |analysis| {
+            if let Some(file) = &mut jsonl {
+                writeln!(file, "{}", analysis.to_json()?)?;
+            }
+            if let Some(file) = &mut out_epd {
+                writeln!(file, "{}", analysis.epd)?;
+            }
+            Ok(())
+        },
+    )
+    .await?;
 
-        tracing::info!(
-            games = total,
-            stockfish = stockfish_wins,
-            reckless = reckless_wins,
-            draws = draws,
-            "Progress"
+    let failed = results.iter().filter(|analysis| analysis.is_none()).count();
+    if failed > 0 {
+        tracing::warn!(
+            failed,
+            positions = results.len(),
+            "Some positions could not be analysed"
         );
     }
+    Ok(())
 }
 
-/// Message sent from workers to aggregator.
-struct GameCompleted {
-    result: GameResult,
-    stockfish_is_white: bool,
+/// Score an engine on an EPD test suite, printing each position's outcome as
+/// it comes in and the totals at the end.
+async fn run_testsuite(args: TestsuiteArgs) -> Result<()> {
+    let (engine, workers, limits, positions) = args.search.load()?;
+    let expected = positions
+        .iter()
+        .map(testsuite::Expected::from_epd)
+        .collect::<Result<Vec<_>>>()?;
+    let results = analysis::analyse(
+        &engine,
+        args.search.choice.engine.label(),
+        positions,
+        limits,
+        workers,
+        |_| {},
+        |_| Ok(()),
+    )
+    .await?;
+    let results = analysis::require_all(results)?;
+
+    let mut report = testsuite::Report::default();
+    for (expected, analysis) in expected.iter().zip(&results) {
+        let outcome = testsuite::score(expected, analysis);
+        report.add(&outcome);
+        let name = analysis.epd.operand("id").unwrap_or(&analysis.epd.fen);
+        let played = analysis
+            .epd
+            .operand("bm")
+            .unwrap_or(&analysis.search.best_move);
+        let verdict = if outcome.solved { "solved" } else { "failed" };
+        let found = outcome
+            .found_ms
+            .map(|found_ms| format!(" in {found_ms} ms"))
+            .unwrap_or_default();
+        let depth = outcome
+            .found_depth
+            .map(|depth| format!(" at depth {depth}"))
+            .unwrap_or_default();
+        // Only worth a mention if the engine wavered after finding the move
+        let settled = match (outcome.settled_ms, outcome.settled_depth) {
+            (Some(ms), Some(depth)) if outcome.settled_ms != outcome.found_ms => {
+                format!(", settled in {ms} ms at depth {depth}")
+            }
+            _ => String::new(),
+        };
+        let points = outcome
+            .points
+            .map(|(points, max)| format!(", {points}/{max} points"))
+            .unwrap_or_default();
+        println!("{name}: {verdict} with {played}{found}{depth}{settled}{points}");
+    }
+    print_testsuite_report(&report);
+    Ok(())
 }
 
-/// Run a worker that plays games continuously.
-async fn run_worker(
-    worker_id: usize,
-    args: Args,
-    game_counter: Arc<AtomicU64>,
-    total_games: u64,
-    tx: mpsc::Sender<GameCompleted>,
-) -> Result<()> {
-    use crate::engine::UciEngine;
+/// Print a test suite's totals and its score on each STS theme.
+fn print_testsuite_report(report: &testsuite::Report) {
+    let percent = |part: u32, whole: u32| f64::from(part) * 100.0 / f64::from(whole.max(1));
+    let total = report.total;
+    print!(
+        "Solved {}/{} ({:.1}%)",
+        total.solved,
+        total.positions,
+        percent(total.solved, total.positions)
+    );
+    match report.mean_found_ms() {
+        Some(found_ms) => println!(", mean time to solution {found_ms} ms"),
+        None => println!(),
+    }
+    for (theme, tally) in &report.themes {
+        println!(
+            "{theme}: {}/{} points ({:.1}%), {}/{} solved",
+            tally.points,
+            tally.max_points,
+            percent(tally.points, tally.max_points),
+            tally.solved,
+            tally.positions
+        );
+    }
+    if total.max_points > 0 {
+        println!(
+            "STS score: {}/{} points ({:.1}%)",
+            total.points,
+            total.max_points,
+            percent(total.points, total.max_points)
+        );
+    }
+}
 
-    // Each worker has its own engine pair
-    let mut stockfish =
-        UciEngine::new(&args.stockfish_path, &format!("stockfish-{worker_id}")).await?;
-    let mut reckless =
-        UciEngine::new(&args.reckless_path, &format!("reckless-{worker_id}")).await?;
+/// Check the engine's `go perft` against the move generator on each position,
+/// printing the moves it counts differently.
+///
+/// # Errors
+/// Returns an error if any position doesn't match, so scripts can gate on it.
+async fn run_perft(args: PerftArgs) -> Result<()> {
+    let config = args.choice.load()?;
+    let engine_config = match args.choice.engine {
+        EngineName::Stockfish => &config.engines.stockfish,
+        EngineName::Reckless => &config.engines.reckless,
+    };
+    if engine_config.protocol != Protocol::Uci {
+        return Err(eyre!("perft needs a UCI engine"));
+    }
+    let positions = if let Some(path) = &args.epd {
+        Epd::load(path)?
+    } else if args.fen.is_empty() {
+        perft::STANDARD_POSITIONS
+            .iter()
+            .map(|fen| Epd::parse(fen))
+            .collect::<Result<_>>()?
+    } else {
+        args.fen
+            .iter()
+            .map(|fen| Epd::parse(fen))
+            .collect::<Result<_>>()?
+    };
 
-    let runner = GameRunner::new(args.movetime_ms, args.max_moves);
+    let mut engine = UciEngine::start(engine_config, args.choice.engine.label()).await?;
+    let mut failed = 0;
+    let result = async {
+        for epd in &positions {
+            let check = perft::check(&mut engine, epd, args.depth).await?;
+            if check.is_ok() {
+                println!(
+                    "{}: {} nodes at depth {}, ok",
+                    check.fen, check.engine.nodes, check.depth
+                );
+                continue;
+            }
+            failed += 1;
+            println!(
+                "{}: engine {} nodes at depth {}, expected {}",
+                check.fen, check.engine.nodes, check.depth, check.expected.nodes
+            );
+            for mismatch in check.mismatches() {
+                println!("  {mismatch}");
+            }
+        }
+        Ok::<_, color_eyre::Report>(())
+    }
+    .await;
+    engine.quit().await.ok();
+    result?;
 
-    loop {
-        // Atomically claim a game number
-        let game_num = game_counter.fetch_add(1, Ordering::Relaxed);
-        if game_num >= total_games {
-            break;
+    if failed > 0 {
+        return Err(eyre!(
+            "Perft mismatch in {failed} of {} positions",
+            positions.len()
+        ));
+    }
+    println!("All {} positions match", positions.len());
+    Ok(())
+}
+
+/// Re-analyse stored games with the referee, printing each blunder and every
+/// engine's blunder rate in each phase.
+async fn run_blunders(args: BlundersArgs) -> Result<()> {
+    let mut config = args.choice.load()?;
+    let workers = args.workers.unwrap_or(config.workers);
+    let referee = args.choice.engine.config(&mut config).clone();
+    let games = blunders::load_games(&args.games)?;
+    let reviews = blunders::review_games(
+        &referee,
+        args.choice.engine.label(),
+        games,
+        SearchLimits::Depth(args.depth),
+        workers,
+    )
+    .await?;
+
+    let mut report = blunders::Report::default();
+    let mut out = args
+        .out_pgn
+        .as_deref()
+        .map(File::create)
+        .transpose()?
+        .map(std::io::BufWriter::new);
+    for review in &reviews {
+        report.add(review, args.threshold_cp);
+        let game = &review.game;
+        for blunder in review.blunders(args.threshold_cp) {
+            println!(
+                "game {}: {}?? by {} in the {}, {} -> {}",
+                game.round,
+                review.move_text(blunder.ply),
+                game.mover(blunder.ply),
+                blunder.phase.as_str(),
+                Score::Cp(blunder.before_cp),
+                Score::Cp(blunder.after_cp)
+            );
         }
+        if let Some(out) = &mut out {
+            let pgn = pgn::format_annotated_game(
+                &game.record,
+                game.white.as_str(),
+                game.black.as_str(),
+                game.round,
+                &review.annotations(args.threshold_cp),
+            )?;
+            out.write_all(pgn.as_bytes())?;
+        }
+    }
+    if let Some(out) = &mut out {
+        out.flush()?;
+    }
+
+    for (engine, phases) in &report.engines {
+        let phases: Vec<String> = blunders::Phase::ALL
+            .into_iter()
+            .map(|phase| {
+                let tally = phases.get(&phase).copied().unwrap_or_default();
+                format!("{} {tally}", phase.as_str())
+            })
+            .collect();
+        println!(
+            "{engine}: {}, total {}",
+            phases.join(", "),
+            report.total(engine)
+        );
+    }
+    Ok(())
+}
 
-        // Alternate colors based on game number
-        let stockfish_is_white = game_num % 2 == 0;
+/// Grow an opening book with the chosen engine and write it out.
+async fn run_book_gen(args: BookGenArgs) -> Result<()> {
+    let mut config = args.choice.load()?;
+    let engine_config = args.choice.engine.config(&mut config);
+    if engine_config.protocol != Protocol::Uci {
+        return Err(eyre!("book-gen needs a UCI engine"));
+    }
+    let settings = book::Settings {
+        plies: args.plies,
+        multipv: args.multipv,
+        margin_cp: args.margin_cp,
+        window_cp: args.window_cp,
+        limits: match (args.depth, args.movetime_ms) {
+            (_, Some(movetime_ms)) => SearchLimits::Movetime(movetime_ms),
+            (depth, None) => SearchLimits::Depth(depth.unwrap_or(12)),
+        },
+    };
 
-        let result = if stockfish_is_white {
-            runner.play_game(&mut stockfish, &mut reckless).await
+    let mut engine = UciEngine::start(engine_config, args.choice.engine.label()).await?;
+    let lines = book::generate(&mut engine, &settings).await;
+    engine.quit().await.ok();
+    let lines = lines?;
+    if lines.is_empty() {
+        return Err(eyre!(
+            "No positions within {} cp of level after {} plies",
+            args.window_cp,
+            args.plies
+        ));
+    }
+
+    let pgn = args
+        .out
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pgn"));
+    let mut out = std::io::BufWriter::new(File::create(&args.out)?);
+    for (index, line) in lines.iter().enumerate() {
+        if pgn {
+            writeln!(out, "{}", line.pgn(index + 1))?;
         } else {
-            runner.play_game(&mut reckless, &mut stockfish).await
-        };
+            writeln!(out, "{}", line.epd(index + 1))?;
+        }
+    }
+    out.flush()?;
+    println!(
+        "Wrote {} positions after {} plies to {}",
+        lines.len(),
+        args.plies,
+        args.out.display()
+    );
+    Ok(())
+}
+
+/// Have every engine search the same positions and print how often each pair
+/// agrees on the move.
+async fn run_similarity(args: SimilarityArgs) -> Result<()> {
+    let config = load_config(args.config.as_deref())?;
+    let contestants = args.contestants(&config)?;
+    if contestants.len() < 2 {
+        return Err(eyre!("Give at least two engines to compare"));
+    }
+    let workers = args.workers.unwrap_or(config.workers);
+    let limits = match (args.nodes, args.movetime_ms) {
+        (Some(nodes), _) => SearchLimits::Nodes(nodes),
+        (None, Some(movetime_ms)) => SearchLimits::Movetime(movetime_ms),
+        (None, None) => return Err(eyre!("Give --nodes or --movetime-ms")),
+    };
+    let positions = Epd::load(&args.epd)?;
+    let matrix = similarity::compare(&contestants, &positions, limits, workers).await?;
+    println!(
+        "Move agreement in {} positions (%):\n{matrix}",
+        positions.len()
+    );
+    Ok(())
+}
+
+/// Tune Reckless's options with SPSA, saving the run after every iteration
+/// and printing the final values.
+async fn run_tune(args: TuneArgs) -> Result<()> {
+    let config = args.resolve()?;
+    let base = config.to_match()?;
+    let mut spsa = if args.checkpoint.exists() {
+        let mut spsa = tune::Spsa::load(&args.checkpoint)?;
+        let names: Vec<&str> = args.param_specs()?.into_iter().map(|(n, _)| n).collect();
+        spsa.check_params(&names)?;
+        if let Some(iterations) = args.iterations.filter(|&i| i != spsa.iterations) {
+            tracing::info!(
+                from = spsa.iterations,
+                to = iterations,
+                "Replanning the resumed run"
+            );
+            spsa.extend_to(iterations);
+        }
+        tracing::info!(
+            iteration = spsa.iteration,
+            iterations = spsa.iterations,
+            checkpoint = %args.checkpoint.display(),
+            "Resuming"
+        );
+        spsa
+    } else {
+        let params = args.params(&config).await?;
+        let iterations = args.iterations.unwrap_or(1000);
+        tune::Spsa::new(params, iterations, config.seed.unwrap_or_default())
+    };
+
+    while !spsa.is_done() {
+        let (plus, minus) = tune::iterate(&mut spsa, &base, args.pairs, config.workers).await?;
+        spsa.save(&args.checkpoint)?;
+        let values: Vec<String> = spsa
+            .params
+            .iter()
+            .map(|param| format!("{}={:.2}", param.name, param.value))
+            .collect();
+        tracing::info!(
+            "Iteration {}/{}: plus {plus} vs minus {minus}, {}",
+            spsa.iteration,
+            spsa.iterations,
+            values.join(" ")
+        );
+    }
+
+    if let Some(path) = &args.history {
+        let mut file = std::io::BufWriter::new(File::create(path)?);
+        let names: Vec<&str> = spsa.params.iter().map(|p| p.name.as_str()).collect();
+        writeln!(file, "iteration,{}", names.join(","))?;
+        for (iteration, values) in spsa.history.iter().enumerate() {
+            let values: Vec<String> = values.iter().map(|v| format!("{v:.3}")).collect();
+            writeln!(file, "{},{}", iteration + 1, values.join(","))?;
+        }
+        file.flush()?;
+    }
+    println!("Tuned values after {} iterations:", spsa.iteration);
+    for param in &spsa.params {
+        println!("{} = {}", param.name, param.clamped(param.value));
+    }
+    Ok(())
+}
+
+/// Print stored games matching the query filters.
+fn run_query(query: QueryArgs) -> Result<()> {
+    let db = ResultsDb::open(&query.db)?;
+    let rows = db.query(&GameFilter {
+        engine: query.engine,
+        side: query.color,
+        outcome: query.outcome,
+        opening: query.opening,
+        eval_drop: query.eval_drop,
+        limit: query.limit,
+    })?;
+
+    for row in &rows {
+        println!(
+            "run {} game {} (#{}): {} vs {} {} by {} in {} plies: {}",
+            row.run_id,
+            row.game_num,
+            row.id,
+            row.white,
+            row.black,
+            row.result,
+            row.termination,
+            row.ply_count,
+            row.moves
+        );
+    }
+    tracing::info!(games = rows.len(), "Query complete");
+    Ok(())
+}
+
+/// Open the results database and record the start of a run in it, along
+/// with the engines if they were probed.
+fn open_results_db(
+    path: &Path,
+    config: &MatchConfig,
+    engines: &[EngineMetadata],
+) -> Result<(ResultsDb, i64)> {
+    let db = ResultsDb::open(path)?;
+    let run_id = db.start_run(&serde_json::to_string(config)?)?;
+    for engine in engines {
+        db.record_engine(run_id, engine)?;
+    }
+    Ok((db, run_id))
+}
+
+/// Collect events from workers until they have all finished, recording
+/// results and driving the dashboard if there is one.
+async fn collect_results(
+    running: &mut RunningMatch,
+    stats: &MatchStats,
+    mut db: Option<&mut (ResultsDb, i64)>,
+    files: &mut GameFiles,
+    config: &MatchConfig,
+) -> Result<()> {
+    let (mut tui, mut actions) = if config.tui {
+        let (tui, actions) = Tui::start(config.workers, config.games, config.sprt);
+        (Some(tui), actions)
+    } else {
+        // Nothing ever sends on this channel, so it closes straight away
+        (None, mpsc::unbounded_channel().1)
+    };
+    let mut redraw = tokio::time::interval(Duration::from_millis(250));
+
+    loop {
+        tokio::select! {
+            event = running.next_event() => {
+                let Some(event) = event else { break };
+                if let Some(tui) = &mut tui {
+                    tui.apply(&event);
+                }
+                if matches!(event, WorkerEvent::GameFailed { .. }) {
+                    stats.record_restart();
+                }
+                let WorkerEvent::GameCompleted(msg) = event else {
+                    continue;
+                };
+                stats.record(&msg.record, msg.stockfish_is_white);
+                if let Err(e) = files.write(&msg) {
+                    tracing::error!(game = msg.game_num, error = %e, "Failed to write game");
+                }
 
-        match result {
-            Ok(result) => {
-                if tx
-                    .send(GameCompleted {
-                        result,
-                        stockfish_is_white,
-                    })
-                    .await
-                    .is_err()
-                {
-                    // Receiver dropped, stop
-                    break;
+                if let Some((db, run_id)) = db.as_deref_mut() {
+                    let (white, black) = if msg.stockfish_is_white {
+                        ("stockfish", "reckless")
+                    } else {
+                        ("reckless", "stockfish")
+                    };
+                    if let Err(e) = db.record_game(*run_id, msg.game_num, white, black, &msg.record) {
+                        tracing::error!(game = msg.game_num, error = %e, "Failed to store game");
+                    }
                 }
             }
-            Err(e) => {
-                tracing::warn!(worker = worker_id, error = %e, "Game failed, restarting engines");
-                // Restart engines on failure
-                stockfish.quit().await.ok();
-                reckless.quit().await.ok();
-                stockfish =
-                    UciEngine::new(&args.stockfish_path, &format!("stockfish-{worker_id}")).await?;
-                reckless =
-                    UciEngine::new(&args.reckless_path, &format!("reckless-{worker_id}")).await?;
+            Some(action) = actions.recv() => match action {
+                Action::Quit => running.shutdown().request(),
+                Action::SelectNext | Action::SelectPrevious => {
+                    if let Some(tui) = &mut tui {
+                        tui.select(action);
+                    }
+                }
+            },
+            _ = redraw.tick(), if tui.is_some() => {
+                if let Some(Err(e)) = tui.as_mut().map(|tui| tui.draw(stats)) {
+                    // Restore the terminal and stop reading keys, then log to the terminal
+                    tui = None;
+                    actions = mpsc::unbounded_channel().1;
+                    tracing::error!(error = %e, "Failed to draw the dashboard, leaving it");
+                }
             }
         }
     }
+    Ok(())
+}
 
-    // Cleanup
-    stockfish.quit().await.ok();
-    reckless.quit().await.ok();
-
+/// Print the resolved match configuration.
+fn run_print_config(print: &PrintConfigArgs) -> Result<()> {
+    let config = print.args.resolve()?;
+    let text = match print.format {
+        ConfigFormat::Toml => config.to_toml()?,
+        ConfigFormat::Yaml => config.to_yaml()?,
+    };
+    print!("{text}");
     Ok(())
 }
 
@@ -215,47 +1438,71 @@ async fn main() -> Result<()> {
     // Initialize tracing
     tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(tui::log_writer))
         .with(ErrorLayer::default())
         .init();
 
     color_eyre::install()?;
 
-    let args = Args::parse();
-    tracing::info!(?args, "Starting chess engine battle");
+    let cli = Cli::parse();
+    let (args, listen) = match cli.command {
+        Some(Command::Query(query)) => return run_query(query),
+        Some(Command::Worker(worker)) => return run_worker(worker).await,
+        Some(Command::Replay(replay)) => return run_replay(replay).await,
+        Some(Command::Analyse(analyse)) => return run_analyse(analyse).await,
+        Some(Command::Testsuite(testsuite)) => return run_testsuite(testsuite).await,
+        Some(Command::Perft(perft)) => return run_perft(perft).await,
+        Some(Command::Blunders(blunders)) => return run_blunders(blunders).await,
+        Some(Command::Similarity(similarity)) => return run_similarity(similarity).await,
+        Some(Command::Tune(tune)) => return run_tune(tune).await,
+        Some(Command::BookGen(book_gen)) => return run_book_gen(book_gen).await,
+        Some(Command::PrintConfig(print)) => return run_print_config(&print),
+        Some(Command::Coordinator(coordinator)) => (coordinator.args, Some(coordinator.listen)),
+        None => (cli.args, None),
+    };
+    let config = args.resolve()?;
+    tracing::info!(?config, "Starting chess engine battle");
+    let settings = config.to_match()?;
+
+    // The engines can only be identified when they run on this machine
+    let engines = if listen.is_none() {
+        probe_engines(&config).await?
+    } else {
+        Vec::new()
+    };
+
+    // Results database and game files, if requested
+    let mut db = match &config.output.db {
+        Some(path) => Some(open_results_db(path, &config, &engines)?),
+        None => None,
+    };
+    let mut files = GameFiles::open(config.output.pgn.as_deref(), config.output.jsonl.as_deref())?
+        .with_engines(&engines);
 
     let stats = Arc::new(MatchStats::default());
-    let game_counter = Arc::new(AtomicU64::new(0));
-    let total_games = args.games;
-
-    // Channel for workers to report completed games
-    let (tx, mut rx) = mpsc::channel::<GameCompleted>(args.workers * 2);
-
-    // Spawn workers
-    let mut worker_handles = Vec::new();
-    for worker_id in 0..args.workers {
-        let args_clone = args.clone();
-        let game_counter_clone = Arc::clone(&game_counter);
-        let tx_clone = tx.clone();
-
-        let handle = tokio::spawn(async move {
-            if let Err(e) = run_worker(
-                worker_id,
-                args_clone,
-                game_counter_clone,
-                total_games,
-                tx_clone,
-            )
-            .await
-            {
-                tracing::error!(worker = worker_id, error = %e, "Worker failed");
-            }
-        });
-        worker_handles.push(handle);
-    }
+    let total_games = config.games;
+    let started = Instant::now();
+    let mut running = match listen {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            tracing::info!(addr = %listener.local_addr()?, "Waiting for workers");
+            settings.coordinate(listener)
+        }
+        None => settings.start(),
+    };
 
-    // Drop our sender so rx will end when all workers are done
-    drop(tx);
+    // Stop claiming games on the first signal, abort games on the second
+    let signal_handle = tokio::spawn(shutdown::handle_signals(Arc::clone(running.shutdown())));
+
+    // Metrics endpoint, if requested
+    let metrics_handle = match config.metrics_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            tracing::info!(%addr, "Serving metrics");
+            Some(tokio::spawn(metrics::serve(listener, Arc::clone(&stats))))
+        }
+        None => None,
+    };
 
     // Progress reporting task
     let stats_clone = Arc::clone(&stats);
@@ -270,20 +1517,37 @@ async fn main() -> Result<()> {
         }
     });
 
-    // Collect results from workers
-    while let Some(msg) = rx.recv().await {
-        stats.record(msg.result, msg.stockfish_is_white);
-    }
+    collect_results(&mut running, &stats, db.as_mut(), &mut files, &config).await?;
 
-    // Cancel progress reporter
+    // Cancel progress reporter, signal handler and metrics endpoint
     progress_handle.abort();
+    signal_handle.abort();
+    if let Some(handle) = metrics_handle {
+        handle.abort();
+    }
 
     // Wait for all workers to finish
-    for handle in worker_handles {
-        handle.await.ok();
+    running.join().await;
+
+    if let Some((db, _)) = db {
+        db.close()?;
     }
 
     stats.print_summary();
+    let time_control = &config.time_control;
+    stats.print_latency(
+        time_control
+            .base_ms
+            .is_none()
+            .then_some(time_control.movetime_ms),
+    );
+    tracing::info!(
+        "Throughput: {:.0} games/hour with {} workers, {} threads per engine and {} concurrency",
+        stats.games_per_hour(started.elapsed()),
+        config.workers,
+        config.threads(),
+        config.resources.concurrency.as_str()
+    );
 
     Ok(())
 }
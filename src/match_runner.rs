@@ -0,0 +1,708 @@
+//! Running a whole match between Stockfish and Reckless.
+//!
+//! A [`Match`] describes the engines and settings. Starting it spawns one
+//! worker per engine pair, which claim game numbers from a shared counter and
+//! report back through a channel, readable either event by event from a
+//! [`RunningMatch`] or as a stream of finished games.
+
+use crate::elo::Wdl;
+use crate::engine::{AnyEngine, Engine, EngineConfig};
+use crate::game::{GameRecord, GameResult, GameRunner, GameSettings, MovePlayed, Termination};
+use crate::metrics::LatencyHistogram;
+use crate::resources::{Resources, SearchSlots};
+use crate::schedule::{GameParams, GameSchedule};
+use crate::shutdown::{self, Shutdown};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use shakmaty::{Board, Color, Position};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+
+/// How far past its movetime a move may run before the engine is flagged,
+/// allowing for scheduling and pipe latency.
+const OVERRUN_TOLERANCE_MS: u64 = 10;
+
+/// Settings for a match between Stockfish and Reckless.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub(crate) stockfish: EngineConfig,
+    pub(crate) reckless: EngineConfig,
+    pub(crate) games: u64,
+    pub(crate) workers: usize,
+    pub(crate) game: GameSettings,
+    pub(crate) openings: Vec<String>,
+    pub(crate) seed: u64,
+    pub(crate) resources: Resources,
+    pub(crate) search_slots: Option<SearchSlots>,
+    pub(crate) live_events: bool,
+}
+
+impl Match {
+    /// A single-worker, single-game match at 100ms per move from the standard
+    /// starting position, between the given engines or engine paths.
+    #[must_use]
+    pub fn new(stockfish: impl Into<EngineConfig>, reckless: impl Into<EngineConfig>) -> Self {
+        Self {
+            stockfish: stockfish.into(),
+            reckless: reckless.into(),
+            games: 1,
+            workers: 1,
+            game: GameSettings::default(),
+            openings: Vec::new(),
+            seed: 0,
+            resources: Resources::default(),
+            search_slots: None,
+            live_events: false,
+        }
+    }
+
+    /// Play `games` games, alternating colours.
+    #[must_use]
+    pub const fn with_games(mut self, games: u64) -> Self {
+        self.games = games;
+        self
+    }
+
+    /// Play on `workers` engine pairs in parallel.
+    #[must_use]
+    pub const fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Give each engine `movetime_ms` milliseconds per move.
+    #[must_use]
+    pub const fn with_movetime(mut self, movetime_ms: u64) -> Self {
+        self.game.time_control.movetime_ms = movetime_ms;
+        self
+    }
+
+    /// Declare a draw after `max_moves` moves.
+    #[must_use]
+    pub const fn with_max_moves(mut self, max_moves: u32) -> Self {
+        self.game.adjudication.max_moves = max_moves;
+        self
+    }
+
+    /// Forfeit engines that take more than `margin_ms` longer than the movetime.
+    #[must_use]
+    pub const fn with_time_margin(mut self, margin_ms: u64) -> Self {
+        self.game.time_control.margin_ms = Some(margin_ms);
+        self
+    }
+
+    /// Play every game with `settings`, replacing any time control or
+    /// adjudication set so far.
+    #[must_use]
+    pub const fn with_game_settings(mut self, settings: GameSettings) -> Self {
+        self.game = settings;
+        self
+    }
+
+    /// Start games from these FENs, shuffled by the seed, each played once
+    /// with either engine as White.
+    #[must_use]
+    pub fn with_openings(mut self, openings: Vec<String>) -> Self {
+        self.openings = openings;
+        self
+    }
+
+    /// Derive opening order and colours from `seed`, so that every game can
+    /// be reproduced from the seed and its number alone.
+    #[must_use]
+    pub const fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Pin and limit engine processes with `resources`.
+    #[must_use]
+    pub const fn with_resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Make every worker wait for a free slot in `slots` before each search,
+    /// so that more workers than cores can play without overloading them.
+    #[must_use]
+    pub fn with_search_slots(mut self, slots: SearchSlots) -> Self {
+        self.search_slots = Some(slots);
+        self
+    }
+
+    /// The schedule mapping game numbers to their openings and colours.
+    #[must_use]
+    pub fn schedule(&self) -> GameSchedule {
+        GameSchedule::new(self.seed, self.openings.clone())
+    }
+
+    /// Also report game starts and every move played, e.g. for a dashboard.
+    #[must_use]
+    pub const fn with_live_events(mut self, live_events: bool) -> Self {
+        self.live_events = live_events;
+        self
+    }
+
+    /// Spawn the workers and start playing.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[must_use]
+    pub fn start(self) -> RunningMatch {
+        let game_counter = Arc::new(AtomicU64::new(0));
+        let shutdown = Arc::new(Shutdown::new(Arc::clone(&game_counter), self.games));
+
+        // Moves are only worth a deep buffer when they are being reported
+        let capacity = if self.live_events {
+            1024
+        } else {
+            self.workers * 2
+        };
+        let (tx, events) = mpsc::channel(capacity.max(1));
+
+        let schedule = Arc::new(self.schedule());
+        let settings = Arc::new(self);
+        let workers = (0..settings.workers)
+            .map(|worker_id| {
+                let settings = Arc::clone(&settings);
+                let schedule = Arc::clone(&schedule);
+                let runner = GameRunner::from_settings(settings.game)
+                    .with_search_slots(settings.search_slots.clone());
+                let game_counter = Arc::clone(&game_counter);
+                let tx = tx.clone();
+                let abort = shutdown.subscribe();
+                tokio::spawn(async move {
+                    if let Err(e) = run_worker(
+                        worker_id,
+                        &settings,
+                        &schedule,
+                        &runner,
+                        game_counter,
+                        tx,
+                        abort,
+                    )
+                    .await
+                    {
+                        tracing::error!(worker = worker_id, error = %e, "Worker failed");
+                    }
+                })
+            })
+            .collect();
+
+        RunningMatch {
+            events,
+            shutdown,
+            workers,
+        }
+    }
+
+    /// Play the match, yielding each game as it finishes.
+    ///
+    /// A game that fails, e.g. because an engine crashed, is not played
+    /// again: its worker restarts the engines and moves on to the next game
+    /// number, so the stream can end with fewer than `games` records. The
+    /// failures are reported as [`WorkerEvent::GameFailed`] by
+    /// [`start`](Self::start), for [`MatchStats::record_restart`] to count.
+    /// Dropping the stream stops the match once the games in progress finish.
+    pub fn play(self) -> impl Stream<Item = GameRecord> + Send + Unpin {
+        self.start().records()
+    }
+}
+
+/// A match whose workers are playing.
+pub struct RunningMatch {
+    pub(crate) events: mpsc::Receiver<WorkerEvent>,
+    pub(crate) shutdown: Arc<Shutdown>,
+    pub(crate) workers: Vec<JoinHandle<()>>,
+}
+
+impl RunningMatch {
+    /// The match's shutdown state, for stopping it early.
+    #[must_use]
+    pub const fn shutdown(&self) -> &Arc<Shutdown> {
+        &self.shutdown
+    }
+
+    /// The next event from the workers, or `None` once they have all finished.
+    pub async fn next_event(&mut self) -> Option<WorkerEvent> {
+        self.events.recv().await
+    }
+
+    /// Wait for every worker to exit.
+    pub async fn join(self) {
+        for worker in self.workers {
+            worker.await.ok();
+        }
+    }
+
+    /// The finished games, ignoring all other events.
+    pub fn records(self) -> impl Stream<Item = GameRecord> + Send + Unpin {
+        ReceiverStream::new(self.events).filter_map(|event| match event {
+            WorkerEvent::GameCompleted(completed) => Some(completed.record),
+            _ => None,
+        })
+    }
+}
+
+/// Statistics for a match (thread-safe).
+#[derive(Debug, Default)]
+pub struct MatchStats {
+    pub(crate) stockfish_wins: AtomicU64,
+    pub(crate) reckless_wins: AtomicU64,
+    pub(crate) draws: AtomicU64,
+    pub(crate) stockfish_white_wins: AtomicU64,
+    pub(crate) stockfish_black_wins: AtomicU64,
+    pub(crate) reckless_white_wins: AtomicU64,
+    pub(crate) reckless_black_wins: AtomicU64,
+    pub(crate) games_completed: AtomicU64,
+    pub(crate) draws_by_termination: [AtomicU64; Termination::ALL.len()],
+    pub(crate) stockfish_time_forfeits: AtomicU64,
+    pub(crate) reckless_time_forfeits: AtomicU64,
+    pub(crate) engine_restarts: AtomicU64,
+    pub(crate) stockfish_latency: LatencyHistogram,
+    pub(crate) reckless_latency: LatencyHistogram,
+    /// Wall-clock and engine-reported time of the moves whose engine
+    /// reported how long it searched
+    pub(crate) reported_move_ms: AtomicU64,
+    pub(crate) reported_search_ms: AtomicU64,
+    pub(crate) reported_moves: AtomicU64,
+    /// Time spent sending positions, and how many were sent
+    pub(crate) position_us: AtomicU64,
+    pub(crate) positions_sent: AtomicU64,
+}
+
+impl MatchStats {
+    /// Count a finished game.
+    pub fn record(&self, record: &GameRecord, stockfish_is_white: bool) {
+        // White moves on even plies, unless the opening has Black to move
+        let white_first = record.white_moves_first();
+        for (ply, played) in record.plies.iter().enumerate() {
+            if ((ply % 2 == 0) == white_first) == stockfish_is_white {
+                self.stockfish_latency.observe(played.time_ms);
+            } else {
+                self.reckless_latency.observe(played.time_ms);
+            }
+            if let Some(search_ms) = played.search_ms {
+                self.reported_move_ms
+                    .fetch_add(played.time_ms, Ordering::Relaxed);
+                self.reported_search_ms
+                    .fetch_add(search_ms.min(played.time_ms), Ordering::Relaxed);
+                self.reported_moves.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Some(position_us) = played.position_us {
+                self.position_us.fetch_add(position_us, Ordering::Relaxed);
+                self.positions_sent.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if record.termination == Termination::TimeForfeit {
+            let white_forfeited = record.result == GameResult::BlackWins;
+            if white_forfeited == stockfish_is_white {
+                self.stockfish_time_forfeits.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.reckless_time_forfeits.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        match record.result {
+            GameResult::WhiteWins => {
+                if stockfish_is_white {
+                    self.stockfish_wins.fetch_add(1, Ordering::Relaxed);
+                    self.stockfish_white_wins.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    self.reckless_wins.fetch_add(1, Ordering::Relaxed);
+                    self.reckless_white_wins.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            GameResult::BlackWins => {
+                if stockfish_is_white {
+                    self.reckless_wins.fetch_add(1, Ordering::Relaxed);
+                    self.reckless_black_wins.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    self.stockfish_wins.fetch_add(1, Ordering::Relaxed);
+                    self.stockfish_black_wins.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            GameResult::Draw => {
+                self.draws.fetch_add(1, Ordering::Relaxed);
+                self.draws_by_termination[record.termination as usize]
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.games_completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a failed game whose engines were restarted.
+    pub fn record_restart(&self) {
+        self.engine_restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of games completed so far.
+    #[must_use]
+    pub fn total_games(&self) -> u64 {
+        self.games_completed.load(Ordering::Relaxed)
+    }
+
+    /// Games completed per hour over `elapsed`.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn games_per_hour(&self, elapsed: Duration) -> f64 {
+        self.total_games() as f64 / elapsed.as_secs_f64().max(f64::EPSILON) * 3600.0
+    }
+
+    /// The average microseconds spent sending the `position` command, over
+    /// the moves it was sent for rather than pondered on.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn position_send_us(&self) -> Option<f64> {
+        let sent = self.positions_sent.load(Ordering::Relaxed);
+        (sent > 0).then(|| self.position_us.load(Ordering::Relaxed) as f64 / sent as f64)
+    }
+
+    /// The average milliseconds from the time an engine last reported with
+    /// `info time` to its `bestmove` arriving, over the moves whose engine
+    /// reported its search time.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn bestmove_lag_ms(&self) -> Option<f64> {
+        let moves = self.reported_moves.load(Ordering::Relaxed);
+        let move_ms = self.reported_move_ms.load(Ordering::Relaxed);
+        let lag_ms = move_ms.saturating_sub(self.reported_search_ms.load(Ordering::Relaxed));
+        (moves > 0).then(|| lag_ms as f64 / moves as f64)
+    }
+
+    /// Wins, draws and losses from Reckless's point of view.
+    #[must_use]
+    pub fn reckless_wdl(&self) -> Wdl {
+        Wdl {
+            wins: self.reckless_wins.load(Ordering::Relaxed),
+            draws: self.draws.load(Ordering::Relaxed),
+            losses: self.stockfish_wins.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Log the final results.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn print_summary(&self) {
+        let total = self.total_games();
+        let stockfish_wins = self.stockfish_wins.load(Ordering::Relaxed);
+        let reckless_wins = self.reckless_wins.load(Ordering::Relaxed);
+        let draws = self.draws.load(Ordering::Relaxed);
+
+        if total == 0 {
+            tracing::info!("No games played");
+            return;
+        }
+
+        let stockfish_pct = (stockfish_wins as f64 / total as f64) * 100.0;
+        let reckless_pct = (reckless_wins as f64 / total as f64) * 100.0;
+        let draw_pct = (draws as f64 / total as f64) * 100.0;
+
+        tracing::info!("=== Match Results ===");
+        tracing::info!(
+            "Total games: {total}, Stockfish: {stockfish_wins} ({stockfish_pct:.1}%), Reckless: {reckless_wins} ({reckless_pct:.1}%), Draws: {draws} ({draw_pct:.1}%)"
+        );
+        tracing::info!(
+            "Stockfish as White: {} wins, as Black: {} wins",
+            self.stockfish_white_wins.load(Ordering::Relaxed),
+            self.stockfish_black_wins.load(Ordering::Relaxed)
+        );
+        tracing::info!(
+            "Reckless as White: {} wins, as Black: {} wins",
+            self.reckless_white_wins.load(Ordering::Relaxed),
+            self.reckless_black_wins.load(Ordering::Relaxed)
+        );
+        if let Some((elo, margin)) = self.reckless_wdl().elo() {
+            tracing::info!("Reckless Elo vs Stockfish: {elo:+.1} ± {margin:.1} (95%)");
+        }
+        if let Some(position_us) = self.position_send_us() {
+            tracing::info!("Position commands: {position_us:.0} µs per move to send");
+        }
+        if let Some(lag_ms) = self.bestmove_lag_ms() {
+            tracing::info!("Bestmove lag: {lag_ms:.2} ms per move after the last info time");
+        }
+    }
+
+    /// Log each engine's move times, flagging engines that took longer than
+    /// `movetime_ms` when every move had a fixed time.
+    pub fn print_latency(&self, movetime_ms: Option<u64>) {
+        for (engine, latency) in [
+            ("Stockfish", &self.stockfish_latency),
+            ("Reckless", &self.reckless_latency),
+        ] {
+            let Some(summary) = latency.summary() else {
+                continue;
+            };
+            let overshoot = movetime_ms
+                .map(|movetime_ms| {
+                    format!(
+                        ", max overshoot {} ms",
+                        summary.max_ms.saturating_sub(movetime_ms)
+                    )
+                })
+                .unwrap_or_default();
+            tracing::info!(
+                "{engine} move time: mean {:.1} ms, p50 {} ms, p99 {} ms{overshoot}",
+                summary.mean_ms,
+                summary.p50_ms,
+                summary.p99_ms
+            );
+
+            let Some(movetime_ms) = movetime_ms else {
+                continue;
+            };
+            let overruns = latency.count_over(movetime_ms + OVERRUN_TOLERANCE_MS);
+            if overruns > 0 {
+                tracing::warn!(
+                    "{engine} overran its {movetime_ms} ms movetime by more than {OVERRUN_TOLERANCE_MS} ms on {overruns} of {} moves",
+                    summary.moves
+                );
+            }
+        }
+    }
+
+    /// Log the score so far.
+    pub fn print_progress(&self) {
+        let total = self.total_games();
+        let stockfish_wins = self.stockfish_wins.load(Ordering::Relaxed);
+        let reckless_wins = self.reckless_wins.load(Ordering::Relaxed);
+        let draws = self.draws.load(Ordering::Relaxed);
+
+        tracing::info!(
+            games = total,
+            stockfish = stockfish_wins,
+            reckless = reckless_wins,
+            draws = draws,
+            "Progress"
+        );
+    }
+}
+
+/// A finished game, sent from workers to aggregator.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameCompleted {
+    /// Number of the game within the match
+    pub game_num: u64,
+    /// The moves and outcome
+    pub record: GameRecord,
+    /// Whether Stockfish played White
+    pub stockfish_is_white: bool,
+}
+
+/// Message sent from workers to aggregator.
+#[derive(Debug)]
+pub enum WorkerEvent {
+    /// A worker started a game (only sent when live events are enabled)
+    GameStarted {
+        worker_id: usize,
+        game_num: u64,
+        stockfish_is_white: bool,
+        /// The opening position, if the game doesn't start from the standard one
+        start_fen: Option<String>,
+    },
+    /// A move was played (only sent when live events are enabled)
+    MovePlayed {
+        worker_id: usize,
+        ply: usize,
+        uci: String,
+        board: Board,
+        nps: Option<u64>,
+        stockfish_moved: bool,
+    },
+    /// A game finished
+    GameCompleted(GameCompleted),
+    /// A game failed and the worker is restarting its engines
+    GameFailed {
+        worker_id: usize,
+        game_num: u64,
+        error: String,
+    },
+}
+
+/// One worker's Stockfish and Reckless processes.
+pub(crate) struct EnginePair {
+    worker_id: usize,
+    stockfish_config: EngineConfig,
+    reckless_config: EngineConfig,
+    resources: Resources,
+    stockfish: AnyEngine,
+    reckless: AnyEngine,
+}
+
+impl EnginePair {
+    /// Start both engines, pinning and limiting them with `resources`.
+    pub(crate) async fn spawn(
+        worker_id: usize,
+        stockfish: &EngineConfig,
+        reckless: &EngineConfig,
+        resources: Resources,
+    ) -> Result<Self> {
+        let threads = stockfish.threads().max(reckless.threads());
+        let mut pair = Self {
+            worker_id,
+            stockfish_config: stockfish.clone(),
+            reckless_config: reckless.clone(),
+            resources,
+            stockfish: AnyEngine::start(stockfish, &format!("stockfish-{worker_id}")).await?,
+            reckless: AnyEngine::start(reckless, &format!("reckless-{worker_id}")).await?,
+        };
+        if resources != Resources::default() {
+            for engine in [&mut pair.stockfish, &mut pair.reckless] {
+                // Let the engine start its search threads so they get pinned too
+                engine.ready().await?;
+                if let Some(pid) = engine.pid() {
+                    resources.apply(pid, worker_id, threads)?;
+                }
+            }
+        }
+        Ok(pair)
+    }
+
+    /// Play one game with the given colours, from `start_fen` or the
+    /// standard starting position.
+    pub(crate) async fn play_game(
+        &mut self,
+        runner: &GameRunner,
+        stockfish_is_white: bool,
+        start_fen: Option<&str>,
+        on_move: impl FnMut(MovePlayed<'_>) + Send,
+    ) -> Result<GameRecord> {
+        if stockfish_is_white {
+            runner
+                .play_game_from(&mut self.stockfish, &mut self.reckless, start_fen, on_move)
+                .await
+        } else {
+            runner
+                .play_game_from(&mut self.reckless, &mut self.stockfish, start_fen, on_move)
+                .await
+        }
+    }
+
+    /// Replace both engines with fresh processes, e.g. after a failed game.
+    pub(crate) async fn restart(&mut self) -> Result<()> {
+        self.quit().await;
+        *self = Self::spawn(
+            self.worker_id,
+            &self.stockfish_config,
+            &self.reckless_config,
+            self.resources,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Quit both engines.
+    pub(crate) async fn quit(&mut self) {
+        self.stockfish.quit().await.ok();
+        self.reckless.quit().await.ok();
+    }
+}
+
+/// Run a worker that plays games continuously.
+async fn run_worker(
+    worker_id: usize,
+    settings: &Match,
+    schedule: &GameSchedule,
+    runner: &GameRunner,
+    game_counter: Arc<AtomicU64>,
+    tx: mpsc::Sender<WorkerEvent>,
+    mut abort: watch::Receiver<bool>,
+) -> Result<()> {
+    settings
+        .resources
+        .check_ponder(settings.game.time_control.ponder)?;
+    // Each worker has its own engine pair
+    let mut engines = EnginePair::spawn(
+        worker_id,
+        &settings.stockfish,
+        &settings.reckless,
+        settings.resources,
+    )
+    .await?;
+
+    loop {
+        // Atomically claim a game number
+        let game_num = game_counter.fetch_add(1, Ordering::Relaxed);
+        if game_num >= settings.games {
+            break;
+        }
+
+        // Colours and opening follow from the seed and game number alone
+        let GameParams {
+            stockfish_is_white,
+            opening,
+            ..
+        } = schedule.game(game_num);
+
+        if settings.live_events {
+            tx.try_send(WorkerEvent::GameStarted {
+                worker_id,
+                game_num,
+                stockfish_is_white,
+                start_fen: opening.clone(),
+            })
+            .ok();
+        }
+
+        // Moves are only reported to the dashboard, and dropped rather than
+        // slowing the game down if the aggregator falls behind
+        let on_move = |played: MovePlayed<'_>| {
+            if settings.live_events {
+                // Black is to move after a White move
+                let stockfish_moved =
+                    (played.position.turn() == Color::Black) == stockfish_is_white;
+                tx.try_send(WorkerEvent::MovePlayed {
+                    worker_id,
+                    ply: played.ply,
+                    uci: played.uci.to_string(),
+                    board: played.position.board().clone(),
+                    nps: played.nps,
+                    stockfish_moved,
+                })
+                .ok();
+            }
+        };
+        let result = tokio::select! {
+            result = engines.play_game(runner, stockfish_is_white, opening.as_deref(), on_move) => result,
+            () = shutdown::aborted(&mut abort) => {
+                tracing::info!(worker = worker_id, game = game_num, "Game aborted");
+                break;
+            }
+        };
+
+        match result {
+            Ok(record) => {
+                if tx
+                    .send(WorkerEvent::GameCompleted(GameCompleted {
+                        game_num,
+                        record,
+                        stockfish_is_white,
+                    }))
+                    .await
+                    .is_err()
+                {
+                    // Receiver dropped, stop
+                    break;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(worker = worker_id, error = %e, "Game failed, restarting engines");
+                tx.send(WorkerEvent::GameFailed {
+                    worker_id,
+                    game_num,
+                    error: e.to_string(),
+                })
+                .await
+                .ok();
+                engines.restart().await?;
+            }
+        }
+    }
+
+    engines.quit().await;
+    Ok(())
+}
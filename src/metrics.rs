@@ -0,0 +1,383 @@
+//! Prometheus metrics endpoint.
+//!
+//! Serves `GET /metrics` in the Prometheus text exposition format, built from
+//! the live [`MatchStats`].
+
+use crate::game::Termination;
+use crate::match_runner::MatchStats;
+use color_eyre::eyre::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Upper bounds of the move latency buckets, in milliseconds.
+const LATENCY_BUCKETS_MS: [u64; 12] = [10, 25, 50, 75, 100, 150, 200, 300, 500, 1000, 2000, 5000];
+
+/// Largest request head we are willing to read.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// A cumulative histogram of move latencies.
+///
+/// Alongside the coarse Prometheus buckets it counts every distinct
+/// millisecond, so percentiles in the summary are exact.
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len()],
+    sum_ms: AtomicU64,
+    count: AtomicU64,
+    times_ms: Mutex<BTreeMap<u64, u64>>,
+}
+
+/// Move time statistics for one engine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    /// Number of moves timed
+    pub moves: u64,
+    /// Mean time per move in milliseconds
+    pub mean_ms: f64,
+    /// Median time per move in milliseconds
+    pub p50_ms: u64,
+    /// 99th percentile time per move in milliseconds
+    pub p99_ms: u64,
+    /// Slowest move in milliseconds
+    pub max_ms: u64,
+}
+
+impl LatencyHistogram {
+    /// Record one move that took `time_ms`.
+    pub fn observe(&self, time_ms: u64) {
+        for (bucket, &bound) in self.buckets.iter().zip(&LATENCY_BUCKETS_MS) {
+            if time_ms <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_ms.fetch_add(time_ms, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut times_ms) = self.times_ms.lock() {
+            *times_ms.entry(time_ms).or_default() += 1;
+        }
+    }
+
+    /// Statistics of the moves observed so far, if there were any.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn summary(&self) -> Option<LatencySummary> {
+        let times_ms = self.times_ms.lock().ok()?.clone();
+        let moves: u64 = times_ms.values().sum();
+        let max_ms = *times_ms.keys().next_back()?;
+        // The smallest time at or below which `percent` of the moves fall
+        let percentile = |percent: u64| {
+            let rank = (moves * percent).div_ceil(100).max(1);
+            let mut seen = 0;
+            times_ms
+                .iter()
+                .find(|&(_, &count)| {
+                    seen += count;
+                    seen >= rank
+                })
+                .map_or(max_ms, |(&time_ms, _)| time_ms)
+        };
+        let total_ms: u64 = times_ms
+            .iter()
+            .map(|(time_ms, count)| time_ms * count)
+            .sum();
+        Some(LatencySummary {
+            moves,
+            mean_ms: total_ms as f64 / moves as f64,
+            p50_ms: percentile(50),
+            p99_ms: percentile(99),
+            max_ms,
+        })
+    }
+
+    /// Number of moves that took longer than `limit_ms`.
+    #[must_use]
+    pub fn count_over(&self, limit_ms: u64) -> u64 {
+        self.times_ms.lock().map_or(0, |times_ms| {
+            times_ms
+                .range(limit_ms.saturating_add(1)..)
+                .map(|(_, count)| count)
+                .sum()
+        })
+    }
+
+    /// Append the histogram's samples for `engine` to `out`.
+    #[allow(clippy::cast_precision_loss)]
+    fn render(&self, out: &mut String, name: &str, engine: &str) {
+        for (bucket, &bound) in self.buckets.iter().zip(&LATENCY_BUCKETS_MS) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{engine=\"{engine}\",le=\"{}\"}} {}",
+                bound as f64 / 1000.0,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{engine=\"{engine}\",le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(
+            out,
+            "{name}_sum{{engine=\"{engine}\"}} {}",
+            self.sum_ms.load(Ordering::Relaxed) as f64 / 1000.0
+        );
+        let _ = writeln!(out, "{name}_count{{engine=\"{engine}\"}} {count}");
+    }
+}
+
+/// Append a metric's `HELP` and `TYPE` lines.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Render all metrics in the Prometheus text format.
+pub fn render(stats: &MatchStats) -> String {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "rvs_games_completed_total",
+        "counter",
+        "Games completed.",
+    );
+    let _ = writeln!(out, "rvs_games_completed_total {}", stats.total_games());
+
+    header(
+        &mut out,
+        "rvs_wins_total",
+        "counter",
+        "Games won, by engine and colour.",
+    );
+    for (engine, color, counter) in [
+        ("stockfish", "white", &stats.stockfish_white_wins),
+        ("stockfish", "black", &stats.stockfish_black_wins),
+        ("reckless", "white", &stats.reckless_white_wins),
+        ("reckless", "black", &stats.reckless_black_wins),
+    ] {
+        let _ = writeln!(
+            out,
+            "rvs_wins_total{{engine=\"{engine}\",color=\"{color}\"}} {}",
+            load(counter)
+        );
+    }
+
+    header(
+        &mut out,
+        "rvs_draws_total",
+        "counter",
+        "Drawn games, by how the game ended.",
+    );
+    for (termination, counter) in Termination::ALL.iter().zip(&stats.draws_by_termination) {
+        let _ = writeln!(
+            out,
+            "rvs_draws_total{{termination=\"{}\"}} {}",
+            termination.as_str(),
+            load(counter)
+        );
+    }
+
+    header(
+        &mut out,
+        "rvs_engine_restarts_total",
+        "counter",
+        "Engine pairs restarted after a failed game.",
+    );
+    let _ = writeln!(
+        out,
+        "rvs_engine_restarts_total {}",
+        load(&stats.engine_restarts)
+    );
+
+    header(
+        &mut out,
+        "rvs_time_forfeits_total",
+        "counter",
+        "Games lost on time, by engine.",
+    );
+    for (engine, counter) in [
+        ("stockfish", &stats.stockfish_time_forfeits),
+        ("reckless", &stats.reckless_time_forfeits),
+    ] {
+        let _ = writeln!(
+            out,
+            "rvs_time_forfeits_total{{engine=\"{engine}\"}} {}",
+            load(counter)
+        );
+    }
+
+    header(
+        &mut out,
+        "rvs_move_latency_seconds",
+        "histogram",
+        "Time from go to bestmove, by engine.",
+    );
+    stats
+        .stockfish_latency
+        .render(&mut out, "rvs_move_latency_seconds", "stockfish");
+    stats
+        .reckless_latency
+        .render(&mut out, "rvs_move_latency_seconds", "reckless");
+
+    if let Some((elo, margin)) = stats.reckless_wdl().elo() {
+        header(
+            &mut out,
+            "rvs_elo_estimate",
+            "gauge",
+            "Estimated Elo difference of Reckless over Stockfish.",
+        );
+        let _ = writeln!(out, "rvs_elo_estimate {elo}");
+        header(
+            &mut out,
+            "rvs_elo_error_margin",
+            "gauge",
+            "Half-width of the 95% confidence interval of the Elo estimate.",
+        );
+        let _ = writeln!(out, "rvs_elo_error_margin {margin}");
+    }
+
+    out
+}
+
+/// Answer a single HTTP request.
+async fn handle(mut stream: TcpStream, stats: &MatchStats) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || request.len() > MAX_REQUEST_BYTES {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status_line, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(stats),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status_line}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Serve metrics on `listener` until the task is dropped.
+pub async fn serve(listener: TcpListener, stats: Arc<MatchStats>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let stats = Arc::clone(&stats);
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, &stats).await {
+                        tracing::debug!(%peer, error = %e, "Metrics request failed");
+                    }
+                });
+            }
+            Err(e) => tracing::warn!(error = %e, "Failed to accept metrics connection"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameRecord, GameResult, PlyRecord};
+
+    async fn scrape(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.expect("Failed to connect");
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .expect("Failed to send request");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("Failed to read response");
+        response
+    }
+
+    #[tokio::test]
+    async fn test_scrape_metrics() {
+        let stats = Arc::new(MatchStats::default());
+        let ply = |uci: &str, time_ms| PlyRecord {
+            uci: uci.to_string(),
+            score: None,
+            depth: None,
+            time_ms,
+            search_ms: None,
+            position_us: None,
+        };
+        stats.record(
+            &GameRecord {
+                result: GameResult::Draw,
+                termination: Termination::Stalemate,
+                plies: vec![ply("e2e4", 40), ply("e7e5", 120)],
+                start_fen: None,
+            },
+            true,
+        );
+        stats.record(
+            &GameRecord {
+                result: GameResult::WhiteWins,
+                termination: Termination::TimeForfeit,
+                plies: vec![ply("d2d4", 90)],
+                start_fen: None,
+            },
+            false,
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let addr = listener.local_addr().expect("No local address");
+        let server = tokio::spawn(serve(listener, Arc::clone(&stats)));
+
+        let response = scrape(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.contains("rvs_games_completed_total 2\n"));
+        assert!(response.contains("rvs_wins_total{engine=\"reckless\",color=\"white\"} 1\n"));
+        assert!(response.contains("rvs_draws_total{termination=\"stalemate\"} 1\n"));
+        assert!(response.contains("rvs_time_forfeits_total{engine=\"stockfish\"} 1\n"));
+        assert!(
+            response
+                .contains("rvs_move_latency_seconds_bucket{engine=\"stockfish\",le=\"0.05\"} 1\n")
+        );
+        assert!(response.contains("rvs_move_latency_seconds_count{engine=\"reckless\"} 2\n"));
+        // One win and one draw for Reckless is a 75% score
+        assert!(response.contains("rvs_elo_estimate 190.8"));
+
+        let summary = stats.reckless_latency.summary().expect("No moves timed");
+        assert_eq!(summary.moves, 2);
+        assert!((summary.mean_ms - 105.0).abs() < f64::EPSILON);
+        assert_eq!(
+            (summary.p50_ms, summary.p99_ms, summary.max_ms),
+            (90, 120, 120)
+        );
+        assert_eq!(stats.reckless_latency.count_over(100), 1);
+
+        let response = scrape(addr, "/").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"), "{response}");
+
+        server.abort();
+    }
+}
@@ -0,0 +1,79 @@
+//! Opening books: starting positions read from EPD or FEN files.
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use std::path::Path;
+
+/// Turn an EPD or FEN line into a full FEN, checking that it is a legal position.
+///
+/// EPD lines lack the move counters and may carry opcodes such as `bm` or
+/// `id`, which are ignored.
+///
+/// # Errors
+/// Returns an error if the line does not describe a legal position.
+pub fn fen_from_line(line: &str) -> Result<String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(eyre!("Expected at least 4 FEN fields in '{line}'"));
+    }
+    let counters = match fields.get(4..6) {
+        Some([halfmoves, fullmoves])
+            if halfmoves.parse::<u32>().is_ok() && fullmoves.parse::<u32>().is_ok() =>
+        {
+            format!("{halfmoves} {fullmoves}")
+        }
+        _ => "0 1".to_string(),
+    };
+    let fen = format!("{} {counters}", fields[..4].join(" "));
+    fen.parse::<Fen>()
+        .map_err(|e| eyre!("Invalid FEN '{fen}': {e}"))?
+        .into_position::<Chess>(CastlingMode::Standard)
+        .map_err(|e| eyre!("Illegal position '{fen}': {e}"))?;
+    Ok(fen)
+}
+
+/// Load every position in an EPD or FEN file, one per line.
+///
+/// Blank lines and lines starting with `#` are skipped.
+///
+/// # Errors
+/// Returns an error if the file cannot be read, contains an invalid
+/// position or contains no positions at all.
+pub fn load(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read openings from {}", path.display()))?;
+    let openings = text
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            fen_from_line(line).wrap_err_with(|| format!("{}:{number}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if openings.is_empty() {
+        return Err(eyre!("No openings in {}", path.display()));
+    }
+    Ok(openings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fen_from_epd_and_fen_lines() {
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - bm e5; id \"e4\";";
+        assert_eq!(
+            fen_from_line(epd).expect("EPD should parse"),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        assert_eq!(fen_from_line(fen).expect("FEN should parse"), fen);
+
+        assert!(fen_from_line("8/8/8/8/8/8/8/8 w - -").is_err());
+        assert!(fen_from_line("not a position").is_err());
+    }
+}
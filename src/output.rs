@@ -0,0 +1,133 @@
+//! Files that finished games are appended to as they complete.
+
+use crate::db::EngineMetadata;
+use crate::match_runner::GameCompleted;
+use crate::pgn::{self, Player};
+use color_eyre::eyre::{Result, WrapErr};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A game as written to a JSON lines file.
+#[derive(Serialize)]
+struct JsonGame<'a> {
+    game_num: u64,
+    white: &'a str,
+    black: &'a str,
+    #[serde(flatten)]
+    record: &'a crate::game::GameRecord,
+}
+
+/// Open `path` for appending, creating it if needed.
+fn append(path: &Path) -> Result<BufWriter<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// How an engine is named in PGN headers.
+struct PgnName {
+    label: &'static str,
+    name: String,
+    binary_sha256: Option<String>,
+}
+
+impl PgnName {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            name: label.to_string(),
+            binary_sha256: None,
+        }
+    }
+
+    fn player(&self) -> Player<'_> {
+        Player {
+            name: &self.name,
+            binary_sha256: self.binary_sha256.as_deref(),
+        }
+    }
+}
+
+/// PGN and JSON lines files to record games in, either of which may be absent.
+pub struct GameFiles {
+    pgn: Option<BufWriter<File>>,
+    jsonl: Option<BufWriter<File>>,
+    stockfish: PgnName,
+    reckless: PgnName,
+}
+
+impl GameFiles {
+    /// Open the requested files for appending.
+    ///
+    /// # Errors
+    /// Returns an error if a file cannot be opened.
+    pub fn open(pgn: Option<&Path>, jsonl: Option<&Path>) -> Result<Self> {
+        Ok(Self {
+            pgn: pgn.map(append).transpose()?,
+            jsonl: jsonl.map(append).transpose()?,
+            stockfish: PgnName::new("stockfish"),
+            reckless: PgnName::new("reckless"),
+        })
+    }
+
+    /// Name engines in PGN headers by their `id name` and binary hash rather
+    /// than their label.
+    #[must_use]
+    pub fn with_engines(mut self, engines: &[EngineMetadata]) -> Self {
+        for named in [&mut self.stockfish, &mut self.reckless] {
+            if let Some(engine) = engines.iter().find(|engine| engine.label == named.label) {
+                named
+                    .name
+                    .clone_from(engine.id_name.as_ref().unwrap_or(&engine.label));
+                named.binary_sha256.clone_from(&engine.binary_sha256);
+            }
+        }
+        self
+    }
+
+    /// Append a finished game to every open file.
+    ///
+    /// # Errors
+    /// Returns an error if the game cannot be formatted or written.
+    pub fn write(&mut self, completed: &GameCompleted) -> Result<()> {
+        let (white, black) = if completed.stockfish_is_white {
+            ("stockfish", "reckless")
+        } else {
+            ("reckless", "stockfish")
+        };
+        if let Some(pgn) = &mut self.pgn {
+            let (white_name, black_name) = if completed.stockfish_is_white {
+                (&self.stockfish, &self.reckless)
+            } else {
+                (&self.reckless, &self.stockfish)
+            };
+            let game = pgn::format_game(
+                &completed.record,
+                white_name.player(),
+                black_name.player(),
+                completed.game_num + 1,
+            )?;
+            pgn.write_all(game.as_bytes())?;
+            pgn.flush()?;
+        }
+        if let Some(jsonl) = &mut self.jsonl {
+            serde_json::to_writer(
+                &mut *jsonl,
+                &JsonGame {
+                    game_num: completed.game_num,
+                    white,
+                    black,
+                    record: &completed.record,
+                },
+            )?;
+            jsonl.write_all(b"\n")?;
+            jsonl.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! Cross-checking an engine's move generator with `go perft`.
+//!
+//! Stockfish and Reckless both answer `go perft N` with the number of leaf
+//! nodes below each legal move ("divide"). Comparing those counts with
+//! shakmaty's own points at the moves whose subtrees the engine gets wrong.
+
+use crate::UciEngine;
+use crate::engine::Divide;
+use crate::epd::Epd;
+use color_eyre::eyre::Result;
+use std::fmt;
+
+/// Well-known positions that between them cover castling, en passant,
+/// promotions and pins.
+pub const STANDARD_POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+/// A move whose subtree the engine counted differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The move in UCI notation
+    pub uci: String,
+    /// Nodes below it by shakmaty, or `None` if it isn't legal
+    pub expected: Option<u64>,
+    /// Nodes below it by the engine, or `None` if it didn't report it
+    pub engine: Option<u64>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.engine, self.expected) {
+            (Some(engine), Some(expected)) => {
+                write!(f, "{}: engine {engine}, expected {expected}", self.uci)
+            }
+            (None, Some(expected)) => {
+                write!(f, "{}: missing from engine, expected {expected}", self.uci)
+            }
+            (Some(engine), None) => write!(f, "{}: not legal, engine {engine}", self.uci),
+            (None, None) => write!(f, "{}: unknown", self.uci),
+        }
+    }
+}
+
+/// An engine's perft of one position next to shakmaty's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerftCheck {
+    /// The position as a FEN
+    pub fen: String,
+    /// The depth counted to
+    pub depth: u32,
+    /// Shakmaty's divide
+    pub expected: Divide,
+    /// The engine's divide
+    pub engine: Divide,
+}
+
+impl PerftCheck {
+    /// The moves the two divides disagree on, in UCI order.
+    #[must_use]
+    pub fn mismatches(&self) -> Vec<Mismatch> {
+        let mut moves: Vec<&String> = self
+            .expected
+            .moves
+            .keys()
+            .chain(self.engine.moves.keys())
+            .collect();
+        moves.sort();
+        moves.dedup();
+        moves
+            .into_iter()
+            .filter_map(|uci| {
+                let expected = self.expected.moves.get(uci).copied();
+                let engine = self.engine.moves.get(uci).copied();
+                (expected != engine).then(|| Mismatch {
+                    uci: uci.clone(),
+                    expected,
+                    engine,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the engine agrees with shakmaty on every move and in total.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.expected == self.engine
+    }
+}
+
+/// Run perft to `depth` on `epd`'s position with both `engine` and shakmaty.
+///
+/// # Errors
+/// Returns an error if the position is invalid or the engine fails.
+pub async fn check(engine: &mut UciEngine, epd: &Epd, depth: u32) -> Result<PerftCheck> {
+    let expected = Divide::of(&epd.position()?, depth);
+    let engine = engine.perft(&epd.fen, depth).await?;
+    Ok(PerftCheck {
+        fen: epd.fen.clone(),
+        depth,
+        expected,
+        engine,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::Chess;
+
+    #[test]
+    fn test_mismatches() {
+        let expected = Divide::of(&Chess::default(), 2);
+        let mut engine = expected.clone();
+        *engine.moves.get_mut("e2e4").expect("No e2e4") += 1;
+        engine.moves.remove("g1h3");
+        engine.moves.insert("e1g1".to_string(), 20);
+        engine.nodes = engine.moves.values().sum();
+        let check = PerftCheck {
+            fen: "startpos".to_string(),
+            depth: 2,
+            expected,
+            engine,
+        };
+
+        assert!(!check.is_ok());
+        let mismatches: Vec<String> = check.mismatches().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                "e1g1: not legal, engine 20",
+                "e2e4: engine 21, expected 20",
+                "g1h3: missing from engine, expected 20",
+            ]
+        );
+    }
+}
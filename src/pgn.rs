@@ -0,0 +1,490 @@
+//! PGN export of finished games, and reading them back.
+
+use crate::engine::Score;
+use crate::game::{self, GameRecord, GameResult, PlyRecord, Termination};
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess, Color, Position};
+use std::fmt::Write as _;
+
+/// Longest movetext line, as the PGN standard recommends.
+const LINE_WIDTH: usize = 79;
+
+/// The standard PGN `Termination` tag for how a game ended.
+const fn termination_tag(termination: Termination) -> &'static str {
+    match termination {
+        Termination::Checkmate
+        | Termination::Stalemate
+        | Termination::InsufficientMaterial
+        | Termination::FiftyMoveRule => "normal",
+        Termination::TimeForfeit => "time forfeit",
+        Termination::NoMove => "rules infraction",
+        Termination::MaxMoves | Termination::DrawAdjudication | Termination::ResignAdjudication => {
+            "adjudication"
+        }
+    }
+}
+
+/// The mover's score, depth and time as a comment, e.g. `{+0.35/12 0.101s}`.
+#[allow(clippy::cast_precision_loss)]
+fn comment(ply: &PlyRecord) -> String {
+    let score = ply
+        .score
+        .map_or_else(|| "?".to_string(), |score| score.to_string());
+    let depth = ply
+        .depth
+        .map_or_else(String::new, |depth| format!("/{depth}"));
+    format!("{{{score}{depth} {:.3}s}}", ply.time_ms as f64 / 1000.0)
+}
+
+/// Read back a comment written by [`comment`] into `ply`, leaving it
+/// untouched if the comment is something else.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn parse_comment(text: &str, ply: &mut PlyRecord) {
+    let Some((eval, time)) = text.trim().split_once(' ') else {
+        return;
+    };
+    let Some(seconds) = time.strip_suffix('s').and_then(|s| s.parse::<f64>().ok()) else {
+        return;
+    };
+    let (score, depth) = eval.split_once('/').unwrap_or((eval, ""));
+    ply.time_ms = (seconds * 1000.0).round() as u64;
+    ply.depth = depth.parse().ok();
+    ply.score = match score.split_once('M') {
+        Some(("+", mate)) => mate.parse().ok().map(Score::Mate),
+        Some(("-", mate)) => mate.parse::<i32>().ok().map(|mate| Score::Mate(-mate)),
+        _ => score
+            .parse::<f64>()
+            .ok()
+            .map(|pawns| Score::Cp((pawns * 100.0).round() as i32)),
+    };
+}
+
+/// A remark on one move, written after the move's evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// Index of the move in the game's plies
+    pub ply: usize,
+    /// Numeric annotation glyph, e.g. 4 for `??`
+    pub nag: u8,
+    /// A further comment, if any
+    pub comment: Option<String>,
+}
+
+/// An engine as named in PGN tags.
+#[derive(Debug, Clone, Copy)]
+pub struct Player<'a> {
+    /// The engine's name, ideally its `id name`
+    pub name: &'a str,
+    /// SHA-256 of the engine binary, tagged so results can be tied to a build
+    pub binary_sha256: Option<&'a str>,
+}
+
+impl<'a> From<&'a str> for Player<'a> {
+    fn from(name: &'a str) -> Self {
+        Self {
+            name,
+            binary_sha256: None,
+        }
+    }
+}
+
+/// Render a game as PGN, with each move's evaluation as a comment.
+///
+/// # Errors
+/// Returns an error if the recorded moves cannot be replayed from the start position.
+pub fn format_game<'a>(
+    record: &GameRecord,
+    white: impl Into<Player<'a>>,
+    black: impl Into<Player<'a>>,
+    round: u64,
+) -> Result<String> {
+    format_annotated_game(record, white, black, round, &[])
+}
+
+/// Render a game as PGN like [`format_game`], with `annotations` on their moves.
+///
+/// # Errors
+/// Returns an error if the recorded moves cannot be replayed from the start position.
+pub fn format_annotated_game<'a>(
+    record: &GameRecord,
+    white: impl Into<Player<'a>>,
+    black: impl Into<Player<'a>>,
+    round: u64,
+    annotations: &[Annotation],
+) -> Result<String> {
+    let (white, black) = (white.into(), black.into());
+    let mut position = match &record.start_fen {
+        Some(fen) => fen
+            .parse::<Fen>()
+            .map_err(|e| eyre!("Invalid FEN '{fen}': {e}"))?
+            .into_position(CastlingMode::Standard)
+            .map_err(|e| eyre!("Illegal position '{fen}': {e}"))?,
+        None => Chess::default(),
+    };
+
+    let mut pgn = String::new();
+    let result = record.result.as_str();
+    let _ = writeln!(pgn, "[Event \"Reckless vs Stockfish\"]");
+    let _ = writeln!(pgn, "[Site \"?\"]");
+    let _ = writeln!(pgn, "[Date \"????.??.??\"]");
+    let _ = writeln!(pgn, "[Round \"{round}\"]");
+    let _ = writeln!(pgn, "[White \"{}\"]", white.name);
+    let _ = writeln!(pgn, "[Black \"{}\"]", black.name);
+    let _ = writeln!(pgn, "[Result \"{result}\"]");
+    for (tag, player) in [("WhiteSha256", white), ("BlackSha256", black)] {
+        if let Some(sha256) = player.binary_sha256 {
+            let _ = writeln!(pgn, "[{tag} \"{sha256}\"]");
+        }
+    }
+    if let Some(fen) = &record.start_fen {
+        let _ = writeln!(pgn, "[SetUp \"1\"]");
+        let _ = writeln!(pgn, "[FEN \"{fen}\"]");
+    }
+    let _ = writeln!(
+        pgn,
+        "[Termination \"{}\"]",
+        termination_tag(record.termination)
+    );
+    let _ = writeln!(pgn, "[PlyCount \"{}\"]", record.plies.len());
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    for (index, ply) in record.plies.iter().enumerate() {
+        if position.turn() == Color::White {
+            tokens.push(format!("{}.", position.fullmoves()));
+        } else if index == 0 {
+            tokens.push(format!("{}...", position.fullmoves()));
+        }
+        let m = ply
+            .uci
+            .parse::<UciMove>()
+            .map_err(|e| eyre!("Invalid UCI move '{}': {e}", ply.uci))?
+            .to_move(&position)
+            .map_err(|e| eyre!("Illegal move '{}': {e}", ply.uci))?;
+        tokens.push(SanPlus::from_move_and_play_unchecked(&mut position, &m).to_string());
+        let annotations = annotations.iter().filter(|a| a.ply == index);
+        tokens.extend(annotations.clone().map(|a| format!("${}", a.nag)));
+        tokens.push(comment(ply));
+        tokens.extend(annotations.filter_map(|a| a.comment.as_ref().map(|c| format!("{{{c}}}"))));
+    }
+    tokens.push(result.to_string());
+
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > LINE_WIDTH {
+            pgn.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            pgn.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        pgn.push_str(&token);
+    }
+    pgn.push_str("\n\n");
+    Ok(pgn)
+}
+
+/// A game read from a PGN file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    /// The tag pairs, in the order they appeared
+    pub tags: Vec<(String, String)>,
+    /// The moves, with evaluations where the comments are ones we wrote
+    pub record: GameRecord,
+}
+
+impl PgnGame {
+    /// The value of the tag called `name`, if there is one.
+    #[must_use]
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a tag pair line such as `[White "stockfish"]`.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+/// A comment or other token of movetext, with variations left out.
+enum Token {
+    Comment(String),
+    Word(String),
+}
+
+/// Split movetext into comments and words, dropping `;` comments and
+/// variations.
+fn movetext_tokens(movetext: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut variations = 0_usize;
+    let mut chars = movetext.chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() || matches!(c, '{' | ';' | '(' | ')') {
+            let word = std::mem::take(&mut word);
+            if !word.is_empty() && variations == 0 {
+                tokens.push(Token::Word(word));
+            }
+        }
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if variations == 0 {
+                    tokens.push(Token::Comment(comment));
+                }
+            }
+            ';' => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '(' => variations += 1,
+            ')' => variations = variations.saturating_sub(1),
+            c if c.is_whitespace() => {}
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() && variations == 0 {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+/// How a game read from PGN ended, as far as its tags and final position tell.
+fn termination_of(tag: Option<&str>, result: GameResult, position: &Chess) -> Termination {
+    match tag {
+        Some("time forfeit") => Termination::TimeForfeit,
+        Some("rules infraction") => Termination::NoMove,
+        _ => match game::game_over(position) {
+            Some((_, termination)) => termination,
+            None if result == GameResult::Draw => Termination::DrawAdjudication,
+            None => Termination::ResignAdjudication,
+        },
+    }
+}
+
+/// Build a game from its tags and movetext.
+fn parse_game(tags: Vec<(String, String)>, movetext: &str) -> Result<PgnGame> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let start_fen = tag("FEN").map(str::to_string);
+    let mut position = start_fen
+        .as_deref()
+        .map_or_else(|| Ok(Chess::default()), game::start_position)?;
+
+    let mut result = tag("Result").map(str::to_string);
+    let mut plies: Vec<PlyRecord> = Vec::new();
+    for token in movetext_tokens(movetext) {
+        let word = match token {
+            Token::Comment(text) => {
+                if let Some(ply) = plies.last_mut() {
+                    parse_comment(&text, ply);
+                }
+                continue;
+            }
+            Token::Word(word) => word,
+        };
+        if matches!(word.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+            result = Some(word);
+            continue;
+        }
+        // Move numbers may be written up against the move, as in `1.e4`
+        let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let san = san.trim_end_matches(['!', '?']);
+        if san.is_empty() || san.starts_with('$') {
+            continue;
+        }
+        let m = san
+            .parse::<SanPlus>()
+            .map_err(|e| eyre!("Invalid move '{word}': {e}"))?
+            .san
+            .to_move(&position)
+            .map_err(|e| eyre!("Illegal move '{word}': {e}"))?;
+        plies.push(PlyRecord {
+            uci: m.to_uci(CastlingMode::Standard).to_string(),
+            score: None,
+            depth: None,
+            time_ms: 0,
+            search_ms: None,
+            position_us: None,
+        });
+        position.play_unchecked(&m);
+    }
+
+    let result = match result.as_deref() {
+        Some("1-0") => GameResult::WhiteWins,
+        Some("0-1") => GameResult::BlackWins,
+        Some("1/2-1/2") => GameResult::Draw,
+        result => return Err(eyre!("Unfinished game, result {}", result.unwrap_or("?"))),
+    };
+    let termination = termination_of(tag("Termination"), result, &position);
+    Ok(PgnGame {
+        record: GameRecord {
+            result,
+            termination,
+            plies,
+            start_fen,
+        },
+        tags,
+    })
+}
+
+/// Read every game of a PGN file, such as the ones [`format_game`] writes.
+///
+/// Evaluations are read back from comments in the form it writes them.
+///
+/// # Errors
+/// Returns an error naming the first game with a bad tag, an illegal move
+/// or no result.
+pub fn parse_games(text: &str) -> Result<Vec<PgnGame>> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            if !movetext.trim().is_empty() {
+                let game = parse_game(std::mem::take(&mut tags), &movetext)
+                    .wrap_err_with(|| format!("Game {}", games.len() + 1))?;
+                games.push(game);
+                movetext.clear();
+            }
+            let tag = parse_tag(line).ok_or_else(|| eyre!("Invalid tag {line}"))?;
+            tags.push(tag);
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !tags.is_empty() || !movetext.trim().is_empty() {
+        let game =
+            parse_game(tags, &movetext).wrap_err_with(|| format!("Game {}", games.len() + 1))?;
+        games.push(game);
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Score;
+    use crate::game::GameResult;
+
+    #[test]
+    fn test_format_fools_mate() {
+        let ply = |uci: &str, score| PlyRecord {
+            uci: uci.to_string(),
+            score,
+            depth: Some(10),
+            time_ms: 100,
+            search_ms: None,
+            position_us: None,
+        };
+        let record = GameRecord {
+            result: GameResult::BlackWins,
+            termination: Termination::Checkmate,
+            plies: vec![
+                ply("f2f3", Some(Score::Cp(-20))),
+                ply("e7e5", Some(Score::Cp(35))),
+                ply("g2g4", Some(Score::Mate(-1))),
+                ply("d8h4", Some(Score::Mate(1))),
+            ],
+            start_fen: None,
+        };
+
+        let pgn = format_game(&record, "stockfish", "reckless", 1).expect("Failed to format");
+        assert!(pgn.contains("[Result \"0-1\"]\n"));
+        assert!(pgn.contains("[Termination \"normal\"]\n"));
+        assert!(pgn.contains("1. f3 {-0.20/10 0.100s} e5 {+0.35/10 0.100s} 2. g4 {-M1/10 0.100s}"));
+        assert!(pgn.contains(" Qh4#\n"));
+        assert!(pgn.ends_with("{+M1/10 0.100s} 0-1\n\n"));
+        assert!(pgn.lines().all(|line| line.len() <= LINE_WIDTH));
+    }
+
+    #[test]
+    fn test_format_from_black_to_move() {
+        let record = GameRecord {
+            result: GameResult::Draw,
+            termination: Termination::MaxMoves,
+            plies: vec![PlyRecord {
+                uci: "e7e5".to_string(),
+                score: None,
+                depth: None,
+                time_ms: 5,
+                search_ms: None,
+                position_us: None,
+            }],
+            start_fen: Some(
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string(),
+            ),
+        };
+
+        let white = Player {
+            name: "Reckless 0.9",
+            binary_sha256: Some("abc123"),
+        };
+        let pgn = format_game(&record, white, "stockfish", 2).expect("Failed to format");
+        assert!(pgn.contains("[White \"Reckless 0.9\"]\n[Black \"stockfish\"]\n"));
+        assert!(pgn.contains("[WhiteSha256 \"abc123\"]\n"));
+        assert!(!pgn.contains("BlackSha256"));
+        assert!(pgn.contains("[SetUp \"1\"]\n"));
+        assert!(pgn.contains("1... e5 {? 0.005s} 1/2-1/2"));
+    }
+
+    #[test]
+    fn test_parse_games_reads_back_formatted_games() {
+        let ply = |uci: &str, score| PlyRecord {
+            uci: uci.to_string(),
+            score,
+            depth: Some(10),
+            time_ms: 100,
+            search_ms: None,
+            position_us: None,
+        };
+        let record = GameRecord {
+            result: GameResult::BlackWins,
+            termination: Termination::Checkmate,
+            plies: vec![
+                ply("f2f3", Some(Score::Cp(-20))),
+                ply("e7e5", Some(Score::Cp(35))),
+                ply("g2g4", Some(Score::Mate(-1))),
+                ply("d8h4", Some(Score::Mate(1))),
+            ],
+            start_fen: None,
+        };
+        let annotations = [Annotation {
+            ply: 2,
+            nag: 4,
+            comment: Some("referee -0.50 -> -10.00".to_string()),
+        }];
+        let annotated = format_annotated_game(&record, "stockfish", "reckless", 3, &annotations)
+            .expect("Failed to format");
+        let movetext = annotated.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(movetext.contains("2. g4 $4 {-M1/10 0.100s} {referee -0.50 -> -10.00} Qh4#"));
+
+        let text = format!(
+            "{annotated}[Event \"?\"]\n[Result \"1/2-1/2\"]\n\n1.e4 (1. d4 {{main}} d5) e5!? ; comment\n1/2-1/2\n"
+        );
+        let games = parse_games(&text).expect("Failed to parse");
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].record, record);
+        assert_eq!(games[0].tag("Round"), Some("3"));
+        assert_eq!(games[1].record.uci_moves(), "e2e4 e7e5");
+        assert_eq!(games[1].record.result, GameResult::Draw);
+        assert_eq!(games[1].record.termination, Termination::DrawAdjudication);
+        assert!(parse_games("[Result \"*\"]\n\n1. e4 *\n").is_err());
+    }
+}
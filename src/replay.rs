@@ -0,0 +1,270 @@
+//! Replaying stored games.
+//!
+//! A stored game is re-checked by playing its moves through `shakmaty`
+//! again, confirming each one is legal and that the recorded result fits the
+//! final position. It can also be played again live, with the engines and
+//! settings of the run it came from.
+
+use crate::config::MatchConfig;
+use crate::db::GameRow;
+use crate::engine::{AnyEngine, Engine, Transcript};
+use crate::game::{self, GameRecord, GameResult, GameRunner, Termination};
+use crate::schedule::GameParams;
+use color_eyre::eyre::{Result, eyre};
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::{Chess, Color, EnPassantMode, Position};
+
+/// What replaying a stored game's moves showed.
+#[derive(Debug, Clone)]
+pub struct Verification {
+    /// Number of moves replayed
+    pub plies: usize,
+    /// The final position
+    pub position: Chess,
+    /// How the rules end the game in the final position, if they do
+    pub game_over: Option<(GameResult, Termination)>,
+}
+
+impl Verification {
+    /// The final position as a FEN.
+    #[must_use]
+    pub fn final_fen(&self) -> String {
+        Fen::from_position(self.position.clone(), EnPassantMode::Legal).to_string()
+    }
+}
+
+/// Play `moves` from `start_fen` (or the standard starting position),
+/// checking that each is legal and that none is played after the game ended.
+///
+/// # Errors
+/// Returns an error naming the first bad move.
+pub fn replay_moves(start_fen: Option<&str>, moves: &[&str]) -> Result<Verification> {
+    let mut position = start_fen.map_or_else(|| Ok(Chess::default()), game::start_position)?;
+    for (ply, uci) in moves.iter().enumerate() {
+        if let Some((_, termination)) = game::game_over(&position) {
+            return Err(eyre!(
+                "Move {uci} at ply {ply} was played after the game ended by {}",
+                termination.as_str()
+            ));
+        }
+        let m = uci
+            .parse::<UciMove>()
+            .map_err(|e| eyre!("Invalid UCI move {uci} at ply {ply}: {e}"))?
+            .to_move(&position)
+            .map_err(|e| eyre!("Illegal move {uci} at ply {ply}: {e}"))?;
+        position.play_unchecked(&m);
+    }
+    Ok(Verification {
+        plies: moves.len(),
+        game_over: game::game_over(&position),
+        position,
+    })
+}
+
+/// Check a stored game's moves and that its result fits them.
+///
+/// With `max_moves`, games drawn by the move limit must have reached it.
+/// Adjudicated results depend on engine scores and are taken as recorded.
+///
+/// # Errors
+/// Returns an error if a move is illegal or the result contradicts the moves.
+pub fn verify(game: &GameRow, max_moves: Option<u32>) -> Result<Verification> {
+    let moves: Vec<&str> = game.moves.split_whitespace().collect();
+    if usize::try_from(game.ply_count).ok() != Some(moves.len()) {
+        return Err(eyre!(
+            "Recorded {} plies but {} moves",
+            game.ply_count,
+            moves.len()
+        ));
+    }
+    let verification = replay_moves(game.start_fen.as_deref(), &moves)?;
+    let recorded = (game.result.as_str(), game.termination.as_str());
+
+    if let Some((result, termination)) = verification.game_over {
+        if recorded != (result.as_str(), termination.as_str()) {
+            return Err(eyre!(
+                "Recorded {} by {}, but the final position is {} by {}",
+                recorded.0,
+                recorded.1,
+                result.as_str(),
+                termination.as_str()
+            ));
+        }
+        return Ok(verification);
+    }
+
+    // The side to move loses when it fails to move or runs out of time
+    let loss = if verification.position.turn() == Color::White {
+        GameResult::BlackWins
+    } else {
+        GameResult::WhiteWins
+    };
+    let expected = match recorded.1 {
+        "no_move" | "time_forfeit" => Some(loss),
+        "max_moves" => {
+            if let Some(max_moves) = max_moves.and_then(|max| usize::try_from(max).ok()) {
+                if moves.len() != max_moves {
+                    return Err(eyre!(
+                        "Recorded max_moves after {} plies, but the limit is {max_moves}",
+                        moves.len()
+                    ));
+                }
+            }
+            Some(GameResult::Draw)
+        }
+        "draw_adjudication" => Some(GameResult::Draw),
+        "resign_adjudication" => None,
+        termination => {
+            return Err(eyre!(
+                "Recorded {termination}, but the final position is not over"
+            ));
+        }
+    };
+    if let Some(expected) = expected.filter(|expected| expected.as_str() != recorded.0) {
+        return Err(eyre!(
+            "Recorded {} by {}, expected {}",
+            recorded.0,
+            recorded.1,
+            expected.as_str()
+        ));
+    }
+    Ok(verification)
+}
+
+/// Check that a stored game was played with the opening and colours its
+/// number maps to.
+///
+/// # Errors
+/// Returns an error describing the first mismatch.
+pub fn check_params(game: &GameRow, params: &GameParams) -> Result<()> {
+    let white = if params.stockfish_is_white {
+        "stockfish"
+    } else {
+        "reckless"
+    };
+    if game.white != white {
+        return Err(eyre!(
+            "Game {} should have {white} as White, but {} played White",
+            game.game_num,
+            game.white
+        ));
+    }
+    if game.start_fen != params.opening {
+        return Err(eyre!(
+            "Game {} should start from {}, but started from {}",
+            game.game_num,
+            params.opening.as_deref().unwrap_or("startpos"),
+            game.start_fen.as_deref().unwrap_or("startpos")
+        ));
+    }
+    Ok(())
+}
+
+/// Play a game again with the engines and settings of `config`, logging
+/// every line exchanged during the game to `transcript` if given.
+///
+/// # Errors
+/// Returns an error if an engine fails to start or the game fails.
+pub async fn rerun(
+    config: &MatchConfig,
+    params: &GameParams,
+    transcript: Option<Transcript>,
+) -> Result<GameRecord> {
+    let mut stockfish = AnyEngine::start(&config.engines.stockfish, "stockfish").await?;
+    let mut reckless = AnyEngine::start(&config.engines.reckless, "reckless").await?;
+    if let Some(transcript) = transcript {
+        stockfish.set_transcript(transcript.clone());
+        reckless.set_transcript(transcript);
+    }
+
+    let runner = GameRunner::from_settings(config.game_settings());
+    let (white, black) = if params.stockfish_is_white {
+        (&mut stockfish, &mut reckless)
+    } else {
+        (&mut reckless, &mut stockfish)
+    };
+    let record = runner
+        .play_game_from(white, black, params.opening.as_deref(), |_| {})
+        .await;
+
+    stockfish.quit().await.ok();
+    reckless.quit().await.ok();
+    record
+}
+
+/// The first ply at which two games' moves differ, if they do.
+#[must_use]
+pub fn first_difference(stored: &GameRow, replayed: &GameRecord) -> Option<usize> {
+    let stored: Vec<&str> = stored.moves.split_whitespace().collect();
+    let differs = stored
+        .iter()
+        .zip(&replayed.plies)
+        .position(|(stored, replayed)| *stored != replayed.uci);
+    differs.or_else(|| {
+        (stored.len() != replayed.plies.len()).then(|| stored.len().min(replayed.plies.len()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(moves: &str, result: &str, termination: &str) -> GameRow {
+        GameRow {
+            id: 1,
+            run_id: 1,
+            game_num: 0,
+            white: "stockfish".to_string(),
+            black: "reckless".to_string(),
+            result: result.to_string(),
+            termination: termination.to_string(),
+            ply_count: u32::try_from(moves.split_whitespace().count()).unwrap_or_default(),
+            moves: moves.to_string(),
+            start_fen: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_fools_mate() {
+        let game = row("f2f3 e7e5 g2g4 d8h4", "0-1", "checkmate");
+        let verification = verify(&game, None).expect("Verification failed");
+        assert_eq!(verification.plies, 4);
+        assert_eq!(
+            verification.game_over,
+            Some((GameResult::BlackWins, Termination::Checkmate))
+        );
+
+        let wrong = row("f2f3 e7e5 g2g4 d8h4", "1/2-1/2", "max_moves");
+        assert!(verify(&wrong, None).is_err());
+        let continued = row("f2f3 e7e5 g2g4 d8h4 e1f2", "0-1", "checkmate");
+        assert!(verify(&continued, None).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_illegal_moves_and_bad_results() {
+        let illegal = row("e2e4 e2e4", "1/2-1/2", "max_moves");
+        let error = verify(&illegal, None).expect_err("Illegal move accepted");
+        assert!(error.to_string().contains("ply 1"), "{error}");
+
+        // White is to move after two plies, so White lost on time
+        assert!(verify(&row("e2e4 e7e5", "0-1", "time_forfeit"), None).is_ok());
+        assert!(verify(&row("e2e4 e7e5", "1-0", "time_forfeit"), None).is_err());
+        assert!(verify(&row("e2e4 e7e5", "1/2-1/2", "max_moves"), Some(2)).is_ok());
+        assert!(verify(&row("e2e4 e7e5", "1/2-1/2", "max_moves"), Some(500)).is_err());
+        assert!(verify(&row("e2e4 e7e5", "1/2-1/2", "stalemate"), None).is_err());
+    }
+
+    #[test]
+    fn test_check_params() {
+        let game = row("e2e4", "1/2-1/2", "max_moves");
+        let mut params = GameParams {
+            game_num: 0,
+            stockfish_is_white: true,
+            opening: None,
+        };
+        assert!(check_params(&game, &params).is_ok());
+        params.stockfish_is_white = false;
+        assert!(check_params(&game, &params).is_err());
+    }
+}
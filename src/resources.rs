@@ -0,0 +1,324 @@
+//! Keeping engine pairs from competing for CPU and memory.
+//!
+//! Each worker's engines can be pinned to cores of their own, so that the
+//! scheduler doesn't move searches around and cost one engine time that the
+//! other didn't lose, and can be given memory and CPU time limits. Both only
+//! work on Linux.
+//!
+//! Since only one engine of a pair searches at a time, workers can also share
+//! a pool of search slots instead, letting more pairs than cores play without
+//! more searches running than there are cores to run them.
+
+use color_eyre::eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// How many engines may search at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Concurrency {
+    /// Every worker searches whenever it likes, one engine at a time
+    #[default]
+    Pairs,
+    /// Searches across all workers share the available cores
+    Searches,
+}
+
+impl Concurrency {
+    /// The name used in configuration files.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pairs => "pairs",
+            Self::Searches => "searches",
+        }
+    }
+}
+
+/// Limits applied to every engine process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Resources {
+    /// Pin each worker's engines to cores of their own
+    pub pin_cores: bool,
+    /// Largest address space of an engine process, in MiB
+    pub memory_mb: Option<u64>,
+    /// CPU time an engine process may use over its lifetime, in seconds
+    pub cpu_seconds: Option<u64>,
+    /// How many engines may search at once
+    pub concurrency: Concurrency,
+}
+
+impl Resources {
+    /// Check that `workers` engine pairs searching with `threads` threads
+    /// each get cores of their own, if cores are pinned.
+    ///
+    /// # Errors
+    /// Returns an error if there are too few cores.
+    pub fn check(&self, workers: usize, threads: usize) -> Result<()> {
+        if !self.pin_cores {
+            return Ok(());
+        }
+        let cores = available_cores()?.len();
+        if workers.saturating_mul(threads) > cores {
+            return Err(eyre!(
+                "{workers} workers with {threads} threads each need more than the {cores} available cores"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that engines pinned to their worker's cores don't ponder, since
+    /// an engine pondering on its opponent's time would then search on the
+    /// same cores as its opponent.
+    ///
+    /// # Errors
+    /// Returns an error if cores are pinned and `ponder` is set.
+    pub fn check_ponder(&self, ponder: bool) -> Result<()> {
+        if self.pin_cores && ponder {
+            return Err(eyre!("Pondering cannot be combined with pinned cores"));
+        }
+        Ok(())
+    }
+
+    /// The search slots workers share, one per available core, with each
+    /// search taking `threads` of them; `None` unless searches are limited.
+    ///
+    /// # Errors
+    /// Returns an error if the available cores cannot be read.
+    pub fn search_slots(&self, threads: usize) -> Result<Option<SearchSlots>> {
+        if self.concurrency == Concurrency::Pairs {
+            return Ok(None);
+        }
+        let cores = available_cores()?.len();
+        Ok(Some(SearchSlots::new(cores, threads)))
+    }
+
+    /// Pin and limit the engine process `pid` of worker `worker_id`, whose
+    /// engines search with `threads` threads.
+    ///
+    /// # Errors
+    /// Returns an error if the process cannot be pinned or limited.
+    pub fn apply(&self, pid: u32, worker_id: usize, threads: usize) -> Result<()> {
+        if self.pin_cores {
+            let cores = assign_cores(&available_cores()?, worker_id, threads)
+                .ok_or_else(|| eyre!("No cores left for worker {worker_id}"))?;
+            tracing::debug!(pid, worker = worker_id, ?cores, "Pinning engine");
+            sys::pin(pid, &cores)?;
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            sys::limit_memory(pid, memory_mb.saturating_mul(1024 * 1024))?;
+        }
+        if let Some(cpu_seconds) = self.cpu_seconds {
+            sys::limit_cpu(pid, cpu_seconds)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cores shared by the searches of every worker.
+#[derive(Debug, Clone)]
+pub struct SearchSlots {
+    cores: Arc<Semaphore>,
+    per_search: u32,
+}
+
+impl SearchSlots {
+    /// Slots for `cores` cores, with each search using `threads` of them.
+    ///
+    /// A search always gets to run, even with more threads than cores.
+    #[must_use]
+    pub fn new(cores: usize, threads: usize) -> Self {
+        let per_search = threads.clamp(1, cores.max(1));
+        Self {
+            cores: Arc::new(Semaphore::new(cores.max(1))),
+            per_search: u32::try_from(per_search).unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Wait for enough cores to be free for one search, holding them until
+    /// the permit is dropped.
+    ///
+    /// # Errors
+    /// Returns an error if the slots were closed.
+    pub async fn acquire(&self) -> Result<tokio::sync::SemaphorePermit<'_>> {
+        Ok(self.cores.acquire_many(self.per_search).await?)
+    }
+}
+
+/// The cores worker `worker_id` gets out of `available`, `threads` of them,
+/// or `None` if they run out.
+fn assign_cores(available: &[usize], worker_id: usize, threads: usize) -> Option<Vec<usize>> {
+    let start = worker_id.checked_mul(threads)?;
+    available
+        .get(start..start.checked_add(threads)?)
+        .map(<[usize]>::to_vec)
+}
+
+/// The cores this process may run on.
+fn available_cores() -> Result<Vec<usize>> {
+    sys::available_cores()
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use color_eyre::eyre::{Result, WrapErr};
+    use nix::sched::{CpuSet, sched_getaffinity, sched_setaffinity};
+    use nix::unistd::Pid;
+    use rlimit::Resource;
+
+    fn pid_of(pid: u32) -> Result<Pid> {
+        Ok(Pid::from_raw(i32::try_from(pid)?))
+    }
+
+    pub fn available_cores() -> Result<Vec<usize>> {
+        let set = sched_getaffinity(Pid::from_raw(0))?;
+        Ok((0..CpuSet::count())
+            .filter(|&core| set.is_set(core).unwrap_or(false))
+            .collect())
+    }
+
+    /// Pin every thread of process `pid` to `cores`.
+    ///
+    /// Threads started later inherit the affinity of the thread starting them.
+    pub fn pin(pid: u32, cores: &[usize]) -> Result<()> {
+        let mut set = CpuSet::new();
+        for &core in cores {
+            set.set(core)?;
+        }
+        let tasks = std::fs::read_dir(format!("/proc/{pid}/task"))
+            .wrap_err_with(|| format!("Failed to list threads of process {pid}"))?;
+        for task in tasks {
+            let Some(tid) = task?.file_name().to_str().and_then(|tid| tid.parse().ok()) else {
+                continue;
+            };
+            sched_setaffinity(pid_of(tid)?, &set)
+                .wrap_err_with(|| format!("Failed to pin thread {tid} of process {pid}"))?;
+        }
+        Ok(())
+    }
+
+    fn limit(pid: u32, resource: Resource, value: u64) -> Result<()> {
+        rlimit::prlimit(i32::try_from(pid)?, resource, Some((value, value)), None)
+            .wrap_err_with(|| format!("Failed to set {} of process {pid}", resource.as_name()))
+    }
+
+    pub fn limit_memory(pid: u32, bytes: u64) -> Result<()> {
+        limit(pid, Resource::AS, bytes)
+    }
+
+    pub fn limit_cpu(pid: u32, seconds: u64) -> Result<()> {
+        limit(pid, Resource::CPU, seconds)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use color_eyre::eyre::{Result, eyre};
+
+    pub fn available_cores() -> Result<Vec<usize>> {
+        Ok((0..std::thread::available_parallelism()?.get()).collect())
+    }
+
+    pub fn pin(_pid: u32, _cores: &[usize]) -> Result<()> {
+        Err(eyre!("Pinning engines to cores is only supported on Linux"))
+    }
+
+    pub fn limit_memory(_pid: u32, _bytes: u64) -> Result<()> {
+        Err(eyre!("Memory limits are only supported on Linux"))
+    }
+
+    pub fn limit_cpu(_pid: u32, _seconds: u64) -> Result<()> {
+        Err(eyre!("CPU time limits are only supported on Linux"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_assign_cores() {
+        let available = [0, 1, 2, 3, 6, 7];
+        assert_eq!(assign_cores(&available, 0, 2), Some(vec![0, 1]));
+        assert_eq!(assign_cores(&available, 2, 2), Some(vec![6, 7]));
+        assert_eq!(assign_cores(&available, 3, 2), None);
+        assert_eq!(assign_cores(&available, 5, 1), Some(vec![7]));
+    }
+
+    #[tokio::test]
+    async fn test_search_slots_share_cores() {
+        let slots = SearchSlots::new(4, 2);
+        let first = slots.acquire().await.expect("Failed to acquire");
+        let _second = slots.acquire().await.expect("Failed to acquire");
+        let waiting = tokio::time::timeout(Duration::from_millis(20), slots.acquire()).await;
+        assert!(waiting.is_err(), "Third search started on two free cores");
+        drop(waiting);
+        drop(first);
+        assert!(slots.acquire().await.is_ok());
+
+        // More threads than cores still get to search
+        let small = SearchSlots::new(1, 8);
+        assert!(small.acquire().await.is_ok());
+    }
+
+    #[test]
+    fn test_check_core_count() {
+        let pinned = Resources {
+            pin_cores: true,
+            ..Resources::default()
+        };
+        let cores = available_cores().expect("Failed to read cores").len();
+        assert!(pinned.check(cores, 1).is_ok());
+        assert!(pinned.check(cores + 1, 1).is_err());
+        assert!(Resources::default().check(cores + 1, 1).is_ok());
+    }
+
+    #[test]
+    fn test_pinned_engines_cannot_ponder() {
+        let pinned = Resources {
+            pin_cores: true,
+            ..Resources::default()
+        };
+        assert!(pinned.check_ponder(false).is_ok());
+        assert!(pinned.check_ponder(true).is_err());
+        assert!(Resources::default().check_ponder(true).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_apply_to_child_process() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .expect("Failed to spawn sleep");
+        let resources = Resources {
+            pin_cores: true,
+            memory_mb: Some(512),
+            cpu_seconds: Some(60),
+            ..Resources::default()
+        };
+        let result = resources.apply(child.id(), 0, 1);
+        let limits = std::fs::read_to_string(format!("/proc/{}/limits", child.id()));
+        child.kill().ok();
+        child.wait().ok();
+
+        result.expect("Failed to apply resources");
+        let limits = limits.expect("Failed to read limits");
+        assert!(
+            limits
+                .lines()
+                .any(|line| line.starts_with("Max address space")
+                    && line.contains(&(512 * 1024 * 1024).to_string())),
+            "{limits}"
+        );
+        assert!(
+            limits
+                .lines()
+                .any(|line| line.starts_with("Max cpu time") && line.contains("60")),
+            "{limits}"
+        );
+    }
+}
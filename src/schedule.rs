@@ -0,0 +1,116 @@
+//! Deterministic game schedules.
+//!
+//! Everything about a game that isn't decided over the board — its opening
+//! and which engine plays White — follows from the match seed and the game
+//! number alone, so any game can be replayed in isolation no matter which
+//! worker happened to play it.
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// The pre-game parameters of one game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameParams {
+    /// Number of the game within the match
+    pub game_num: u64,
+    /// Whether Stockfish plays White
+    pub stockfish_is_white: bool,
+    /// Starting position as a FEN, or `None` for the standard one
+    pub opening: Option<String>,
+}
+
+/// Maps game numbers to their parameters for a given seed.
+///
+/// Games are played in pairs sharing an opening, one with each engine as
+/// White. Openings are shuffled once by the seed and then used in turn; which
+/// game of a pair has Stockfish as White is also drawn from the seed.
+#[derive(Debug, Clone)]
+pub struct GameSchedule {
+    seed: u64,
+    openings: Vec<String>,
+}
+
+impl GameSchedule {
+    /// A schedule drawing from `openings`, which may be empty to always start
+    /// from the standard position.
+    #[must_use]
+    pub fn new(seed: u64, mut openings: Vec<String>) -> Self {
+        openings.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+        Self { seed, openings }
+    }
+
+    /// The seed the schedule was built from.
+    #[must_use]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A random number generator for game `game_num` alone, e.g. for
+    /// randomised adjudication.
+    #[must_use]
+    pub fn rng(&self, game_num: u64) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(game_num);
+        rng
+    }
+
+    /// The parameters of game `game_num`.
+    #[must_use]
+    pub fn game(&self, game_num: u64) -> GameParams {
+        let pair = game_num / 2;
+        // Both games of a pair draw from the pair's first game's stream
+        let swap = self.rng(pair * 2).random::<bool>();
+        let opening = u64::try_from(self.openings.len())
+            .ok()
+            .filter(|&len| len > 0)
+            .and_then(|len| usize::try_from(pair % len).ok())
+            .and_then(|index| self.openings.get(index).cloned());
+        GameParams {
+            game_num,
+            stockfish_is_white: (game_num % 2 == 0) != swap,
+            opening,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn openings() -> Vec<String> {
+        (0..8).map(|n| format!("opening {n}")).collect()
+    }
+
+    #[test]
+    fn test_games_depend_only_on_seed_and_number() {
+        let schedule = GameSchedule::new(42, openings());
+        let again = GameSchedule::new(42, openings());
+        for game_num in (0..100).rev() {
+            assert_eq!(schedule.game(game_num), again.game(game_num));
+        }
+
+        let other = GameSchedule::new(43, openings());
+        assert!((0..100).any(|game_num| schedule.game(game_num) != other.game(game_num)));
+    }
+
+    #[test]
+    fn test_pairs_share_an_opening_with_swapped_colours() {
+        let schedule = GameSchedule::new(7, openings());
+        let mut seen = Vec::new();
+        for pair in 0..8 {
+            let first = schedule.game(pair * 2);
+            let second = schedule.game(pair * 2 + 1);
+            assert_eq!(first.opening, second.opening);
+            assert_ne!(first.stockfish_is_white, second.stockfish_is_white);
+            seen.push(first.opening.expect("Opening missing"));
+        }
+        // Every opening is used once before any repeats
+        seen.sort();
+        assert_eq!(seen, openings());
+
+        let standard = GameSchedule::new(7, Vec::new());
+        assert_eq!(standard.game(3).opening, None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Graceful shutdown on SIGINT/SIGTERM, or Ctrl-C where there is no SIGTERM.
+//!
+//! The first request stops workers from claiming new games and lets the games
+//! in progress finish. A second request aborts those games as well.
+
+use color_eyre::eyre::Result;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use tokio::sync::watch;
+
+/// Shutdown state shared between the signal handler, the dashboard and workers.
+pub struct Shutdown {
+    game_counter: Arc<AtomicU64>,
+    total_games: u64,
+    requests: AtomicU8,
+    abort: watch::Sender<bool>,
+}
+
+impl Shutdown {
+    /// Create the shutdown state for a match that claims games from `game_counter`.
+    #[must_use]
+    pub fn new(game_counter: Arc<AtomicU64>, total_games: u64) -> Self {
+        Self {
+            game_counter,
+            total_games,
+            requests: AtomicU8::new(0),
+            abort: watch::channel(false).0,
+        }
+    }
+
+    /// A receiver that becomes `true` when games in progress should be aborted.
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.abort.subscribe()
+    }
+
+    /// Ask the match to stop.
+    ///
+    /// The first request pushes `game_counter` past the total so no worker
+    /// can claim another game. The second aborts the games being played.
+    pub fn request(&self) {
+        match self.requests.fetch_add(1, Ordering::Relaxed) {
+            0 => {
+                self.game_counter
+                    .fetch_max(self.total_games, Ordering::Relaxed);
+                tracing::warn!(
+                    "Shutting down: finishing games in progress, request again to abort them"
+                );
+            }
+            1 => {
+                tracing::warn!("Aborting games in progress");
+                self.abort.send_replace(true);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Wait for the next SIGINT or SIGTERM.
+#[cfg(unix)]
+async fn next_signal() -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+/// Wait for the next Ctrl-C.
+#[cfg(not(unix))]
+async fn next_signal() -> Result<()> {
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Turn SIGINT and SIGTERM into shutdown requests.
+pub async fn handle_signals(shutdown: Arc<Shutdown>) {
+    loop {
+        if let Err(e) = next_signal().await {
+            tracing::error!(error = %e, "Failed to listen for shutdown signals");
+            return;
+        }
+        shutdown.request();
+    }
+}
+
+/// Wait until the match has been aborted.
+///
+/// Never completes if the abort sender goes away without aborting.
+pub async fn aborted(abort: &mut watch::Receiver<bool>) {
+    if abort.wait_for(|aborted| *aborted).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_stop_then_abort() {
+        let game_counter = Arc::new(AtomicU64::new(3));
+        let shutdown = Shutdown::new(Arc::clone(&game_counter), 10);
+        let abort = shutdown.subscribe();
+
+        shutdown.request();
+        assert_eq!(game_counter.load(Ordering::Relaxed), 10);
+        assert!(!*abort.borrow());
+
+        shutdown.request();
+        assert!(*abort.borrow());
+
+        // Further requests change nothing
+        shutdown.request();
+        assert_eq!(game_counter.load(Ordering::Relaxed), 10);
+        assert!(*abort.borrow());
+    }
+
+    #[test]
+    fn test_request_keeps_a_counter_already_past_the_total() {
+        let game_counter = Arc::new(AtomicU64::new(12));
+        Shutdown::new(Arc::clone(&game_counter), 10).request();
+        assert_eq!(game_counter.load(Ordering::Relaxed), 12);
+    }
+}
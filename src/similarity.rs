@@ -0,0 +1,163 @@
+//! Move agreement between engines, the classic "similarity" test.
+//!
+//! Every engine searches the same positions with the same budget, and each
+//! pair of engines is scored by how often they chose the same move. Builds
+//! that share most of their evaluation agree far more often than unrelated
+//! engines of similar strength.
+
+use crate::analysis;
+use crate::engine::{EngineConfig, SearchLimits};
+use crate::epd::Epd;
+use color_eyre::eyre::{Result, eyre};
+use std::fmt;
+
+/// An engine taking part, under the name it is shown by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contestant {
+    /// The name in the matrix
+    pub label: String,
+    /// How to start it
+    pub config: EngineConfig,
+}
+
+impl Contestant {
+    /// Parse `label=path`, e.g. `net42=./reckless`.
+    ///
+    /// # Errors
+    /// Returns an error if there is no `=` or either side is empty.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.split_once('=') {
+            Some((label, path)) if !label.is_empty() && !path.is_empty() => Ok(Self {
+                label: label.to_string(),
+                config: EngineConfig::new(path),
+            }),
+            _ => Err(eyre!("Expected LABEL=PATH, got '{spec}'")),
+        }
+    }
+}
+
+/// Every engine's best move in every position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matrix {
+    /// The engines, in the order given
+    pub labels: Vec<String>,
+    /// Each engine's moves in UCI notation, in position order
+    pub moves: Vec<Vec<String>>,
+}
+
+impl Matrix {
+    /// The percentage of positions in which engines `a` and `b` chose the
+    /// same move.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn agreement(&self, a: usize, b: usize) -> f64 {
+        let (a, b) = (&self.moves[a], &self.moves[b]);
+        let same = a.iter().zip(b).filter(|(a, b)| a == b).count();
+        same as f64 * 100.0 / a.len().min(b.len()).max(1) as f64
+    }
+}
+
+impl fmt::Display for Matrix {
+    /// A table of the pairwise agreement percentages, one row per engine.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .labels
+            .iter()
+            .map(String::len)
+            .max()
+            .unwrap_or_default()
+            .max(6);
+        write!(f, "{:width$}", "")?;
+        for label in &self.labels {
+            write!(f, " {label:>width$}")?;
+        }
+        for (a, label) in self.labels.iter().enumerate() {
+            write!(f, "\n{label:width$}")?;
+            for b in 0..self.labels.len() {
+                write!(f, " {:>width$.1}", self.agreement(a, b))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Have each engine in turn search every position with `limits`, using
+/// `workers` copies of it, and collect their moves.
+///
+/// # Errors
+/// Returns an error if an engine fails to start or a search fails.
+pub async fn compare(
+    contestants: &[Contestant],
+    positions: &[Epd],
+    limits: SearchLimits,
+    workers: usize,
+) -> Result<Matrix> {
+    let mut moves = Vec::new();
+    for contestant in contestants {
+        tracing::info!(
+            engine = contestant.label,
+            positions = positions.len(),
+            "Searching"
+        );
+        let results = analysis::analyse(
+            &contestant.config,
+            &contestant.label,
+            positions.to_vec(),
+            limits,
+            workers,
+            |_| {},
+            |_| Ok(()),
+        )
+        .await?;
+        moves.push(
+            analysis::require_all(results)?
+                .into_iter()
+                .map(|analysis| analysis.search.best_move)
+                .collect(),
+        );
+    }
+    Ok(Matrix {
+        labels: contestants
+            .iter()
+            .map(|contestant| contestant.label.clone())
+            .collect(),
+        moves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agreement_matrix() {
+        let moves = |line: &str| line.split_whitespace().map(str::to_string).collect();
+        let matrix = Matrix {
+            labels: vec![
+                "stockfish".to_string(),
+                "net1".to_string(),
+                "net2".to_string(),
+            ],
+            moves: vec![
+                moves("e2e4 d7d5 g1f3 a2a3"),
+                moves("e2e4 d7d5 g1f3 h2h3"),
+                moves("d2d4 d7d5 b1c3 h2h3"),
+            ],
+        };
+        assert!((matrix.agreement(0, 1) - 75.0).abs() < f64::EPSILON);
+        assert!((matrix.agreement(1, 2) - 50.0).abs() < f64::EPSILON);
+        assert!((matrix.agreement(2, 2) - 100.0).abs() < f64::EPSILON);
+        assert_eq!(
+            matrix.to_string(),
+            "          stockfish      net1      net2\n\
+             stockfish     100.0      75.0      25.0\n\
+             net1           75.0     100.0      50.0\n\
+             net2           25.0      50.0     100.0"
+        );
+
+        let contestant = Contestant::parse("net1=./reckless").expect("Failed to parse");
+        assert_eq!(contestant.label, "net1");
+        assert_eq!(contestant.config.path, "./reckless");
+        assert!(Contestant::parse("./reckless").is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//! Scoring an engine against EPD test suites such as WAC or STS.
+//!
+//! A position is solved when the engine's final move is one of its `bm` moves
+//! and none of its `am` moves. The time to solution is when the engine first
+//! found a correct move, from its principal variation updates; when it
+//! settled on one for good is reported too.
+//! STS positions also give points for the alternatives listed in `c0`.
+
+use crate::analysis::Analysis;
+use crate::epd::{self, Epd};
+use color_eyre::eyre::{Result, eyre};
+use std::collections::BTreeMap;
+
+/// What a test position expects of the engine.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expected {
+    /// Moves that solve the position (`bm`), in UCI notation
+    pub best: Vec<String>,
+    /// Moves that fail it (`am`), in UCI notation
+    pub avoid: Vec<String>,
+    /// Points for each move STS scores, in UCI notation
+    pub points: Vec<(String, u32)>,
+    /// The STS theme, such as "Undermining"
+    pub theme: Option<String>,
+}
+
+impl Expected {
+    /// Read the expected moves from `epd`'s `bm`, `am`, `c0` and `id`.
+    ///
+    /// # Errors
+    /// Returns an error if the record has neither `bm` nor `am`, or names an
+    /// illegal move.
+    pub fn from_epd(epd: &Epd) -> Result<Self> {
+        let position = epd.position()?;
+        let moves = |opcode: &str| {
+            let san = epd.operands(opcode).unwrap_or_default();
+            epd::uci_moves(&position, san)
+                .ok_or_else(|| eyre!("Illegal {opcode} move in '{}'", san.join(" ")))
+        };
+        let best = moves("bm")?;
+        let avoid = moves("am")?;
+        if best.is_empty() && avoid.is_empty() {
+            return Err(eyre!("Position '{}' has no bm or am", epd.fen));
+        }
+
+        let points = epd
+            .operand("c0")
+            .and_then(parse_points)
+            .map(|points| {
+                points
+                    .into_iter()
+                    .filter_map(|(san, points)| {
+                        let uci = epd::uci_moves(&position, &[san])?.pop()?;
+                        Some((uci, points))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let theme = if points.is_empty() {
+            None
+        } else {
+            epd.operand("id").map(theme_of)
+        };
+        Ok(Self {
+            best,
+            avoid,
+            points,
+            theme,
+        })
+    }
+
+    /// Whether playing `uci` solves the position.
+    #[must_use]
+    pub fn is_correct(&self, uci: &str) -> bool {
+        (self.best.is_empty() || self.best.iter().any(|m| m == uci))
+            && !self.avoid.iter().any(|m| m == uci)
+    }
+
+    /// The points STS gives for `uci`, out of the most any move gets.
+    #[must_use]
+    pub fn points_for(&self, uci: &str) -> Option<(u32, u32)> {
+        let max = self.points.iter().map(|&(_, points)| points).max()?;
+        let points = self
+            .points
+            .iter()
+            .find(|(m, _)| m == uci)
+            .map_or(0, |&(_, points)| points);
+        Some((points, max))
+    }
+}
+
+/// STS move scores from a `c0` such as `"f5=10, Be5+=2, Bf2=3"`, or `None` if
+/// the comment is anything else.
+fn parse_points(comment: &str) -> Option<Vec<(String, u32)>> {
+    comment
+        .split(',')
+        .map(|entry| {
+            let (san, points) = entry.trim().rsplit_once('=')?;
+            Some((san.to_string(), points.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// The theme of an STS `id` such as `"STS(v1.0) Undermining.001"`.
+fn theme_of(id: &str) -> String {
+    let name = id.split_once(") ").map_or(id, |(_, name)| name);
+    let name = name
+        .rsplit_once('.')
+        .filter(|(_, number)| number.chars().all(|c| c.is_ascii_digit()))
+        .map_or(name, |(name, _)| name);
+    name.trim().to_string()
+}
+
+/// How the engine did on one test position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// Whether the engine's final move solves the position
+    pub solved: bool,
+    /// When the engine first found a correct move, in milliseconds
+    pub found_ms: Option<u64>,
+    /// The depth it first found a correct move at
+    pub found_depth: Option<u32>,
+    /// When the engine settled on a correct move for good, in milliseconds
+    pub settled_ms: Option<u64>,
+    /// The depth it settled on a correct move at
+    pub settled_depth: Option<u32>,
+    /// STS points scored, out of the most the position gives
+    pub points: Option<(u32, u32)>,
+    /// The STS theme
+    pub theme: Option<String>,
+}
+
+/// Score `analysis` of a position against what the position `expected`.
+#[must_use]
+pub fn score(expected: &Expected, analysis: &Analysis) -> Outcome {
+    let solved = expected.is_correct(&analysis.search.best_move);
+    // The first correct move, and the start of the last run of them that the
+    // engine ended the search on
+    let (mut found, mut settled) = (None, None);
+    for change in &analysis.changes {
+        if !expected.is_correct(&change.best_move) {
+            settled = None;
+        } else if settled.is_none() {
+            settled = Some((Some(change.time_ms), change.depth));
+            found = found.or(settled);
+        }
+    }
+    let search = (analysis.search.time_ms, analysis.search.depth);
+    let ((found_ms, found_depth), (settled_ms, settled_depth)) = if solved {
+        (found.unwrap_or(search), settled.unwrap_or(search))
+    } else {
+        ((None, None), (None, None))
+    };
+    Outcome {
+        solved,
+        found_ms,
+        found_depth,
+        settled_ms,
+        settled_depth,
+        points: expected.points_for(&analysis.search.best_move),
+        theme: expected.theme.clone(),
+    }
+}
+
+/// Positions, solutions and points for one STS theme, or the whole suite.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    /// Positions scored
+    pub positions: u32,
+    /// Positions solved
+    pub solved: u32,
+    /// STS points scored
+    pub points: u32,
+    /// The most STS points there were to score
+    pub max_points: u32,
+}
+
+impl Tally {
+    fn add(&mut self, outcome: &Outcome) {
+        self.positions += 1;
+        self.solved += u32::from(outcome.solved);
+        if let Some((points, max)) = outcome.points {
+            self.points += points;
+            self.max_points += max;
+        }
+    }
+}
+
+/// Totals over a whole test suite.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Totals over every position
+    pub total: Tally,
+    /// Totals for each STS theme
+    pub themes: BTreeMap<String, Tally>,
+    found_ms: Vec<u64>,
+}
+
+impl Report {
+    /// Count one position's outcome.
+    pub fn add(&mut self, outcome: &Outcome) {
+        self.total.add(outcome);
+        if let Some(theme) = &outcome.theme {
+            self.themes.entry(theme.clone()).or_default().add(outcome);
+        }
+        if let Some(found_ms) = outcome.found_ms {
+            self.found_ms.push(found_ms);
+        }
+    }
+
+    /// Mean time to solution over the solved positions that reported one.
+    #[must_use]
+    pub fn mean_found_ms(&self) -> Option<u64> {
+        let count = u64::try_from(self.found_ms.len()).ok().filter(|&n| n > 0)?;
+        Some(self.found_ms.iter().sum::<u64>() / count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::BestMoveChange;
+    use crate::engine::SearchResult;
+
+    fn analysis(best_move: &str, changes: &[(&str, u64, u32)]) -> Analysis {
+        Analysis {
+            epd: Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - -").expect("Invalid EPD"),
+            search: SearchResult {
+                best_move: best_move.to_string(),
+                ..SearchResult::default()
+            },
+            changes: changes
+                .iter()
+                .map(|&(best_move, time_ms, depth)| BestMoveChange {
+                    best_move: best_move.to_string(),
+                    time_ms,
+                    depth: Some(depth),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_time_to_solution_is_when_the_engine_first_found_it() {
+        let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - bm e4; am Kd1;").expect("Invalid EPD");
+        let expected = Expected::from_epd(&epd).expect("Invalid expectations");
+        assert_eq!(expected.best, vec!["e2e4"]);
+        assert_eq!(expected.avoid, vec!["e1d1"]);
+
+        // Found at depth 2, lost at depth 3, found again for good at depth 5
+        let outcome = score(
+            &expected,
+            &analysis(
+                "e2e4",
+                &[
+                    ("e2e3", 1, 1),
+                    ("e2e4", 3, 2),
+                    ("e1d1", 8, 3),
+                    ("e2e4", 20, 5),
+                ],
+            ),
+        );
+        assert!(outcome.solved);
+        assert_eq!((outcome.found_ms, outcome.found_depth), (Some(3), Some(2)));
+        assert_eq!(
+            (outcome.settled_ms, outcome.settled_depth),
+            (Some(20), Some(5))
+        );
+        assert_eq!(outcome.points, None);
+
+        let outcome = score(
+            &expected,
+            &analysis("e1d1", &[("e2e4", 3, 2), ("e1d1", 8, 3)]),
+        );
+        assert!(!outcome.solved);
+        assert_eq!((outcome.found_ms, outcome.settled_ms), (None, None));
+
+        // With only am, anything else solves it
+        let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - am Kd1;").expect("Invalid EPD");
+        let expected = Expected::from_epd(&epd).expect("Invalid expectations");
+        assert!(expected.is_correct("e1f2"));
+        assert!(!expected.is_correct("e1d1"));
+
+        let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - id \"none\";").expect("Invalid EPD");
+        assert!(Expected::from_epd(&epd).is_err());
+    }
+
+    #[test]
+    fn test_sts_points_and_themes() {
+        let epd = Epd::parse(
+            r#"4k3/8/8/8/8/8/4P3/4K3 w - - bm e4; id "STS(v1.0) Undermining.001"; c0 "e4=10, e3=4, Kd2=1";"#,
+        )
+        .expect("Invalid EPD");
+        let expected = Expected::from_epd(&epd).expect("Invalid expectations");
+        assert_eq!(expected.theme.as_deref(), Some("Undermining"));
+        assert_eq!(expected.points_for("e2e3"), Some((4, 10)));
+        assert_eq!(expected.points_for("e1f1"), Some((0, 10)));
+
+        let mut report = Report::default();
+        report.add(&score(&expected, &analysis("e2e4", &[("e2e4", 5, 1)])));
+        report.add(&score(&expected, &analysis("e2e3", &[("e2e3", 7, 1)])));
+        let theme = report.themes["Undermining"];
+        assert_eq!(
+            theme,
+            Tally {
+                positions: 2,
+                solved: 1,
+                points: 14,
+                max_points: 20,
+            }
+        );
+        assert_eq!(report.total, theme);
+        assert_eq!(report.mean_found_ms(), Some(5));
+
+        assert_eq!(theme_of("WAC.001"), "WAC");
+        assert_eq!(parse_points("mate in 3"), None);
+    }
+}
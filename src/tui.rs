@@ -0,0 +1,493 @@
+//! Live terminal dashboard for a running match.
+//!
+//! Shows the overall score with Elo and SPRT statistics, what every worker is
+//! doing, a board for one selected game and the most recent failures.
+
+use crate::elo::Sprt;
+use crate::match_runner::{MatchStats, WorkerEvent};
+use color_eyre::eyre::Result;
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use shakmaty::fen::Fen;
+use shakmaty::{Board, File, Piece, Rank, Square};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Whether the dashboard currently owns the terminal.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Number of failures kept for display.
+const FAILURE_ROWS: u16 = 8;
+
+/// Something the user asked for from the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Stop the match (twice to abort games in progress)
+    Quit,
+    /// Show the next worker's game on the board
+    SelectNext,
+    /// Show the previous worker's game on the board
+    SelectPrevious,
+}
+
+/// What a worker is currently doing.
+#[derive(Default)]
+struct WorkerView {
+    game_num: Option<u64>,
+    stockfish_is_white: bool,
+    ply: usize,
+    last_move: String,
+    stockfish_nps: Option<u64>,
+    reckless_nps: Option<u64>,
+    board: Option<Board>,
+}
+
+/// A game that failed.
+struct Failure {
+    at: Duration,
+    worker_id: usize,
+    game_num: u64,
+    error: String,
+}
+
+/// Dashboard state, built from worker events.
+struct Dashboard {
+    workers: Vec<WorkerView>,
+    failures: VecDeque<Failure>,
+    selected: usize,
+    started: Instant,
+    total_games: u64,
+    sprt: Sprt,
+}
+
+impl Dashboard {
+    fn new(workers: usize, total_games: u64, sprt: Sprt) -> Self {
+        Self {
+            workers: (0..workers).map(|_| WorkerView::default()).collect(),
+            failures: VecDeque::new(),
+            selected: 0,
+            started: Instant::now(),
+            total_games,
+            sprt,
+        }
+    }
+
+    fn apply(&mut self, event: &WorkerEvent) {
+        match event {
+            WorkerEvent::GameStarted {
+                worker_id,
+                game_num,
+                stockfish_is_white,
+                start_fen,
+            } => {
+                let board = start_fen
+                    .as_deref()
+                    .and_then(|fen| fen.parse::<Fen>().ok())
+                    .map_or_else(Board::default, |fen| fen.into_setup().board);
+                if let Some(view) = self.workers.get_mut(*worker_id) {
+                    *view = WorkerView {
+                        game_num: Some(*game_num),
+                        stockfish_is_white: *stockfish_is_white,
+                        board: Some(board),
+                        ..WorkerView::default()
+                    };
+                }
+            }
+            WorkerEvent::MovePlayed {
+                worker_id,
+                ply,
+                uci,
+                board,
+                nps,
+                stockfish_moved,
+            } => {
+                if let Some(view) = self.workers.get_mut(*worker_id) {
+                    view.ply = *ply;
+                    view.last_move.clone_from(uci);
+                    view.board = Some(board.clone());
+                    if *stockfish_moved {
+                        view.stockfish_nps = *nps;
+                    } else {
+                        view.reckless_nps = *nps;
+                    }
+                }
+            }
+            WorkerEvent::GameCompleted(_) => {}
+            WorkerEvent::GameFailed {
+                worker_id,
+                game_num,
+                error,
+            } => {
+                self.failures.push_front(Failure {
+                    at: self.started.elapsed(),
+                    worker_id: *worker_id,
+                    game_num: *game_num,
+                    error: error.clone(),
+                });
+                self.failures.truncate(usize::from(FAILURE_ROWS));
+            }
+        }
+    }
+
+    fn select(&mut self, action: Action) {
+        let count = self.workers.len().max(1);
+        self.selected = match action {
+            Action::SelectNext => (self.selected + 1) % count,
+            Action::SelectPrevious => (self.selected + count - 1) % count,
+            Action::Quit => self.selected,
+        };
+    }
+
+    fn render(&self, frame: &mut Frame, stats: &MatchStats) {
+        let [header, middle, failures] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(12),
+            Constraint::Length(FAILURE_ROWS + 2),
+        ])
+        .areas(frame.area());
+        let [workers, board] =
+            Layout::horizontal([Constraint::Min(60), Constraint::Length(24)]).areas(middle);
+
+        self.render_header(frame, header, stats);
+        self.render_workers(frame, workers);
+        self.render_board(frame, board);
+        self.render_failures(frame, failures);
+    }
+
+    /// Overall score, Elo and SPRT state.
+    #[allow(clippy::cast_precision_loss)]
+    fn render_header(&self, frame: &mut Frame, area: Rect, stats: &MatchStats) {
+        let wdl = stats.reckless_wdl();
+        let elo = wdl.elo().map_or_else(
+            || "Elo: n/a".to_string(),
+            |(elo, margin)| format!("Elo: {elo:+.1} ± {margin:.1}"),
+        );
+        let (lower, upper) = self.sprt.bounds();
+        let llr = wdl.llr(self.sprt.elo0, self.sprt.elo1);
+        let elapsed = self.started.elapsed();
+        let games_per_hour = wdl.total() as f64 / elapsed.as_secs_f64().max(1.0) * 3600.0;
+        let summary = vec![
+            Line::from(format!(
+                "Games: {}/{}   Reckless W/D/L: {}/{}/{}   {elo}",
+                wdl.total(),
+                self.total_games,
+                wdl.wins,
+                wdl.draws,
+                wdl.losses
+            )),
+            Line::from(format!(
+                "LLR: {llr:.2} [{lower:.2}, {upper:.2}] for elo0={} elo1={}   Elapsed: {}s   {games_per_hour:.0} games/hour",
+                self.sprt.elo0,
+                self.sprt.elo1,
+                elapsed.as_secs()
+            )),
+        ];
+        frame.render_widget(
+            Paragraph::new(summary).block(Block::bordered().title(" Reckless vs Stockfish ")),
+            area,
+        );
+    }
+
+    /// What every worker is doing.
+    fn render_workers(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.workers.iter().enumerate().map(|(id, view)| {
+            let nps = |nps: Option<u64>| nps.map_or_else(|| "-".to_string(), |n| n.to_string());
+            let row = Row::new(vec![
+                id.to_string(),
+                view.game_num
+                    .map_or_else(|| "-".to_string(), |n| n.to_string()),
+                if view.game_num.is_none() {
+                    "-".to_string()
+                } else if view.stockfish_is_white {
+                    "stockfish".to_string()
+                } else {
+                    "reckless".to_string()
+                },
+                view.ply.to_string(),
+                view.last_move.clone(),
+                nps(view.stockfish_nps),
+                nps(view.reckless_nps),
+            ]);
+            if id == self.selected {
+                row.style(Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                row
+            }
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(6),
+                Constraint::Length(9),
+                Constraint::Length(10),
+                Constraint::Length(5),
+                Constraint::Length(8),
+                Constraint::Length(12),
+                Constraint::Length(12),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                "Worker",
+                "Game",
+                "White",
+                "Ply",
+                "Last",
+                "SF nps",
+                "Reckless nps",
+            ])
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title(" Workers (↑/↓ to select, q to stop) "));
+        frame.render_widget(table, area);
+    }
+
+    /// Board of the selected worker's game.
+    fn render_board(&self, frame: &mut Frame, area: Rect) {
+        let lines = self
+            .workers
+            .get(self.selected)
+            .and_then(|view| view.board.as_ref())
+            .map_or_else(|| vec![Line::from("No game")], board_lines);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().title(format!(" Worker {} ", self.selected))),
+            area,
+        );
+    }
+
+    /// The most recent failed games.
+    fn render_failures(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .failures
+            .iter()
+            .map(|failure| {
+                Line::from(format!(
+                    "[{}s] worker {} game {}: {}",
+                    failure.at.as_secs(),
+                    failure.worker_id,
+                    failure.game_num,
+                    failure.error
+                ))
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Recent failures ")),
+            area,
+        );
+    }
+}
+
+/// Draw a board as text, white at the bottom.
+fn board_lines(board: &Board) -> Vec<Line<'static>> {
+    let mut lines: Vec<Line> = Rank::ALL
+        .iter()
+        .rev()
+        .map(|&rank| {
+            let mut line = String::from(rank.char());
+            for &file in &File::ALL {
+                let piece = board.piece_at(Square::from_coords(file, rank));
+                line.push(' ');
+                line.push(piece.map_or('.', Piece::char));
+            }
+            Line::from(line)
+        })
+        .collect();
+    lines.push(Line::from("  a b c d e f g h"));
+    lines
+}
+
+/// Where log output goes: the terminal, unless the dashboard is showing.
+pub fn log_writer() -> Box<dyn io::Write> {
+    if ACTIVE.load(Ordering::Relaxed) {
+        Box::new(io::sink())
+    } else {
+        Box::new(io::stdout())
+    }
+}
+
+/// Read keys on a blocking thread and forward them as actions.
+fn forward_keys(actions: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    while !actions.is_closed() {
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let action = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            // Raw mode swallows SIGINT, so handle Ctrl-C here
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => Action::SelectNext,
+            KeyCode::Up | KeyCode::Char('k') | KeyCode::BackTab => Action::SelectPrevious,
+            _ => continue,
+        };
+        if actions.send(action).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The dashboard, owning the terminal while the match runs.
+pub struct Tui {
+    terminal: DefaultTerminal,
+    dashboard: Dashboard,
+}
+
+impl Tui {
+    /// Take over the terminal, returning the dashboard and a stream of key actions.
+    #[must_use]
+    pub fn start(
+        workers: usize,
+        total_games: u64,
+        sprt: Sprt,
+    ) -> (Self, mpsc::UnboundedReceiver<Action>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            if let Err(e) = forward_keys(&tx) {
+                tracing::error!(error = %e, "Failed to read keyboard input");
+            }
+        });
+        ACTIVE.store(true, Ordering::Relaxed);
+        let tui = Self {
+            terminal: ratatui::init(),
+            dashboard: Dashboard::new(workers, total_games, sprt),
+        };
+        (tui, rx)
+    }
+
+    /// Update the dashboard from a worker event.
+    pub fn apply(&mut self, event: &WorkerEvent) {
+        self.dashboard.apply(event);
+    }
+
+    /// Handle a selection change.
+    pub fn select(&mut self, action: Action) {
+        self.dashboard.select(action);
+    }
+
+    /// Redraw the dashboard.
+    ///
+    /// # Errors
+    /// Returns an error if the terminal cannot be written to.
+    pub fn draw(&mut self, stats: &MatchStats) -> Result<()> {
+        let dashboard = &self.dashboard;
+        self.terminal.draw(|frame| dashboard.render(frame, stats))?;
+        Ok(())
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        ratatui::restore();
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(worker_id: usize, game_num: u64, start_fen: Option<&str>) -> WorkerEvent {
+        WorkerEvent::GameStarted {
+            worker_id,
+            game_num,
+            stockfish_is_white: true,
+            start_fen: start_fen.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_game_started_resets_the_worker() {
+        let mut dashboard = Dashboard::new(2, 10, Sprt::default());
+        dashboard.apply(&started(1, 3, None));
+        dashboard.apply(&WorkerEvent::MovePlayed {
+            worker_id: 1,
+            ply: 1,
+            uci: "e2e4".to_string(),
+            board: Board::default(),
+            nps: Some(1000),
+            stockfish_moved: true,
+        });
+        let view = &dashboard.workers[1];
+        assert_eq!(view.game_num, Some(3));
+        assert_eq!((view.ply, view.last_move.as_str()), (1, "e2e4"));
+        assert_eq!((view.stockfish_nps, view.reckless_nps), (Some(1000), None));
+
+        dashboard.apply(&started(1, 4, None));
+        let view = &dashboard.workers[1];
+        assert_eq!(view.game_num, Some(4));
+        assert_eq!((view.ply, view.last_move.as_str()), (0, ""));
+        assert_eq!(view.stockfish_nps, None);
+        assert_eq!(dashboard.workers[0].game_num, None);
+
+        // Events from unknown workers are ignored
+        dashboard.apply(&started(5, 6, None));
+    }
+
+    #[test]
+    fn test_board_starts_from_the_opening_position() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let mut dashboard = Dashboard::new(1, 10, Sprt::default());
+        dashboard.apply(&started(0, 0, Some(fen)));
+        let expected = fen.parse::<Fen>().expect("Valid FEN").into_setup().board;
+        assert_eq!(dashboard.workers[0].board, Some(expected));
+
+        dashboard.apply(&started(0, 1, None));
+        assert_eq!(dashboard.workers[0].board, Some(Board::default()));
+    }
+
+    #[test]
+    fn test_failures_keep_the_most_recent() {
+        let mut dashboard = Dashboard::new(1, 100, Sprt::default());
+        for game_num in 0..20 {
+            dashboard.apply(&WorkerEvent::GameFailed {
+                worker_id: 0,
+                game_num,
+                error: "crashed".to_string(),
+            });
+        }
+        assert_eq!(dashboard.failures.len(), usize::from(FAILURE_ROWS));
+        let games: Vec<u64> = dashboard.failures.iter().map(|f| f.game_num).collect();
+        assert_eq!(games, (12..20).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_selection_wraps_around() {
+        let mut dashboard = Dashboard::new(3, 10, Sprt::default());
+        dashboard.select(Action::SelectPrevious);
+        assert_eq!(dashboard.selected, 2);
+        dashboard.select(Action::SelectNext);
+        assert_eq!(dashboard.selected, 0);
+        dashboard.select(Action::SelectNext);
+        dashboard.select(Action::Quit);
+        assert_eq!(dashboard.selected, 1);
+
+        // No workers still leaves a valid selection
+        let mut empty = Dashboard::new(0, 10, Sprt::default());
+        empty.select(Action::SelectNext);
+        assert_eq!(empty.selected, 0);
+    }
+
+    #[test]
+    fn test_board_lines() {
+        let lines = board_lines(&Board::default());
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0].to_string(), "8 r n b q k b n r");
+        assert_eq!(lines[4].to_string(), "4 . . . . . . . .");
+        assert_eq!(lines[8].to_string(), "  a b c d e f g h");
+    }
+}
@@ -0,0 +1,425 @@
+//! Tuning Reckless's UCI parameters with SPSA against a reference engine.
+//!
+//! Each iteration nudges every tuned `spin` option up or down at random,
+//! plays one set of games with the values nudged one way and one with them
+//! nudged the other, both against Stockfish and from the same openings, and
+//! moves the values towards whichever side scored better. Gains shrink over
+//! the run as in fishtest's SPSA, so the values settle.
+//!
+//! The state is written to a checkpoint after every iteration, so a tuning
+//! run can be stopped and resumed.
+
+use crate::engine::{EngineInfo, OptionKind, OptionValue};
+use crate::game::GameResult;
+use crate::match_runner::{Match, WorkerEvent};
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Exponent of the decay of the step size `a`.
+const ALPHA: f64 = 0.602;
+
+/// Exponent of the decay of the perturbation size `c`.
+const GAMMA: f64 = 0.101;
+
+/// Default learning rate at the end of the run, as fishtest uses.
+pub const DEFAULT_R_END: f64 = 0.002;
+
+/// A tuned option and where SPSA has taken it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    /// The UCI option's name
+    pub name: String,
+    /// The current value, kept fractional between iterations
+    pub value: f64,
+    /// Smallest value the engine accepts
+    pub min: i64,
+    /// Largest value the engine accepts
+    pub max: i64,
+    /// How far the value is perturbed by the end of the run
+    pub c_end: f64,
+    /// Learning rate at the end of the run
+    pub r_end: f64,
+}
+
+impl Param {
+    /// Start tuning the `spin` option `name` from its default, perturbing it
+    /// by `c_end` at the end of the run, or a twentieth of its range if not
+    /// given.
+    ///
+    /// # Errors
+    /// Returns an error if the engine has no such `spin` option with a
+    /// default and a range.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn from_engine(info: &EngineInfo, name: &str, c_end: Option<f64>) -> Result<Self> {
+        let option = info
+            .option(name)
+            .ok_or_else(|| eyre!("The engine has no option '{name}'"))?;
+        if option.kind != OptionKind::Spin {
+            return Err(eyre!("Option '{name}' is not a spin option"));
+        }
+        let (Some(default), Some(min), Some(max)) = (
+            option
+                .default
+                .as_deref()
+                .and_then(|d| d.parse::<i64>().ok()),
+            option.min,
+            option.max,
+        ) else {
+            return Err(eyre!("Option '{name}' has no default or range"));
+        };
+        let c_end = c_end.unwrap_or_else(|| ((max - min) as f64 / 20.0).max(1.0));
+        Ok(Self {
+            name: option.name.clone(),
+            value: default as f64,
+            min,
+            max,
+            c_end,
+            r_end: DEFAULT_R_END,
+        })
+    }
+
+    /// The value to send the engine: `value` rounded into range.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn clamped(&self, value: f64) -> i64 {
+        (value.round() as i64).clamp(self.min, self.max)
+    }
+}
+
+/// The perturbation of one iteration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Perturbation {
+    /// The direction of each parameter's nudge, `1` or `-1`
+    pub deltas: Vec<i8>,
+    /// The values nudged in the direction of `deltas`
+    pub plus: Vec<i64>,
+    /// The values nudged against it
+    pub minus: Vec<i64>,
+}
+
+/// A tuning run, as saved to its checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spsa {
+    /// Iterations the run is planned for, which sets the gain schedule
+    pub iterations: u64,
+    /// Iterations done so far
+    pub iteration: u64,
+    /// Seed for the perturbations and for each iteration's openings
+    pub seed: u64,
+    /// The parameters and their current values
+    pub params: Vec<Param>,
+    /// The values after each iteration
+    pub history: Vec<Vec<f64>>,
+}
+
+impl Spsa {
+    /// A fresh run of `iterations` iterations over `params`.
+    #[must_use]
+    pub const fn new(params: Vec<Param>, iterations: u64, seed: u64) -> Self {
+        Self {
+            iterations,
+            iteration: 0,
+            seed,
+            params,
+            history: Vec::new(),
+        }
+    }
+
+    /// Read a checkpoint written by [`save`](Self::save).
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&text).wrap_err_with(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Write the run to `path`, replacing it only once fully written.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .wrap_err_with(|| format!("Failed to replace {}", path.display()))
+    }
+
+    /// Check that a resumed run tunes the options named `names`, in order.
+    ///
+    /// # Errors
+    /// Returns an error naming both lists if they differ.
+    pub fn check_params(&self, names: &[&str]) -> Result<()> {
+        let tuned: Vec<&str> = self.params.iter().map(|p| p.name.as_str()).collect();
+        let same = tuned.len() == names.len()
+            && tuned
+                .iter()
+                .zip(names)
+                .all(|(tuned, name)| tuned.eq_ignore_ascii_case(name));
+        if same {
+            Ok(())
+        } else {
+            Err(eyre!(
+                "The checkpoint tunes {} but the run was asked to tune {}",
+                tuned.join(", "),
+                names.join(", ")
+            ))
+        }
+    }
+
+    /// Plan the run for `iterations` iterations in all, spreading the rest
+    /// of the gain schedule over the new total.
+    pub const fn extend_to(&mut self, iterations: u64) {
+        self.iterations = iterations;
+    }
+
+    /// Whether every planned iteration has been played.
+    #[must_use]
+    pub const fn is_done(&self) -> bool {
+        self.iteration >= self.iterations
+    }
+
+    /// The perturbation size `c_k` and learning rate `r_k = a_k / c_k^2` of
+    /// `param` in the current iteration.
+    #[allow(clippy::cast_precision_loss)]
+    fn gains(&self, param: &Param) -> (f64, f64) {
+        let n = self.iterations as f64;
+        let k = self.iteration as f64 + 1.0;
+        let big_a = 0.1 * n;
+        let c = param.c_end * n.powf(GAMMA);
+        let a = param.r_end * param.c_end.powi(2) * (big_a + n).powf(ALPHA);
+        let c_k = c / k.powf(GAMMA);
+        let a_k = a / (big_a + k).powf(ALPHA);
+        (c_k, a_k / c_k.powi(2))
+    }
+
+    /// Draw this iteration's nudges, the same every time for a given seed.
+    #[must_use]
+    pub fn perturb(&self) -> Perturbation {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(self.iteration);
+        let mut perturbation = Perturbation {
+            deltas: Vec::new(),
+            plus: Vec::new(),
+            minus: Vec::new(),
+        };
+        for param in &self.params {
+            let delta: i8 = if rng.random() { 1 } else { -1 };
+            let (c_k, _) = self.gains(param);
+            let step = c_k * f64::from(delta);
+            perturbation.deltas.push(delta);
+            perturbation.plus.push(param.clamped(param.value + step));
+            perturbation.minus.push(param.clamped(param.value - step));
+        }
+        perturbation
+    }
+
+    /// Move the values by the result of an iteration: how many more points
+    /// the `plus` values scored than the `minus` ones.
+    pub fn update(&mut self, perturbation: &Perturbation, result: f64) {
+        let gains: Vec<(f64, f64)> = self.params.iter().map(|p| self.gains(p)).collect();
+        for ((param, delta), (c_k, r_k)) in
+            self.params.iter_mut().zip(&perturbation.deltas).zip(gains)
+        {
+            #[allow(clippy::cast_precision_loss)]
+            let (min, max) = (param.min as f64, param.max as f64);
+            param.value = (r_k * c_k * result)
+                .mul_add(f64::from(*delta), param.value)
+                .clamp(min, max);
+        }
+        self.history
+            .push(self.params.iter().map(|param| param.value).collect());
+        self.iteration += 1;
+    }
+
+    /// A match like `base` with the tuned options of Reckless set to `values`,
+    /// playing `games` games from this iteration's openings.
+    fn side(&self, base: &Match, values: &[i64], games: u64, workers: usize) -> Match {
+        let mut side = base
+            .clone()
+            .with_games(games)
+            .with_workers(workers)
+            .with_seed(self.seed.wrapping_add(self.iteration))
+            .with_live_events(false);
+        for (param, value) in self.params.iter().zip(values) {
+            side.reckless
+                .options
+                .insert(param.name.clone(), OptionValue::Int(*value));
+        }
+        side
+    }
+}
+
+/// Play `settings` to the end and return Reckless's points and the number
+/// of games that finished.
+async fn reckless_points(settings: Match) -> (f64, u64) {
+    let mut running = settings.start();
+    let (mut played, mut points) = (0, 0.0);
+    while let Some(event) = running.next_event().await {
+        let WorkerEvent::GameCompleted(completed) = event else {
+            continue;
+        };
+        played += 1;
+        let reckless_is_white = !completed.stockfish_is_white;
+        points += match completed.record.result {
+            GameResult::Draw => 0.5,
+            GameResult::WhiteWins if reckless_is_white => 1.0,
+            GameResult::BlackWins if !reckless_is_white => 1.0,
+            _ => 0.0,
+        };
+    }
+    running.join().await;
+    (points, played)
+}
+
+/// How many more points the `plus` side scored than the `minus` one over
+/// `games` games each, given each side's points and games finished.
+///
+/// A game lost to a crash or a timeout shouldn't cost hours of tuning, so
+/// the sides are compared by their average over the games they finished,
+/// as long as each finished at least half of them.
+///
+/// # Errors
+/// Returns an error if either side finished fewer than half its games.
+#[allow(clippy::cast_precision_loss)]
+pub fn iteration_result(games: u64, plus: (f64, u64), minus: (f64, u64)) -> Result<f64> {
+    for (side, (_, played)) in [("plus", plus), ("minus", minus)] {
+        if played < games.div_ceil(2).max(1) {
+            return Err(eyre!(
+                "Only {played} of {games} games were played with the {side} values"
+            ));
+        }
+        if played < games {
+            tracing::warn!(side, played, games, "Scoring from the games that finished");
+        }
+    }
+    let average = |(points, played): (f64, u64)| points / played as f64;
+    Ok((average(plus) - average(minus)) * games as f64)
+}
+
+/// Play one iteration: `pairs` game pairs against Stockfish with each side's
+/// values, the two sides at once on half of `workers` each, then update.
+///
+/// Returns Reckless's points with the `plus` and `minus` values.
+///
+/// # Errors
+/// Returns an error if either side finished fewer than half its games.
+pub async fn iterate(
+    spsa: &mut Spsa,
+    base: &Match,
+    pairs: u64,
+    workers: usize,
+) -> Result<(f64, f64)> {
+    let perturbation = spsa.perturb();
+    let games = pairs * 2;
+    let workers = (workers / 2).max(1);
+    let (plus, minus) = tokio::join!(
+        reckless_points(spsa.side(base, &perturbation.plus, games, workers)),
+        reckless_points(spsa.side(base, &perturbation.minus, games, workers)),
+    );
+    let result = iteration_result(games, plus, minus)?;
+    spsa.update(&perturbation, result);
+    Ok((plus.0, minus.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(value: f64) -> Param {
+        Param {
+            name: "Aspiration".to_string(),
+            value,
+            min: 0,
+            max: 100,
+            c_end: 5.0,
+            r_end: DEFAULT_R_END,
+        }
+    }
+
+    #[test]
+    fn test_param_from_engine() {
+        let mut info = EngineInfo::default();
+        info.parse_line("option name Aspiration type spin default 40 min 0 max 200");
+        info.parse_line("option name Ponder type check default false");
+        let param = Param::from_engine(&info, "aspiration", None).expect("No param");
+        assert_eq!(param.name, "Aspiration");
+        assert!((param.value - 40.0).abs() < f64::EPSILON);
+        assert!((param.c_end - 10.0).abs() < f64::EPSILON);
+        assert!(Param::from_engine(&info, "Ponder", None).is_err());
+        assert!(Param::from_engine(&info, "Missing", Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_spsa_moves_towards_the_better_side() {
+        let mut spsa = Spsa::new(vec![param(50.0), param(99.0)], 100, 7);
+        let perturbation = spsa.perturb();
+        assert_eq!(perturbation, spsa.perturb());
+        for (i, delta) in perturbation.deltas.iter().enumerate() {
+            let (plus, minus) = (perturbation.plus[i], perturbation.minus[i]);
+            assert_eq!(plus > minus, *delta > 0, "{perturbation:?}");
+            assert!((0..=100).contains(&plus) && (0..=100).contains(&minus));
+        }
+
+        // The plus side won, so every value moves in its direction
+        let before: Vec<f64> = spsa.params.iter().map(|p| p.value).collect();
+        spsa.update(&perturbation, 2.0);
+        for ((param, before), delta) in spsa.params.iter().zip(before).zip(&perturbation.deltas) {
+            let moved = param.value - before;
+            assert!(moved == 0.0 || (moved > 0.0) == (*delta > 0), "{moved}");
+            assert!(param.value <= 100.0);
+        }
+        assert_eq!(spsa.iteration, 1);
+        assert_eq!(spsa.history.len(), 1);
+        assert_ne!(spsa.perturb(), perturbation);
+
+        // Perturbations shrink as the run goes on
+        let (first, _) = Spsa::new(vec![param(50.0)], 100, 7).gains(&spsa.params[0]);
+        spsa.iteration = 99;
+        let (last, _) = spsa.gains(&spsa.params[0]);
+        assert!(first > last && (last - 5.0).abs() < 1e-9, "{first} {last}");
+    }
+
+    #[test]
+    fn test_iteration_scored_from_finished_games() {
+        let result = iteration_result(4, (3.0, 4), (1.0, 4)).expect("All games played");
+        assert!((result - 2.0).abs() < f64::EPSILON);
+
+        // A plus game was lost to a crash, which doesn't count against it
+        let result = iteration_result(4, (2.25, 3), (2.0, 4)).expect("Enough games played");
+        assert!((result - 1.0).abs() < f64::EPSILON, "{result}");
+        assert!(iteration_result(4, (1.0, 1), (2.0, 4)).is_err());
+        assert!(iteration_result(2, (0.0, 2), (0.0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_resumed_params_must_match() {
+        let spsa = Spsa::new(vec![param(50.0)], 10, 3);
+        assert!(spsa.check_params(&["aspiration"]).is_ok());
+        let error = spsa
+            .check_params(&["Aspiration", "Hash"])
+            .expect_err("Mismatch not detected");
+        assert!(
+            error
+                .to_string()
+                .contains("tunes Aspiration but the run was asked to tune Aspiration, Hash"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!("rvs-spsa-{}.json", std::process::id()));
+        let mut spsa = Spsa::new(vec![param(50.0)], 10, 3);
+        let perturbation = spsa.perturb();
+        spsa.update(&perturbation, -1.0);
+        spsa.save(&path).expect("Failed to save");
+        assert_eq!(Spsa::load(&path).expect("Failed to load"), spsa);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,201 @@
+//! Batch analysis of EPD files with the `analyse` subcommand.
+
+mod common;
+
+#[test]
+fn test_analyse_writes_jsonl_and_epd_in_file_order() {
+    let dir = common::scratch_dir("analyse");
+    let positions = dir.join("positions.epd");
+    std::fs::write(
+        &positions,
+        "# Three quiet positions\n\
+         4k3/8/8/8/8/8/4P3/4K3 w - - id \"pawn\";\n\
+         rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n\
+         r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\n",
+    )
+    .expect("Failed to write positions");
+    let jsonl = dir.join("analysis.jsonl");
+    let out_epd = dir.join("analysis.epd");
+
+    let output = common::finish(common::spawn(
+        "random:2",
+        &[
+            "analyse",
+            "--epd",
+            positions.to_str().expect("UTF-8 path"),
+            "--engine-path",
+            common::MOCK,
+            "--depth",
+            "8",
+            "--workers",
+            "2",
+            "--jsonl",
+            jsonl.to_str().expect("UTF-8 path"),
+            "--out-epd",
+            out_epd.to_str().expect("UTF-8 path"),
+        ],
+    ));
+    assert!(output.contains("pawn: "), "{output}");
+    assert!(output.contains("e4: "), "{output}");
+    assert!(output.contains(" +0.00 depth 1 pv "), "{output}");
+
+    let jsonl = std::fs::read_to_string(&jsonl).expect("No JSONL written");
+    let lines: Vec<&str> = jsonl.lines().collect();
+    assert_eq!(lines.len(), 3, "{jsonl}");
+    assert!(lines[0].starts_with(r#"{"id":"pawn","fen":"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1","#));
+    assert!(lines[1].starts_with(r#"{"id":"e4","#));
+    assert!(
+        lines[2].contains(
+            r#""fen":"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3""#
+        )
+    );
+
+    let epd = std::fs::read_to_string(&out_epd).expect("No EPD written");
+    let lines: Vec<&str> = epd.lines().collect();
+    assert_eq!(lines.len(), 3, "{epd}");
+    assert!(
+        lines[0].starts_with("4k3/8/8/8/8/8/4P3/4K3 w - - id \"pawn\"; bm "),
+        "{epd}"
+    );
+    assert!(
+        lines.iter().all(|line| line.contains("; ce 0; pv ")),
+        "{epd}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_analyse_reports_multipv_lines() {
+    let dir = common::scratch_dir("analyse-multipv");
+    let positions = dir.join("positions.epd");
+    std::fs::write(&positions, "4k3/8/8/8/8/8/4P3/4K3 w - - id \"pawn\";\n")
+        .expect("Failed to write positions");
+    let jsonl = dir.join("analysis.jsonl");
+
+    let output = common::finish(common::spawn(
+        "random:4",
+        &[
+            "analyse",
+            "--epd",
+            positions.to_str().expect("UTF-8 path"),
+            "--engine-path",
+            common::MOCK,
+            "--depth",
+            "8",
+            "--multipv",
+            "3",
+            "--jsonl",
+            jsonl.to_str().expect("UTF-8 path"),
+        ],
+    ));
+    assert!(output.contains("  line 2: +0.00 pv "), "{output}");
+    assert!(output.contains("  line 3: +0.00 pv "), "{output}");
+    assert!(!output.contains("  line 4:"), "{output}");
+
+    let jsonl = std::fs::read_to_string(&jsonl).expect("No JSONL written");
+    assert!(
+        jsonl.contains(r#""lines":[{"multipv":1,"score":{"Cp":0},"depth":1,"pv":["#),
+        "{jsonl}"
+    );
+    assert!(jsonl.contains(r#"{"multipv":3,"#), "{jsonl}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_analyse_skips_positions_that_crash_the_engine() {
+    let dir = common::scratch_dir("analyse-crash");
+    let positions = dir.join("positions.epd");
+    // The mock crashes on the position at move 3, and only there
+    std::fs::write(
+        &positions,
+        "4k3/8/8/8/8/8/4P3/4K3 w - - id \"pawn\";\n\
+         r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - hmvc 2; fmvn 3; id \"crash\";\n\
+         rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n",
+    )
+    .expect("Failed to write positions");
+    let jsonl = dir.join("analysis.jsonl");
+
+    let output = common::finish(common::spawn(
+        "crash:4",
+        &[
+            "analyse",
+            "--epd",
+            positions.to_str().expect("UTF-8 path"),
+            "--engine-path",
+            common::MOCK,
+            "--depth",
+            "8",
+            "--workers",
+            "1",
+            "--jsonl",
+            jsonl.to_str().expect("UTF-8 path"),
+        ],
+    ));
+    assert!(
+        output.contains("Analysis failed, restarting engine"),
+        "{output}"
+    );
+    assert!(
+        output.contains("Some positions could not be analysed"),
+        "{output}"
+    );
+    assert!(output.contains("e4: "), "{output}");
+
+    let jsonl = std::fs::read_to_string(&jsonl).expect("No JSONL written");
+    let lines: Vec<&str> = jsonl.lines().collect();
+    assert_eq!(lines.len(), 2, "{jsonl}");
+    assert!(lines[0].starts_with(r#"{"id":"pawn","#), "{jsonl}");
+    assert!(lines[1].starts_with(r#"{"id":"e4","#), "{jsonl}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_analyse_checks_multipv_against_the_engine() {
+    let dir = common::scratch_dir("analyse-multipv-limits");
+    let positions = dir.join("positions.epd");
+    std::fs::write(&positions, "4k3/8/8/8/8/8/4P3/4K3 w - - id \"pawn\";\n")
+        .expect("Failed to write positions");
+    let config = dir.join("xboard.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[engines.reckless]\npath = \"{}\"\nprotocol = \"xboard\"\n",
+            common::MOCK
+        ),
+    )
+    .expect("Failed to write config");
+
+    let analyse = |args: &[&str]| {
+        let mut all_args = vec![
+            "analyse",
+            "--epd",
+            positions.to_str().expect("UTF-8 path"),
+            "--depth",
+            "8",
+        ];
+        all_args.extend_from_slice(args);
+        let output = common::spawn("random", &all_args)
+            .wait_with_output()
+            .expect("Failed to wait for binary");
+        assert!(!output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    // The mock allows up to 500 lines
+    let stderr = analyse(&["--engine-path", common::MOCK, "--multipv", "501"]);
+    assert!(
+        stderr.contains("allows MultiPV from 1 to 500, not 501"),
+        "{stderr}"
+    );
+    let stderr = analyse(&[
+        "--config",
+        config.to_str().expect("UTF-8 path"),
+        "--multipv",
+        "2",
+    ]);
+    assert!(stderr.contains("--multipv needs a UCI engine"), "{stderr}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,65 @@
+//! Finding blunders in stored games with the `blunders` subcommand.
+
+mod common;
+
+#[test]
+fn test_blunders_flags_hung_queen() {
+    let dir = common::scratch_dir("blunders");
+    let games = dir.join("games.jsonl");
+    let out_pgn = dir.join("annotated.pgn");
+    // 1. e4 e5 2. Qh5 Nc6 3. Qxe5+ Nxe5, giving the queen away for a pawn
+    let plies: Vec<String> = ["e2e4", "e7e5", "d1h5", "b8c6", "h5e5", "c6e5"]
+        .iter()
+        .map(|uci| format!(r#"{{"uci":"{uci}","score":null,"depth":null,"time_ms":5}}"#))
+        .collect();
+    std::fs::write(
+        &games,
+        format!(
+            r#"{{"game_num":0,"white":"stockfish","black":"reckless","result":"BlackWins","termination":"ResignAdjudication","plies":[{}]}}"#,
+            plies.join(",")
+        ) + "\n",
+    )
+    .expect("Failed to write games");
+
+    // The mock referee scores positions by what the side to move can capture
+    let output = common::finish(common::spawn(
+        "material",
+        &[
+            "blunders",
+            "--games",
+            games.to_str().expect("UTF-8 path"),
+            "--engine-path",
+            common::MOCK,
+            "--depth",
+            "1",
+            "--workers",
+            "2",
+            "--out-pgn",
+            out_pgn.to_str().expect("UTF-8 path"),
+        ],
+    ));
+    assert!(
+        output.contains("game 1: 3. Qxe5+?? by stockfish in the opening, +1.00 -> -8.00"),
+        "{output}"
+    );
+    assert_eq!(output.matches("??").count(), 1, "{output}");
+    assert!(
+        output.contains(
+            "reckless: opening 0/3 (0.0%), middlegame 0/0 (0.0%), endgame 0/0 (0.0%), total 0/3 (0.0%)"
+        ),
+        "{output}"
+    );
+    assert!(
+        output.contains("stockfish: opening 1/3 (33.3%)"),
+        "{output}"
+    );
+
+    let pgn = std::fs::read_to_string(&out_pgn).expect("No annotated PGN");
+    let movetext = pgn.split_whitespace().collect::<Vec<_>>().join(" ");
+    assert!(
+        movetext.contains("3. Qxe5+ $4 {? 0.005s} {referee +1.00 -> -8.00} Nxe5"),
+        "{pgn}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
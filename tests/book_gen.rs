@@ -0,0 +1,47 @@
+//! Growing an opening book with the `book-gen` subcommand.
+
+mod common;
+
+use reckless_vs_stockfish::openings;
+
+#[test]
+fn test_book_gen_writes_balanced_epd_book() {
+    let dir = common::scratch_dir("book-gen");
+    let book = dir.join("book.epd");
+
+    // The mock scores every line level, so all three lines are followed at
+    // both plies
+    let output = common::finish(common::spawn(
+        "random:3",
+        &[
+            "book-gen",
+            "--engine-path",
+            common::MOCK,
+            "--plies",
+            "2",
+            "--multipv",
+            "3",
+            "--depth",
+            "1",
+            "--out",
+            book.to_str().expect("UTF-8 path"),
+        ],
+    ));
+    assert!(
+        output.contains("Wrote 9 positions after 2 plies"),
+        "{output}"
+    );
+
+    let text = std::fs::read_to_string(&book).expect("Failed to read book");
+    assert!(text.contains("id \"book.009\"; ce 0; c0 \"1. "), "{text}");
+    let positions = openings::load(&book).expect("Book should load as openings");
+    assert_eq!(positions.len(), 9);
+    assert!(
+        positions
+            .iter()
+            .all(|fen| fen.split(' ').nth(1) == Some("w")),
+        "{positions:?}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
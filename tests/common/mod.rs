@@ -0,0 +1,60 @@
+//! Helpers for running the binaries against the mock UCI engine.
+
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Longest a mock match may take before the test gives up.
+#[allow(dead_code, reason = "Not every test waits for the binary to exit")]
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Path to the mock engine binary.
+pub const MOCK: &str = env!("CARGO_BIN_EXE_mock-uci-engine");
+
+/// A fresh directory for one test's files.
+#[allow(dead_code, reason = "Not every test writes files")]
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rvs-{test}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed to create scratch dir");
+    dir
+}
+
+/// A port that was free a moment ago.
+#[allow(dead_code, reason = "Not every test listens")]
+pub fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port")
+        .port()
+}
+
+/// Start the main binary with mock engines behaving as `behaviour`.
+pub fn spawn(behaviour: &str, args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_reckless-vs-stockfish"))
+        .args(args)
+        .env("MOCK_UCI_BEHAVIOUR", behaviour)
+        .env("NO_COLOR", "1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start binary")
+}
+
+/// Wait for a successful exit and return what was printed.
+#[allow(dead_code, reason = "Not every test waits for the binary to exit")]
+pub fn finish(child: Child) -> String {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || tx.send(child.wait_with_output()));
+    let output: Output = rx
+        .recv_timeout(TIMEOUT)
+        .expect("Timed out")
+        .expect("Failed to wait for binary");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "{stdout}{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+}
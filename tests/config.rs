@@ -0,0 +1,141 @@
+//! Match configuration files and the `print-config` subcommand.
+
+mod common;
+
+#[test]
+fn test_config_file_with_openings_and_outputs() {
+    let dir = common::scratch_dir("config-file");
+    let openings = dir.join("openings.epd");
+    std::fs::write(
+        &openings,
+        "# Black to move after 1. e4\nrnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n",
+    )
+    .expect("Failed to write openings");
+    let config = dir.join("match.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+games = 2
+workers = 1
+openings = "{openings}"
+
+[engines.stockfish]
+path = "{mock}"
+options = {{ Hash = 16 }}
+
+# The mock speaks XBoard too, so this is a mixed-protocol match
+[engines.reckless]
+path = "{mock}"
+protocol = "xboard"
+
+# XBoard engines are given whole seconds per move
+[time_control]
+movetime_ms = 1000
+
+[adjudication]
+max_moves = 20
+
+[output]
+pgn = "{pgn}"
+jsonl = "{jsonl}"
+"#,
+            openings = openings.display(),
+            mock = common::MOCK,
+            pgn = dir.join("games.pgn").display(),
+            jsonl = dir.join("games.jsonl").display(),
+        ),
+    )
+    .expect("Failed to write config");
+
+    let output = common::finish(common::spawn(
+        "random:5",
+        &["--config", config.to_str().expect("UTF-8 path")],
+    ));
+    assert!(output.contains("Total games: 2"), "{output}");
+    assert!(output.contains("Engine identified"), "{output}");
+
+    let pgn = std::fs::read_to_string(dir.join("games.pgn")).expect("No PGN written");
+    assert_eq!(pgn.matches("[Event ").count(), 2, "{pgn}");
+    assert!(pgn.contains("[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]"));
+    assert!(pgn.contains("\n1... "), "{pgn}");
+    // Engines are named by their `id name`, with the binary's hash alongside
+    assert!(pgn.contains("[White \"MockEngine 1.0\"]"), "{pgn}");
+    assert_eq!(pgn.matches("Sha256 \"").count(), 4, "{pgn}");
+
+    let jsonl = std::fs::read_to_string(dir.join("games.jsonl")).expect("No JSONL written");
+    assert_eq!(jsonl.lines().count(), 2, "{jsonl}");
+    assert!(jsonl.contains("\"start_fen\""), "{jsonl}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_print_config_applies_flag_overrides() {
+    let dir = common::scratch_dir("print-config");
+    let config = dir.join("match.yaml");
+    std::fs::write(
+        &config,
+        "games: 50\nworkers: 3\ntime_control:\n  base_ms: 60000\n  increment_ms: 500\n",
+    )
+    .expect("Failed to write config");
+    let path = config.to_str().expect("UTF-8 path");
+
+    let toml = common::finish(common::spawn(
+        "random",
+        &[
+            "print-config",
+            "--config",
+            path,
+            "--games",
+            "10",
+            "--seed",
+            "7",
+        ],
+    ));
+    assert!(toml.contains("games = 10\n"), "{toml}");
+    assert!(toml.contains("seed = 7\n"), "{toml}");
+    assert!(toml.contains("workers = 3\n"), "{toml}");
+    assert!(toml.contains("base_ms = 60000\n"), "{toml}");
+    assert!(toml.contains("path = \"stockfish\"\n"), "{toml}");
+
+    let yaml = common::finish(common::spawn(
+        "random",
+        &["print-config", "--format", "yaml", "--config", path],
+    ));
+    assert!(yaml.contains("games: 50\n"), "{yaml}");
+    // Without --seed a random one is chosen and recorded
+    assert!(yaml.contains("seed: "), "{yaml}");
+    assert!(yaml.contains("increment_ms: 500\n"), "{yaml}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_pondering_with_shared_search_slots_is_rejected() {
+    let output = common::spawn(
+        "random",
+        &["print-config", "--ponder", "--concurrency", "searches"],
+    )
+    .wait_with_output()
+    .expect("Failed to wait for binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("Pondering cannot be combined with searches sharing the cores"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_pondering_with_pinned_cores_is_rejected() {
+    let output = common::spawn("random", &["print-config", "--ponder", "--pin-cores"])
+        .wait_with_output()
+        .expect("Failed to wait for binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("Pondering cannot be combined with pinned cores"),
+        "{stderr}"
+    );
+}
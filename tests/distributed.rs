@@ -0,0 +1,103 @@
+//! Matches handed out by a coordinator to worker processes on localhost.
+
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+mod common;
+
+/// Wait until something is listening on `addr`.
+fn wait_for_listener(addr: &str) {
+    let started = Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "Coordinator never listened"
+        );
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+const fn worker_args<'a>(addr: &'a str, workers: &'a str) -> [&'a str; 9] {
+    [
+        "worker",
+        "--coordinator",
+        addr,
+        "--stockfish-path",
+        common::MOCK,
+        "--reckless-path",
+        common::MOCK,
+        "--workers",
+        workers,
+    ]
+}
+
+#[test]
+fn test_disconnected_worker_games_are_reassigned() {
+    let addr = format!("127.0.0.1:{}", common::free_port());
+    let coordinator = common::spawn(
+        "random",
+        &[
+            "coordinator",
+            "--listen",
+            &addr,
+            "--games",
+            "6",
+            "--movetime-ms",
+            "5",
+            "--max-moves",
+            "40",
+        ],
+    );
+    wait_for_listener(&addr);
+
+    // A worker whose engines never move holds on to a game until it dies
+    let mut stuck = common::spawn("hang", &worker_args(&addr, "1"));
+    std::thread::sleep(Duration::from_secs(1));
+    stuck.kill().expect("Failed to kill stuck worker");
+    stuck.wait().ok();
+
+    let healthy = common::spawn("random:5", &worker_args(&addr, "2"));
+    common::finish(healthy);
+
+    let output = common::finish(coordinator);
+    assert!(output.contains("reassigning"), "{output}");
+    assert!(output.contains("Total games: 6"), "{output}");
+}
+
+#[test]
+fn test_worker_sharing_cores_fails_games_that_ponder() {
+    let dir = common::scratch_dir("ponder-worker");
+    let config = dir.join("worker.toml");
+    std::fs::write(&config, "[resources]\nconcurrency = \"searches\"\n")
+        .expect("Failed to write config");
+    let addr = format!("127.0.0.1:{}", common::free_port());
+    let coordinator = common::spawn(
+        "random",
+        &[
+            "coordinator",
+            "--listen",
+            &addr,
+            "--games",
+            "2",
+            "--movetime-ms",
+            "5",
+            "--ponder",
+        ],
+    );
+    wait_for_listener(&addr);
+
+    let mut args = worker_args(&addr, "1").to_vec();
+    args.extend(["--config", config.to_str().expect("UTF-8 path")]);
+    let worker = common::finish(common::spawn("random:5", &args));
+    assert_eq!(
+        worker
+            .matches("Pondering cannot be combined with searches sharing the cores")
+            .count(),
+        2,
+        "{worker}"
+    );
+
+    let output = common::finish(coordinator);
+    assert!(output.contains("No games played"), "{output}");
+    std::fs::remove_dir_all(&dir).ok();
+}
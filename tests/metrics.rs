@@ -0,0 +1,76 @@
+//! Scraping the Prometheus endpoint of a running match.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+mod common;
+
+/// Fetch `path` from `addr`, or `None` if nothing is listening yet.
+fn get(addr: &str, path: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(addr).ok()?;
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: {addr}\r\n\r\n").expect("Failed to send request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("Failed to read response");
+    Some(response)
+}
+
+#[test]
+fn test_metrics_are_served_while_playing() {
+    let addr = format!("127.0.0.1:{}", common::free_port());
+    let mut child = common::spawn(
+        "random:7",
+        &[
+            "--stockfish-path",
+            common::MOCK,
+            "--reckless-path",
+            common::MOCK,
+            "--movetime-ms",
+            "5",
+            "--max-moves",
+            "40",
+            "--games",
+            "1000",
+            "--metrics-addr",
+            &addr,
+        ],
+    );
+
+    // Scrape until the first game shows up
+    let started = Instant::now();
+    let metrics = loop {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "No game was ever counted"
+        );
+        match get(&addr, "/metrics") {
+            Some(response) if !response.contains("rvs_games_completed_total 0\n") => {
+                break response;
+            }
+            _ => std::thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    let not_found = get(&addr, "/").expect("Endpoint went away");
+    child.kill().ok();
+    child.wait().ok();
+
+    assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"), "{metrics}");
+    assert!(
+        metrics.contains("Content-Type: text/plain; version=0.0.4"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("# TYPE rvs_games_completed_total counter\n"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("rvs_move_latency_seconds_count{engine=\"stockfish\"} "),
+        "{metrics}"
+    );
+    assert!(
+        not_found.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{not_found}"
+    );
+}
@@ -0,0 +1,112 @@
+//! End-to-end match runs against the bundled mock UCI engine.
+
+mod common;
+
+/// Run a match between two mock engines with the given behaviour.
+fn run_match(behaviour: &str, args: &[&str]) -> String {
+    let mut all_args = vec![
+        "--stockfish-path",
+        common::MOCK,
+        "--reckless-path",
+        common::MOCK,
+        "--movetime-ms",
+        "5",
+        "--max-moves",
+        "40",
+    ];
+    all_args.extend_from_slice(args);
+    common::finish(common::spawn(behaviour, &all_args))
+}
+
+#[test]
+fn test_random_match_completes() {
+    let output = run_match("random:3", &["--games", "4", "--workers", "2"]);
+    assert!(output.contains("Total games: 4"), "{output}");
+    assert!(output.contains("Position commands: "), "{output}");
+    // The mock reports searching for 1ms, so the rest comes after its last info
+    assert!(output.contains("Bestmove lag: "), "{output}");
+    assert!(output.contains("Stockfish move time: mean "), "{output}");
+    assert!(output.contains("max overshoot "), "{output}");
+}
+
+#[test]
+fn test_more_workers_than_cores_share_search_slots() {
+    let workers = std::thread::available_parallelism().map_or(1, usize::from) * 2;
+    let output = run_match(
+        "random:3",
+        &[
+            "--games",
+            "8",
+            "--workers",
+            &workers.to_string(),
+            "--concurrency",
+            "searches",
+        ],
+    );
+    assert!(output.contains("Total games: 8"), "{output}");
+    assert!(output.contains("games/hour with"), "{output}");
+    assert!(output.contains("searches concurrency"), "{output}");
+}
+
+#[test]
+fn test_pondering_match_completes() {
+    let output = run_match("random:4", &["--games", "4", "--workers", "2", "--ponder"]);
+    assert!(output.contains("Total games: 4"), "{output}");
+    assert!(!output.contains("Game failed"), "{output}");
+}
+
+#[test]
+fn test_slow_engines_forfeit_on_time() {
+    let output = run_match(
+        "slow:200",
+        &["--games", "2", "--workers", "1", "--time-margin-ms", "20"],
+    );
+    // White loses on time every game, and each engine is White once
+    assert!(
+        output.contains("Stockfish: 1 (50.0%), Reckless: 1 (50.0%)"),
+        "{output}"
+    );
+}
+
+#[test]
+fn test_slow_engines_are_flagged() {
+    let output = run_match("slow:20", &["--games", "1", "--workers", "1"]);
+    assert!(output.contains("Total games: 1"), "{output}");
+    assert!(
+        output.contains("Stockfish overran its 5 ms movetime by more than 10 ms on 20 of 20 moves"),
+        "{output}"
+    );
+}
+
+#[test]
+fn test_illegal_moves_fail_games() {
+    let output = run_match("illegal", &["--games", "2", "--workers", "1"]);
+    assert_eq!(output.matches("Game failed").count(), 2, "{output}");
+    assert!(output.contains("No games played"), "{output}");
+}
+
+#[test]
+fn test_crashing_engines_fail_games() {
+    let output = run_match("crash:2", &["--games", "2", "--workers", "1"]);
+    assert_eq!(output.matches("Game failed").count(), 2, "{output}");
+    assert!(output.contains("closed unexpectedly"), "{output}");
+    assert!(output.contains("No games played"), "{output}");
+}
+
+#[tokio::test]
+async fn test_library_match_streams_records() {
+    use reckless_vs_stockfish::Match;
+    use tokio_stream::StreamExt;
+
+    let records: Vec<_> = Match::new(common::MOCK, common::MOCK)
+        .with_games(4)
+        .with_workers(2)
+        .with_movetime(5)
+        .with_max_moves(40)
+        .play()
+        .collect()
+        .await;
+
+    assert_eq!(records.len(), 4);
+    assert!(records.iter().all(|record| record.plies.len() <= 40));
+}
@@ -0,0 +1,48 @@
+//! Cross-checking an engine's move generator with the `perft` subcommand.
+
+mod common;
+
+#[test]
+fn test_perft_matches_move_generator() {
+    let output = common::finish(common::spawn(
+        "random",
+        &["perft", "--engine-path", common::MOCK, "--depth", "2"],
+    ));
+    assert!(
+        output.contains(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1: 400 nodes at depth 2, ok"
+        ),
+        "{output}"
+    );
+    assert!(output.contains("All 6 positions match"), "{output}");
+}
+
+#[test]
+fn test_perft_reports_divide_mismatches() {
+    let output = common::spawn(
+        "miscount",
+        &[
+            "perft",
+            "--engine-path",
+            common::MOCK,
+            "--depth",
+            "1",
+            "--fen",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        ],
+    )
+    .wait_with_output()
+    .expect("Failed to wait for binary");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1: engine 7 nodes at depth 1, expected 6"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("  e2e4: engine 2, expected 1"), "{stdout}");
+    assert!(
+        stderr.contains("Perft mismatch in 1 of 1 positions"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,61 @@
+//! Replaying stored games with the `replay` subcommand.
+
+mod common;
+
+#[test]
+fn test_replay_verifies_and_reruns_a_stored_game() {
+    let dir = common::scratch_dir("replay");
+    let db = dir.join("results.db");
+    let db = db.to_str().expect("UTF-8 path");
+    let transcript = dir.join("transcript.log");
+
+    common::finish(common::spawn(
+        "random:3",
+        &[
+            "--stockfish-path",
+            common::MOCK,
+            "--reckless-path",
+            common::MOCK,
+            "--movetime-ms",
+            "5",
+            "--max-moves",
+            "40",
+            "--games",
+            "2",
+            "--workers",
+            "1",
+            "--seed",
+            "11",
+            "--db",
+            db,
+        ],
+    ));
+
+    let output = common::finish(common::spawn(
+        "random:3",
+        &[
+            "replay",
+            "--game",
+            "0",
+            "--db",
+            db,
+            "--live",
+            "--transcript",
+            transcript.to_str().expect("UTF-8 path"),
+        ],
+    ));
+    assert!(output.contains("run 1 game 0:"), "{output}");
+    assert!(output.contains("Moves and result verified"), "{output}");
+    assert!(
+        output.contains("Opening and colours match seed 11"),
+        "{output}"
+    );
+    // Fresh mock engines replay their first game exactly
+    assert!(output.contains("Moves are identical"), "{output}");
+
+    let transcript = std::fs::read_to_string(&transcript).expect("No transcript written");
+    assert!(transcript.contains("> go movetime 5"), "{transcript}");
+    assert!(transcript.contains("< bestmove "), "{transcript}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
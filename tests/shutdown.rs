@@ -0,0 +1,66 @@
+//! Stopping a running match early with a signal.
+#![cfg(unix)]
+
+mod common;
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_sigint_finishes_games_in_progress() {
+    let dir = common::scratch_dir("sigint");
+    let pgn = dir.join("games.pgn");
+    let jsonl = dir.join("games.jsonl");
+    let child = common::spawn(
+        "random:6",
+        &[
+            "--stockfish-path",
+            common::MOCK,
+            "--reckless-path",
+            common::MOCK,
+            "--movetime-ms",
+            "5",
+            "--max-moves",
+            "40",
+            "--games",
+            "1000",
+            "--workers",
+            "2",
+            "--pgn",
+            pgn.to_str().expect("UTF-8 path"),
+            "--jsonl",
+            jsonl.to_str().expect("UTF-8 path"),
+        ],
+    );
+
+    // Interrupt once the first game has been written, long before the last
+    let started = Instant::now();
+    while std::fs::read_to_string(&jsonl).map_or(true, |games| games.is_empty()) {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "No game finished"
+        );
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to run kill");
+    assert!(status.success());
+
+    let output = common::finish(child);
+    assert!(output.contains("request again to abort them"), "{output}");
+    let games = std::fs::read_to_string(&jsonl)
+        .expect("Failed to read JSON lines")
+        .lines()
+        .count();
+    assert!(games < 1000, "{output}");
+    assert!(
+        output.contains(&format!("Total games: {games}")),
+        "{output}"
+    );
+    let pgn = std::fs::read_to_string(&pgn).expect("Failed to read PGN");
+    assert_eq!(pgn.matches("[Event ").count(), games, "{pgn}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
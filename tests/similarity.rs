@@ -0,0 +1,44 @@
+//! Move agreement between engines with the `similarity` subcommand.
+
+mod common;
+
+#[test]
+fn test_similarity_prints_agreement_matrix() {
+    let dir = common::scratch_dir("similarity");
+    let positions = dir.join("positions.epd");
+    // Positions with a single legal move, which every engine must agree on
+    std::fs::write(
+        &positions,
+        "7k/8/8/8/8/8/6q1/7K w - - 0 1\n\
+         k7/1Q6/8/8/8/8/8/7K b - - 0 1\n",
+    )
+    .expect("Failed to write positions");
+
+    let output = common::finish(common::spawn(
+        "random:5",
+        &[
+            "similarity",
+            "--epd",
+            positions.to_str().expect("UTF-8 path"),
+            "--engine",
+            &format!("base={}", common::MOCK),
+            "--engine",
+            &format!("net1={}", common::MOCK),
+            "--option",
+            "net1:Hash=32",
+            "--nodes",
+            "1000",
+            "--workers",
+            "2",
+        ],
+    ));
+    assert!(
+        output.contains("Move agreement in 2 positions (%):"),
+        "{output}"
+    );
+    assert!(output.contains("         base   net1\n"), "{output}");
+    assert!(output.contains("base    100.0  100.0\n"), "{output}");
+    assert!(output.contains("net1    100.0  100.0"), "{output}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
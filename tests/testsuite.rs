@@ -0,0 +1,52 @@
+//! Scoring EPD test suites with the `testsuite` subcommand.
+
+mod common;
+
+#[test]
+fn test_testsuite_reports_solved_positions_and_sts_points() {
+    let dir = common::scratch_dir("testsuite");
+    let suite = dir.join("suite.epd");
+    // The mock plays its script by move number: e4, then Kf8, then e3
+    std::fs::write(
+        &suite,
+        "4k3/8/8/8/8/8/4P3/4K3 w - - bm e4; id \"solved\";\n\
+         4k3/8/8/8/8/8/4P3/4K3 b - - bm Kd7; id \"failed\";\n\
+         4k3/8/8/8/8/8/4P3/4K3 w - - bm e4; id \"STS(v1.0) Pawns.001\"; \
+         c0 \"e4=10, e3=5\"; hmvc 0; fmvn 2;\n",
+    )
+    .expect("Failed to write suite");
+
+    let output = common::finish(common::spawn(
+        "script:e2e4,e8f8,e2e3",
+        &[
+            "testsuite",
+            "--epd",
+            suite.to_str().expect("UTF-8 path"),
+            "--engine-path",
+            common::MOCK,
+            "--depth",
+            "4",
+            "--workers",
+            "2",
+        ],
+    ));
+    assert!(
+        output.contains("solved: solved with e4 in 1 ms at depth 1"),
+        "{output}"
+    );
+    assert!(output.contains("failed: failed with Kf8"), "{output}");
+    assert!(
+        output.contains("STS(v1.0) Pawns.001: failed with e3, 5/10 points"),
+        "{output}"
+    );
+    assert!(
+        output.contains("Solved 1/3 (33.3%), mean time to solution 1 ms"),
+        "{output}"
+    );
+    assert!(
+        output.contains("Pawns: 5/10 points (50.0%), 0/1 solved"),
+        "{output}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,73 @@
+//! SPSA tuning of engine options with the `tune` subcommand.
+
+mod common;
+
+#[test]
+fn test_tune_checkpoints_and_resumes() {
+    let dir = common::scratch_dir("tune");
+    let checkpoint = dir.join("spsa.json");
+    let history = dir.join("history.csv");
+    let spawn = |params: &[&str], iterations: &str| {
+        let mut args = vec!["tune"];
+        for param in params {
+            args.extend(["--param", param]);
+        }
+        args.extend([
+            "--iterations",
+            iterations,
+            "--checkpoint",
+            checkpoint.to_str().expect("UTF-8 path"),
+            "--history",
+            history.to_str().expect("UTF-8 path"),
+            "--stockfish-path",
+            common::MOCK,
+            "--reckless-path",
+            common::MOCK,
+            "--movetime-ms",
+            "5",
+            "--max-moves",
+            "20",
+            "--workers",
+            "2",
+            "--seed",
+            "11",
+        ]);
+        common::spawn("random:3", &args)
+    };
+    let tune = |iterations: &str| common::finish(spawn(&["Hash", "Threads:2"], iterations));
+
+    let output = tune("2");
+    assert!(
+        output.contains("Tuned values after 2 iterations:"),
+        "{output}"
+    );
+    assert!(output.contains("\nHash = "), "{output}");
+    assert!(output.contains("\nThreads = "), "{output}");
+    let saved = std::fs::read_to_string(&checkpoint).expect("No checkpoint");
+    assert!(saved.contains("\"iteration\": 2"), "{saved}");
+
+    // Resuming with more iterations extends the run
+    let resumed = tune("3");
+    assert!(
+        resumed.contains("Tuned values after 3 iterations:"),
+        "{resumed}"
+    );
+    let history_text = std::fs::read_to_string(&history).expect("No history");
+    let lines: Vec<&str> = history_text.lines().collect();
+    assert_eq!(lines.len(), 4, "{history_text}");
+    assert_eq!(lines[0], "iteration,Hash,Threads");
+    assert!(lines[3].starts_with("3,"), "{history_text}");
+
+    // A checkpoint of other options is not silently resumed
+    let mismatched = spawn(&["Hash"], "3")
+        .wait_with_output()
+        .expect("Failed to wait for binary");
+    let stderr = String::from_utf8_lossy(&mismatched.stderr);
+    assert!(!mismatched.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("The checkpoint tunes Hash, Threads but the run was asked to tune Hash"),
+        "{stderr}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}